@@ -0,0 +1,59 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Prints the code generated for a thrift IDL file
+//
+// cargo run --example generate -- -I fboss/ fboss/agent/if/ctrl.thrift
+
+extern crate torc_thrift_codegen;
+
+use std::env;
+use std::process;
+
+fn main() {
+    let mut builder = torc_thrift_codegen::Builder::new();
+    let mut args = env::args().skip(1);
+    let mut has_file = false;
+    while let Some(arg) = args.next() {
+        if arg == "-I" {
+            match args.next() {
+                Some(dir) => builder.include_dir(dir),
+                None => break,
+            };
+        } else {
+            builder.file(arg);
+            has_file = true;
+        }
+    }
+    if !has_file {
+        eprintln!("usage: generate [-I include_dir]... file.thrift...");
+        process::exit(2);
+    }
+
+    match builder.generate_string() {
+        Ok(code) => print!("{}", code),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
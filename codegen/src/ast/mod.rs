@@ -0,0 +1,155 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Syntax tree of thrift IDL documents
+
+use std::path::PathBuf;
+
+/// One parsed `.thrift` file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    /// Name of the generated module, the file name without `.thrift`
+    pub name: String,
+    pub path: PathBuf,
+    /// Paths of included documents, as written in the IDL
+    pub includes: Vec<String>,
+    pub definitions: Vec<Definition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Definition {
+    Const(Const),
+    Typedef(Typedef),
+    Enum(Enum),
+    Struct(Struct),
+    Service(Service),
+}
+
+impl Definition {
+    pub fn name(&self) -> &str {
+        match *self {
+            Definition::Const(ref definition) => &definition.name,
+            Definition::Typedef(ref definition) => &definition.name,
+            Definition::Enum(ref definition) => &definition.name,
+            Definition::Struct(ref definition) => &definition.name,
+            Definition::Service(ref definition) => &definition.name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Const {
+    pub name: String,
+    pub field_type: FieldType,
+    pub value: ConstValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Typedef {
+    pub name: String,
+    pub field_type: FieldType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub values: Vec<(String, i32)>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StructKind {
+    Struct,
+    Union,
+    Exception,
+}
+
+/// Struct, union or exception
+#[derive(Clone, Debug, PartialEq)]
+pub struct Struct {
+    pub kind: StructKind,
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Requiredness {
+    Required,
+    Optional,
+    /// Neither `required` nor `optional` given
+    Default,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub id: i16,
+    pub name: String,
+    pub field_type: FieldType,
+    pub requiredness: Requiredness,
+    pub default: Option<ConstValue>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Service {
+    pub name: String,
+    pub extends: Option<String>,
+    pub functions: Vec<Function>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub oneway: bool,
+    /// `None` for `void`
+    pub return_type: Option<FieldType>,
+    pub args: Vec<Field>,
+    pub throws: Vec<Field>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldType {
+    Bool,
+    Byte,
+    I16,
+    I32,
+    I64,
+    Double,
+    String,
+    Binary,
+    List(Box<FieldType>),
+    Set(Box<FieldType>),
+    Map(Box<FieldType>, Box<FieldType>),
+    /// Reference to a typedef, enum or struct, qualified with the module for included types
+    Named(String),
+    /// Streaming and sink types of fbthrift, which the generated clients can't call
+    Unsupported(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Double(f64),
+    String(String),
+    /// Reference to an enum value or another constant
+    Identifier(String),
+    List(Vec<ConstValue>),
+    Map(Vec<(ConstValue, ConstValue)>),
+}
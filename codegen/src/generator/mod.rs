@@ -0,0 +1,1156 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Generates rust types, Encode/Decode implementations and clients from parsed documents
+
+use std::collections::BTreeSet;
+
+use ast::{Document, Definition, Const, Typedef, Enum, Struct, StructKind, Field, Requiredness, Service, Function,
+          FieldType, ConstValue};
+use Error;
+
+const KEYWORDS: &[&str] = &["abstract", "as", "async", "await", "become", "box", "break", "const",
+                                            "continue", "crate", "do", "dyn", "else", "enum", "extern", "false",
+                                            "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+                                            "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+                                            "return", "self", "Self", "static", "struct", "super", "trait", "true",
+                                            "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where",
+                                            "while", "yield"];
+
+/// Escapes identifiers that are rust keywords with a trailing underscore
+pub fn escape(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// `getIPRoute` becomes `get_ip_route`
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous != '_' && (previous.is_lowercase() || previous.is_numeric() || next_is_lower) {
+                result.push('_');
+            }
+        }
+        result.extend(c.to_lowercase());
+    }
+    escape(&result)
+}
+
+/// `PORT_UP` becomes `PortUp`, `portUp` becomes `PortUp`
+pub fn camel_case(name: &str) -> String {
+    let mut result = String::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let all_upper = !part.chars().any(|c| c.is_lowercase());
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            result.extend(first.to_uppercase());
+            if all_upper {
+                result.extend(chars.flat_map(|c| c.to_lowercase()));
+            } else {
+                result.extend(chars);
+            }
+        }
+    }
+    if result.is_empty() || result.starts_with(|c: char| c.is_numeric()) {
+        result.insert(0, '_');
+    }
+    escape(&result)
+}
+
+/// Module the definitions of `document` are generated into
+pub fn module_name(document: &Document) -> String {
+    snake_case(&document.name)
+}
+
+/// Generates one module per document, the modules refer to each other through `super::`
+pub fn generate(documents: &[Document], runtime: &str) -> Result<String, Error> {
+    let generator = Generator {
+        documents: documents,
+        runtime: runtime,
+    };
+
+    let mut out = String::new();
+    out.push_str("// Generated by torc_thrift_codegen, do not edit\n");
+    for document in documents {
+        try!(generator.document(&mut out, document));
+    }
+    Ok(out)
+}
+
+/// A definition together with the document it is defined in
+#[derive(Clone, Copy)]
+struct Resolved<'a> {
+    document: &'a Document,
+    definition: &'a Definition,
+}
+
+struct Generator<'a> {
+    documents: &'a [Document],
+    runtime: &'a str,
+}
+
+impl<'a> Generator<'a> {
+    fn find_document<'b>(&'b self, name: &str) -> Option<&'b Document> {
+        self.documents.iter().find(|document| document.name == name)
+    }
+
+    /// Looks up `Name` in `document` or `module.Name` in an included document
+    fn resolve<'b>(&'b self, document: &'b Document, name: &str) -> Result<Resolved<'b>, Error> {
+        let (document, local_name) = match name.find('.') {
+            Some(dot) => {
+                match self.find_document(&name[..dot]) {
+                    Some(included) => (included, &name[dot + 1..]),
+                    None => (document, name),
+                }
+            }
+            None => (document, name),
+        };
+
+        match document.definitions.iter().find(|definition| definition.name() == local_name) {
+            Some(definition) => {
+                Ok(Resolved {
+                    document: document,
+                    definition: definition,
+                })
+            }
+            None => Err(Error::Resolve(format!("unknown type {} in {}", name, document.path.display()))),
+        }
+    }
+
+    /// Follows typedefs down to a base type, container, enum or struct
+    fn resolve_type<'b>(&'b self, document: &'b Document, field_type: &'b FieldType)
+                    -> Result<(&'b Document, &'b FieldType, Option<Resolved<'b>>), Error> {
+        match *field_type {
+            FieldType::Named(ref name) => {
+                let resolved = try!(self.resolve(document, name));
+                match *resolved.definition {
+                    Definition::Typedef(ref typedef) => self.resolve_type(resolved.document, &typedef.field_type),
+                    Definition::Enum(_) | Definition::Struct(_) => Ok((document, field_type, Some(resolved))),
+                    _ => Err(Error::Resolve(format!("{} is not a type", name))),
+                }
+            }
+            _ => Ok((document, field_type, None)),
+        }
+    }
+
+    fn path(&self, from: &Document, resolved: &Resolved, name: &str) -> String {
+        if resolved.document.name == from.name {
+            name.to_string()
+        } else {
+            format!("super::{}::{}", module_name(resolved.document), name)
+        }
+    }
+
+    fn is_supported<'b>(&'b self, document: &'b Document, field_type: &'b FieldType) -> bool {
+        match *field_type {
+            FieldType::Unsupported(_) => false,
+            FieldType::List(ref element) |
+            FieldType::Set(ref element) => self.is_supported(document, element),
+            FieldType::Map(ref key, ref value) => {
+                self.is_supported(document, key) && self.is_supported(document, value)
+            }
+            FieldType::Named(ref name) => {
+                match self.resolve(document, name) {
+                    Ok(Resolved { document, definition: Definition::Typedef(typedef) }) => {
+                        self.is_supported(document, &typedef.field_type)
+                    }
+                    // unknown names are reported when their rust type is generated
+                    _ => true,
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether the rust type can derive `Eq`, `Hash` and `Ord`, that is it contains no doubles
+    fn is_orderable<'b>(&'b self, document: &'b Document, field_type: &'b FieldType,
+                    visiting: &mut BTreeSet<(String, String)>)
+                    -> Result<bool, Error> {
+        let (document, field_type, resolved) = try!(self.resolve_type(document, field_type));
+        match *field_type {
+            FieldType::Double => Ok(false),
+            FieldType::List(ref element) |
+            FieldType::Set(ref element) => self.is_orderable(document, element, visiting),
+            FieldType::Map(ref key, ref value) => {
+                Ok(try!(self.is_orderable(document, key, visiting)) &&
+                   try!(self.is_orderable(document, value, visiting)))
+            }
+            FieldType::Named(_) => {
+                let resolved = resolved.unwrap();
+                match *resolved.definition {
+                    Definition::Struct(ref definition) => {
+                        self.is_struct_orderable(resolved.document, definition, visiting)
+                    }
+                    _ => Ok(true),
+                }
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn is_struct_orderable<'b>(&'b self, document: &'b Document, definition: &'b Struct,
+                           visiting: &mut BTreeSet<(String, String)>)
+                           -> Result<bool, Error> {
+        if !visiting.insert((document.name.clone(), definition.name.clone())) {
+            return Ok(true);
+        }
+        for field in &definition.fields {
+            if self.is_supported(document, &field.field_type) &&
+               !try!(self.is_orderable(document, &field.field_type, visiting)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether a field of `field_type` in the struct `name` of `document` refers back to the struct, which
+    /// takes a `Box` to keep the struct sized
+    fn is_recursive<'b>(&'b self, document: &'b Document, name: &str, field_type: &'b FieldType)
+                        -> Result<bool, Error> {
+        self.contains_struct(document, field_type, (&document.name, name), &mut BTreeSet::new())
+    }
+
+    /// Whether `field_type` holds the struct `target` inline, containers already allocate their items
+    fn contains_struct<'b>(&'b self, document: &'b Document, field_type: &'b FieldType, target: (&str, &str),
+                           visiting: &mut BTreeSet<(String, String)>)
+                           -> Result<bool, Error> {
+        if !self.is_supported(document, field_type) {
+            return Ok(false);
+        }
+        let (document, definition) = match try!(self.resolve_type(document, field_type)) {
+            (_, _, Some(Resolved { document, definition: Definition::Struct(definition) })) => (document, definition),
+            _ => return Ok(false),
+        };
+        if document.name == target.0 && definition.name == target.1 {
+            return Ok(true);
+        }
+        if !visiting.insert((document.name.clone(), definition.name.clone())) {
+            return Ok(false);
+        }
+        for field in &definition.fields {
+            if try!(self.contains_struct(document, &field.field_type, target, visiting)) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn rust_type<'b>(&'b self, document: &'b Document, field_type: &'b FieldType) -> Result<String, Error> {
+        self.rust_type_in(document, document, field_type)
+    }
+
+    /// Rust type for code emitted into the module of `module`, names are looked up from `document`
+    fn rust_type_in<'b>(&'b self, module: &'b Document, document: &'b Document, field_type: &'b FieldType)
+                        -> Result<String, Error> {
+        Ok(match *field_type {
+            FieldType::Bool => "bool".to_string(),
+            FieldType::Byte => "i8".to_string(),
+            FieldType::I16 => "i16".to_string(),
+            FieldType::I32 => "i32".to_string(),
+            FieldType::I64 => "i64".to_string(),
+            FieldType::Double => "f64".to_string(),
+            FieldType::String => "String".to_string(),
+            FieldType::Binary => "Vec<u8>".to_string(),
+            FieldType::List(ref element) => format!("Vec<{}>", try!(self.rust_type_in(module, document, element))),
+            FieldType::Set(ref element) => {
+                try!(self.check_orderable(document, element));
+                format!("::std::collections::BTreeSet<{}>", try!(self.rust_type_in(module, document, element)))
+            }
+            FieldType::Map(ref key, ref value) => {
+                try!(self.check_orderable(document, key));
+                format!("::std::collections::BTreeMap<{}, {}>",
+                        try!(self.rust_type_in(module, document, key)),
+                        try!(self.rust_type_in(module, document, value)))
+            }
+            FieldType::Named(ref name) => {
+                let resolved = try!(self.resolve(document, name));
+                let local_name = resolved.definition.name().to_string();
+                self.path(module, &resolved, &local_name)
+            }
+            FieldType::Unsupported(ref name) => return Err(Error::Resolve(format!("{} is not supported", name))),
+        })
+    }
+
+    fn check_orderable<'b>(&'b self, document: &'b Document, field_type: &'b FieldType) -> Result<(), Error> {
+        if try!(self.is_orderable(document, field_type, &mut BTreeSet::new())) {
+            Ok(())
+        } else {
+            Err(Error::Resolve(format!("sets and map keys of {:?} are not supported", field_type)))
+        }
+    }
+
+    fn wire_type<'b>(&'b self, document: &'b Document, field_type: &'b FieldType) -> Result<String, Error> {
+        let (_, field_type, resolved) = try!(self.resolve_type(document, field_type));
+        let name = match *field_type {
+            FieldType::Bool => "Bool",
+            FieldType::Byte => "Byte",
+            FieldType::I16 => "I16",
+            FieldType::I32 => "I32",
+            FieldType::I64 => "I64",
+            FieldType::Double => "Double",
+            FieldType::String | FieldType::Binary => "String",
+            FieldType::List(_) => "List",
+            FieldType::Set(_) => "Set",
+            FieldType::Map(_, _) => "Map",
+            FieldType::Named(_) => {
+                match *resolved.unwrap().definition {
+                    Definition::Enum(_) => "I32",
+                    _ => "Struct",
+                }
+            }
+            FieldType::Unsupported(ref name) => return Err(Error::Resolve(format!("{} is not supported", name))),
+        };
+        Ok(format!("runtime::protocol::Type::{}", name))
+    }
+
+    /// Statements writing the value `value` refers to, `value` is either `&place` or a reference
+    fn encode_value<'b>(&'b self, out: &mut String, document: &'b Document, field_type: &'b FieldType, value: &str,
+                        depth: usize, indent: &str)
+                        -> Result<(), Error> {
+        let (document, field_type, resolved) = try!(self.resolve_type(document, field_type));
+        let place = match value.strip_prefix('&') {
+            Some(place) => place.to_string(),
+            None => format!("*{}", value),
+        };
+        let receiver = match value.strip_prefix('&') {
+            Some(place) => place,
+            None => value,
+        };
+
+        let write = |method: &str, argument: &str| {
+            format!("{}protocol.write_{}(transport, {})?;\n", indent, method, argument)
+        };
+        match *field_type {
+            FieldType::Bool => out.push_str(&write("bool", &place)),
+            FieldType::Byte => out.push_str(&write("byte", &place)),
+            FieldType::I16 => out.push_str(&write("i16", &place)),
+            FieldType::I32 => out.push_str(&write("i32", &place)),
+            FieldType::I64 => out.push_str(&write("i64", &place)),
+            FieldType::Double => out.push_str(&write("double", &place)),
+            FieldType::String => out.push_str(&write("str", value)),
+            FieldType::Binary => out.push_str(&write("binary", value)),
+            FieldType::List(ref element) |
+            FieldType::Set(ref element) => {
+                let kind = if let FieldType::List(_) = *field_type { "list" } else { "set" };
+                let item = format!("item_{}", depth);
+                out.push_str(&format!("{}protocol.write_{}_begin(transport, {}, {}.len())?;\n{}for {} in {} {{\n",
+                                      indent,
+                                      kind,
+                                      try!(self.wire_type(document, element)),
+                                      receiver,
+                                      indent,
+                                      item,
+                                      value));
+                try!(self.encode_value(out, document, element, &item, depth + 1, &format!("{}    ", indent)));
+                out.push_str(&format!("{}}}\n{}protocol.write_{}_end(transport)?;\n", indent, indent, kind));
+            }
+            FieldType::Map(ref key_type, ref value_type) => {
+                let key = format!("key_{}", depth);
+                let item = format!("value_{}", depth);
+                out.push_str(&format!("{}protocol.write_map_begin(transport, {}, {}, {}.len())?;\n{}for ({}, {}) in {} \
+                                       {{\n",
+                                      indent,
+                                      try!(self.wire_type(document, key_type)),
+                                      try!(self.wire_type(document, value_type)),
+                                      receiver,
+                                      indent,
+                                      key,
+                                      item,
+                                      value));
+                let inner = format!("{}    ", indent);
+                try!(self.encode_value(out, document, key_type, &key, depth + 1, &inner));
+                try!(self.encode_value(out, document, value_type, &item, depth + 1, &inner));
+                out.push_str(&format!("{}}}\n{}protocol.write_map_end(transport)?;\n", indent, indent));
+            }
+            FieldType::Named(_) => {
+                match *resolved.unwrap().definition {
+                    Definition::Enum(_) => out.push_str(&write("i32", &format!("{} as i32", place))),
+                    _ => {
+                        out.push_str(&format!("{}runtime::protocol::Encode::encode({}, protocol, transport)?;\n",
+                                              indent,
+                                              value))
+                    }
+                }
+            }
+            FieldType::Unsupported(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Expression reading a value of `field_type`
+    fn decode_value<'b>(&'b self, document: &'b Document, field_type: &'b FieldType, depth: usize, indent: &str)
+                    -> Result<String, Error> {
+        let rust_type = try!(self.rust_type(document, field_type));
+        let (document, field_type, resolved) = try!(self.resolve_type(document, field_type));
+        let inner = format!("{}    ", indent);
+
+        Ok(match *field_type {
+            FieldType::Bool => "protocol.read_bool(transport)?".to_string(),
+            FieldType::Byte => "protocol.read_byte(transport)?".to_string(),
+            FieldType::I16 => "protocol.read_i16(transport)?".to_string(),
+            FieldType::I32 => "protocol.read_i32(transport)?".to_string(),
+            FieldType::I64 => "protocol.read_i64(transport)?".to_string(),
+            FieldType::Double => "protocol.read_double(transport)?".to_string(),
+            FieldType::String => "protocol.read_string(transport)?".to_string(),
+            FieldType::Binary => "protocol.read_binary(transport)?".to_string(),
+            FieldType::List(ref element) |
+            FieldType::Set(ref element) => {
+                let (kind, insert) = if let FieldType::List(_) = *field_type {
+                    ("list", "push")
+                } else {
+                    ("set", "insert")
+                };
+                format!("{{\n{inner}let (type_{d}, size_{d}) = protocol.read_{kind}_begin(transport)?;\n{inner}\
+                         runtime::protocol::check_element_type({element_type}, type_{d}, size_{d})?;\n{inner}let mut \
+                         items_{d} = {ty}::new();\n{inner}for _ in 0..size_{d} {{\n{inner}    \
+                         items_{d}.{insert}({element});\n{inner}}}\n{inner}protocol.read_{kind}_end(transport)?;\
+                         \n{inner}items_{d}\n{indent}}}",
+                        inner = inner,
+                        indent = indent,
+                        d = depth,
+                        kind = kind,
+                        insert = insert,
+                        ty = rust_type.split('<').next().unwrap(),
+                        element_type = try!(self.wire_type(document, element)),
+                        element = try!(self.decode_value(document, element, depth + 1, &format!("{}    ", inner))))
+            }
+            FieldType::Map(ref key, ref value) => {
+                format!("{{\n{inner}let (key_type_{d}, value_type_{d}, size_{d}) = \
+                         protocol.read_map_begin(transport)?;\n{inner}\
+                         runtime::protocol::check_element_type({key_type}, key_type_{d}, size_{d})?;\n{inner}\
+                         runtime::protocol::check_element_type({value_type}, value_type_{d}, size_{d})?;\n{inner}\
+                         let mut items_{d} = ::std::collections::BTreeMap::new();\n{inner}\
+                         for _ in 0..size_{d} {{\n{inner}    let key_{d} = {key};\n{inner}    \
+                         let value_{d} = {value};\n{inner}    items_{d}.insert(key_{d}, value_{d});\n{inner}}}\n{inner}\
+                         protocol.read_map_end(transport)?;\
+                         \n{inner}items_{d}\n{indent}}}",
+                        inner = inner,
+                        indent = indent,
+                        d = depth,
+                        key_type = try!(self.wire_type(document, key)),
+                        value_type = try!(self.wire_type(document, value)),
+                        key = try!(self.decode_value(document, key, depth + 1, &format!("{}    ", inner))),
+                        value = try!(self.decode_value(document, value, depth + 1, &format!("{}    ", inner))))
+            }
+            FieldType::Named(_) => {
+                match *resolved.unwrap().definition {
+                    Definition::Enum(_) => "runtime::protocol::helpers::read_enum(protocol, transport)?".to_string(),
+                    _ => {
+                        format!("{{\n{inner}let mut value_{d} = {ty}::default();\n{inner}\
+                                 runtime::protocol::Decode::decode(&mut value_{d}, protocol, transport)?;\
+                                 \n{inner}value_{d}\n{indent}}}",
+                                inner = inner,
+                                indent = indent,
+                                d = depth,
+                                ty = rust_type)
+                    }
+                }
+            }
+            FieldType::Unsupported(_) => unreachable!(),
+        })
+    }
+
+    /// Rust expression for a constant, `owned` is false where a `&'static str` is expected
+    fn const_value<'b>(&'b self, document: &'b Document, field_type: &'b FieldType, value: &ConstValue, owned: bool)
+                   -> Result<String, Error> {
+        let rust_type = try!(self.rust_type(document, field_type));
+        let (resolved_document, resolved_type, resolved) = try!(self.resolve_type(document, field_type));
+
+        if let ConstValue::Identifier(ref name) = *value {
+            match (resolved_type, name.as_str()) {
+                (&FieldType::Bool, "true") |
+                (&FieldType::Bool, "false") => return Ok(name.clone()),
+                _ => {}
+            }
+            if let Some(Resolved { definition: Definition::Enum(definition), .. }) = resolved {
+                let variant = name.rsplit('.').next().unwrap();
+                if definition.values.iter().any(|(value_name, _)| value_name == variant) {
+                    return Ok(format!("{}::{}", rust_type, camel_case(variant)));
+                }
+            }
+            let constant = try!(self.resolve(document, name));
+            return match *constant.definition {
+                Definition::Const(ref definition) => {
+                    if self.is_const_item(constant.document, &definition.field_type) {
+                        let path = self.path(document, &constant, &escape(&definition.name));
+                        if owned && self.is_string(constant.document, &definition.field_type) {
+                            Ok(format!("{}.to_string()", path))
+                        } else {
+                            Ok(path)
+                        }
+                    } else {
+                        Ok(format!("{}()", self.path(document, &constant, &snake_case(&definition.name))))
+                    }
+                }
+                _ => Err(Error::Resolve(format!("{} is not a constant", name))),
+            };
+        }
+
+        let unexpected = || Error::Resolve(format!("constant {:?} doesn't match {:?}", value, field_type));
+        Ok(match (resolved_type, value) {
+            (&FieldType::Bool, &ConstValue::Int(value)) => format!("{}", value != 0),
+            (&FieldType::Byte, &ConstValue::Int(value)) |
+            (&FieldType::I16, &ConstValue::Int(value)) |
+            (&FieldType::I32, &ConstValue::Int(value)) |
+            (&FieldType::I64, &ConstValue::Int(value)) => format!("{}", value),
+            (&FieldType::Double, &ConstValue::Int(value)) => format!("{}.0", value),
+            (&FieldType::Double, &ConstValue::Double(value)) => format!("{:?}", value),
+            (&FieldType::String, ConstValue::String(value)) => {
+                if owned {
+                    format!("{:?}.to_string()", value)
+                } else {
+                    format!("{:?}", value)
+                }
+            }
+            (&FieldType::Binary, ConstValue::String(value)) => format!("{:?}.as_bytes().to_vec()", value),
+            (&FieldType::List(ref element), &ConstValue::List(ref values)) |
+            (&FieldType::Set(ref element), &ConstValue::List(ref values)) => {
+                let mut items = Vec::new();
+                for value in values {
+                    items.push(try!(self.const_value(resolved_document, element, value, true)));
+                }
+                if let FieldType::List(_) = *resolved_type {
+                    format!("vec![{}]", items.join(", "))
+                } else {
+                    format!("vec![{}].into_iter().collect()", items.join(", "))
+                }
+            }
+            (FieldType::Map(key_type, value_type), ConstValue::Map(entries)) => {
+                let mut items = Vec::new();
+                for (key, value) in entries {
+                    items.push(format!("({}, {})",
+                                       try!(self.const_value(resolved_document, key_type, key, true)),
+                                       try!(self.const_value(resolved_document, value_type, value, true))));
+                }
+                format!("vec![{}].into_iter().collect()", items.join(", "))
+            }
+            (&FieldType::Named(_), &ConstValue::Int(value)) => {
+                match *resolved.unwrap().definition {
+                    Definition::Enum(ref definition) => {
+                        match definition.values.iter().find(|&&(_, number)| number as i64 == value) {
+                            Some((name, _)) => format!("{}::{}", rust_type, camel_case(name)),
+                            None => return Err(unexpected()),
+                        }
+                    }
+                    _ => return Err(unexpected()),
+                }
+            }
+            (FieldType::Named(_), ConstValue::Map(entries)) => {
+                let resolved = resolved.unwrap();
+                let definition = match *resolved.definition {
+                    Definition::Struct(ref definition) => definition,
+                    _ => return Err(unexpected()),
+                };
+                let mut assignments = String::new();
+                for (key, value) in entries {
+                    let field = match *key {
+                        ConstValue::String(ref name) => definition.fields.iter().find(|field| &field.name == name),
+                        _ => None,
+                    };
+                    let field = match field {
+                        Some(field) => field,
+                        None => return Err(unexpected()),
+                    };
+                    let mut value = try!(self.const_value(resolved.document, &field.field_type, value, true));
+                    if try!(self.is_recursive(resolved.document, &definition.name, &field.field_type)) {
+                        value = format!("Box::new({})", value);
+                    }
+                    let value = if field.requiredness == Requiredness::Required {
+                        value
+                    } else {
+                        format!("Some({})", value)
+                    };
+                    assignments.push_str(&format!(" value.{} = {};", snake_case(&field.name), value));
+                }
+                format!("{{ let mut value = {}::default();{} value }}", rust_type, assignments)
+            }
+            _ => return Err(unexpected()),
+        })
+    }
+
+    fn is_string<'b>(&'b self, document: &'b Document, field_type: &'b FieldType) -> bool {
+        matches!(self.resolve_type(document, field_type), Ok((_, &FieldType::String, _)))
+    }
+
+    /// Base types, strings and enums become `pub const` items, everything else a function
+    fn is_const_item<'b>(&'b self, document: &'b Document, field_type: &'b FieldType) -> bool {
+        match self.resolve_type(document, field_type) {
+            Ok((_, &FieldType::Named(_), resolved)) => {
+                matches!(resolved, Some(Resolved { definition: &Definition::Enum(_), .. }))
+            }
+            Ok((_, &FieldType::Binary, _)) |
+            Ok((_, &FieldType::List(_), _)) |
+            Ok((_, &FieldType::Set(_), _)) |
+            Ok((_, &FieldType::Map(_, _), _)) => false,
+            _ => true,
+        }
+    }
+
+    fn document<'b>(&'b self, out: &mut String, document: &'b Document) -> Result<(), Error> {
+        out.push_str(&format!("\n#[allow(dead_code, unused_imports, non_camel_case_types, non_upper_case_globals, \
+                               unused_mut, clippy::all)]\npub mod {} {{\n    use {} as runtime;\n",
+                              module_name(document),
+                              self.runtime));
+
+        for definition in &document.definitions {
+            match *definition {
+                Definition::Const(ref definition) => try!(self.constant(out, document, definition)),
+                Definition::Typedef(ref definition) => try!(self.typedef(out, document, definition)),
+                Definition::Enum(ref definition) => try!(self.enumeration(out, definition)),
+                Definition::Struct(ref definition) => try!(self.structure(out, document, definition)),
+                Definition::Service(ref definition) => try!(self.service(out, document, definition)),
+            }
+        }
+
+        out.push_str("}\n");
+        Ok(())
+    }
+
+    fn constant<'b>(&'b self, out: &mut String, document: &'b Document, definition: &'b Const) -> Result<(), Error> {
+        out.push('\n');
+        if self.is_const_item(document, &definition.field_type) {
+            let rust_type = if self.is_string(document, &definition.field_type) {
+                "&'static str".to_string()
+            } else {
+                try!(self.rust_type(document, &definition.field_type))
+            };
+            out.push_str(&format!("    pub const {}: {} = {};\n",
+                                  escape(&definition.name),
+                                  rust_type,
+                                  try!(self.const_value(document, &definition.field_type, &definition.value,
+                                                        false))));
+        } else {
+            out.push_str(&format!("    pub fn {}() -> {} {{\n        {}\n    }}\n",
+                                  snake_case(&definition.name),
+                                  try!(self.rust_type(document, &definition.field_type)),
+                                  try!(self.const_value(document, &definition.field_type, &definition.value,
+                                                        true))));
+        }
+        Ok(())
+    }
+
+    fn typedef<'b>(&'b self, out: &mut String, document: &'b Document, definition: &'b Typedef) -> Result<(), Error> {
+        if !self.is_supported(document, &definition.field_type) {
+            out.push_str(&format!("\n    // typedef {} is not supported\n", definition.name));
+            return Ok(());
+        }
+        out.push_str(&format!("\n    pub type {} = {};\n",
+                              definition.name,
+                              try!(self.rust_type(document, &definition.field_type))));
+        Ok(())
+    }
+
+    fn enumeration<'b>(&'b self, out: &mut String, definition: &'b Enum) -> Result<(), Error> {
+        let name = &definition.name;
+        let mut variants: Vec<(String, i32)> = Vec::new();
+        let mut aliases = Vec::new();
+        for &(ref value_name, number) in &definition.values {
+            match variants.iter().find(|&&(_, existing)| existing == number) {
+                Some((existing, _)) => aliases.push((escape(value_name), existing.clone())),
+                None => variants.push((camel_case(value_name), number)),
+            }
+        }
+        if variants.is_empty() {
+            return Err(Error::Resolve(format!("enum {} has no values", name)));
+        }
+
+        out.push_str(&format!("\n    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]\n    pub \
+                               enum {} {{\n",
+                              name));
+        for &(ref variant, number) in &variants {
+            out.push_str(&format!("        {} = {},\n", variant, number));
+        }
+        out.push_str("    }\n");
+
+        if !aliases.is_empty() {
+            out.push_str(&format!("\n    impl {} {{\n", name));
+            for (alias, variant) in &aliases {
+                out.push_str(&format!("        pub const {}: {} = {}::{};\n", alias, name, name, variant));
+            }
+            out.push_str("    }\n");
+        }
+
+        out.push_str(&format!("
+    impl Default for {name} {{
+        fn default() -> Self {{
+            {name}::{first}
+        }}
+    }}
+",
+                              name = name,
+                              first = variants[0].0));
+
+        out.push_str(&format!("
+    impl runtime::protocol::FromNum for {} {{
+        fn from_num(num: i32) -> Option<Self> {{
+            match num {{
+",
+                              name));
+        for &(ref variant, number) in &variants {
+            out.push_str(&format!("                {} => Some({}::{}),\n", number, name, variant));
+        }
+        out.push_str("                _ => None,\n            }\n        }\n    }\n");
+
+        out.push_str(&format!("
+    impl runtime::protocol::ThriftTyped for {name} {{
+        fn typ() -> runtime::protocol::Type {{
+            runtime::protocol::Type::I32
+        }}
+    }}
+
+    impl runtime::protocol::Encode for {name} {{
+        fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> runtime::Result<()>
+        where P: runtime::Protocol, T: runtime::Transport {{
+            protocol.write_i32(transport, *self as i32)
+        }}
+    }}
+
+    impl runtime::protocol::Decode for {name} {{
+        fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> runtime::Result<()>
+        where P: runtime::Protocol, T: runtime::Transport {{
+            *self = runtime::protocol::helpers::read_enum(protocol, transport)?;
+            Ok(())
+        }}
+    }}
+",
+                              name = name));
+        Ok(())
+    }
+
+    fn field_rust_type<'b>(&'b self, document: &'b Document, field: &'b Field, boxed: bool) -> Result<String, Error> {
+        let mut rust_type = try!(self.rust_type(document, &field.field_type));
+        if boxed {
+            rust_type = format!("Box<{}>", rust_type);
+        }
+        Ok(if field.requiredness == Requiredness::Required {
+            rust_type
+        } else {
+            format!("Option<{}>", rust_type)
+        })
+    }
+
+    fn structure<'b>(&'b self, out: &mut String, document: &'b Document, definition: &'b Struct) -> Result<(), Error> {
+        let fields: Vec<&Field> = definition.fields
+            .iter()
+            .filter(|field| self.is_supported(document, &field.field_type))
+            .collect();
+        let orderable = try!(self.is_struct_orderable(document, definition, &mut BTreeSet::new()));
+        self.struct_with_fields(out, document, &definition.name, definition.kind, &fields, orderable)
+    }
+
+    fn struct_with_fields<'b>(&'b self, out: &mut String, document: &'b Document, name: &str, kind: StructKind,
+                          fields: &[&'b Field], orderable: bool)
+                          -> Result<(), Error> {
+        let mut boxed = Vec::new();
+        for field in fields {
+            let recursive = try!(self.is_recursive(document, name, &field.field_type));
+            if recursive && field.requiredness == Requiredness::Required {
+                return Err(Error::Resolve(format!("required field {} of {} refers back to {}",
+                                                  field.name,
+                                                  name,
+                                                  name)));
+            }
+            boxed.push(recursive);
+        }
+
+        out.push_str(&format!("\n    #[derive(Clone, Debug, PartialEq{})]\n    pub struct {} {{\n",
+                              if orderable { ", Eq, Hash, PartialOrd, Ord" } else { "" },
+                              name));
+        for (field, &boxed) in fields.iter().zip(&boxed) {
+            out.push_str(&format!("        pub {}: {},\n",
+                                  snake_case(&field.name),
+                                  try!(self.field_rust_type(document, field, boxed))));
+        }
+        out.push_str("    }\n");
+
+        out.push_str(&format!("\n    impl Default for {} {{\n        fn default() -> Self {{\n            {} {{\n",
+                              name,
+                              name));
+        for (field, &boxed) in fields.iter().zip(&boxed) {
+            let value = match (field.requiredness, &field.default) {
+                (Requiredness::Required, Some(value)) => {
+                    try!(self.const_value(document, &field.field_type, value, true))
+                }
+                (Requiredness::Required, &None) => "Default::default()".to_string(),
+                (_, Some(value)) if boxed => {
+                    format!("Some(Box::new({}))", try!(self.const_value(document, &field.field_type, value, true)))
+                }
+                (_, Some(value)) => {
+                    format!("Some({})", try!(self.const_value(document, &field.field_type, value, true)))
+                }
+                (_, &None) => "None".to_string(),
+            };
+            out.push_str(&format!("                {}: {},\n", snake_case(&field.name), value));
+        }
+        out.push_str("            }\n        }\n    }\n");
+
+        out.push_str(&format!("
+    impl runtime::protocol::ThriftTyped for {name} {{
+        fn typ() -> runtime::protocol::Type {{
+            runtime::protocol::Type::Struct
+        }}
+    }}
+
+    impl runtime::protocol::Encode for {name} {{
+        fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> runtime::Result<()>
+        where P: runtime::Protocol, T: runtime::Transport {{
+",
+                              name = name));
+        if kind == StructKind::Union && !fields.is_empty() {
+            // checked before anything is written, so the stream stays usable
+            out.push_str(&format!("            if {} > 1 {{
+                return Err(runtime::Error::InvalidArgument({:?}.to_string()));
+            }}
+",
+                                  try!(self.union_set_fields(fields)),
+                                  format!("more than one field of union {} is set", name)));
+        }
+        out.push_str(&format!("            protocol.write_struct_begin(transport, {:?})?;\n", name));
+        for (field, &boxed) in fields.iter().zip(&boxed) {
+            let field_name = snake_case(&field.name);
+            let (value, indent) = if field.requiredness == Requiredness::Required {
+                (format!("&self.{}", field_name), "            ")
+            } else {
+                out.push_str(&format!("            if let Some(ref value) = self.{} {{\n", field_name));
+                (if boxed { "&**value" } else { "value" }.to_string(), "                ")
+            };
+            out.push_str(&format!("{}protocol.write_field_begin(transport, {:?}, {}, {})?;\n",
+                                  indent,
+                                  field.name,
+                                  try!(self.wire_type(document, &field.field_type)),
+                                  field.id));
+            try!(self.encode_value(out, document, &field.field_type, &value, 0, indent));
+            out.push_str(&format!("{}protocol.write_field_end(transport)?;\n", indent));
+            if field.requiredness != Requiredness::Required {
+                out.push_str("            }\n");
+            }
+        }
+        out.push_str("            protocol.write_field_stop(transport)?;
+            protocol.write_struct_end(transport)
+        }
+    }
+");
+
+        out.push_str(&format!("
+    impl runtime::protocol::Decode for {} {{
+        fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> runtime::Result<()>
+        where P: runtime::Protocol, T: runtime::Transport {{
+",
+                              name));
+        let required: Vec<&&Field> = fields.iter()
+            .filter(|field| field.requiredness == Requiredness::Required)
+            .collect();
+        for field in &required {
+            out.push_str(&format!("            let mut has_{} = false;\n", snake_case(&field.name)));
+        }
+        out.push_str("            protocol.read_struct_begin(transport)?;
+            loop {
+                let (_, field_type, field_id) = protocol.read_field_begin(transport)?;
+                if field_type == runtime::protocol::Type::Stop {
+                    break;
+                }
+                match (field_id, field_type) {
+");
+        for (field, &boxed) in fields.iter().zip(&boxed) {
+            let field_name = snake_case(&field.name);
+            let mut value = try!(self.decode_value(document, &field.field_type, 0, "                        "));
+            if boxed {
+                value = format!("Box::new({})", value);
+            }
+            out.push_str(&format!("                    ({}, {}) => {{\n",
+                                  field.id,
+                                  try!(self.wire_type(document, &field.field_type))));
+            if field.requiredness == Requiredness::Required {
+                out.push_str(&format!("                        self.{} = {};\n", field_name, value));
+                out.push_str(&format!("                        has_{} = true;\n", field_name));
+            } else {
+                out.push_str(&format!("                        self.{} = Some({});\n", field_name, value));
+            }
+            out.push_str("                    }\n");
+        }
+        out.push_str("                    _ => protocol.skip(transport, field_type)?,
+                }
+                protocol.read_field_end(transport)?;
+            }
+            protocol.read_struct_end(transport)?;
+");
+        let mut violations: Vec<String> = required.iter()
+            .map(|field| format!("!has_{}", snake_case(&field.name)))
+            .collect();
+        if kind == StructKind::Union && !fields.is_empty() {
+            // at most one field of a union may be set
+            violations.push(format!("{} > 1", try!(self.union_set_fields(fields))));
+        }
+        if !violations.is_empty() {
+            out.push_str(&format!("            if {} {{
+                return Err(runtime::Error::from(runtime::protocol::Error::ProtocolViolation));
+            }}
+",
+                                  violations.join(" || ")));
+        }
+        out.push_str("            Ok(())
+        }
+    }
+");
+
+        if kind == StructKind::Exception {
+            out.push_str(&format!("
+    impl ::std::fmt::Display for {name} {{
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {{
+            ::std::fmt::Debug::fmt(self, f)
+        }}
+    }}
+
+    impl ::std::error::Error for {name} {{}}
+",
+                                  name = name));
+        }
+        Ok(())
+    }
+
+    /// Expression counting the set fields of a union
+    fn union_set_fields(&self, fields: &[&Field]) -> Result<String, Error> {
+        if let Some(field) = fields.iter().find(|field| field.requiredness == Requiredness::Required) {
+            return Err(Error::Resolve(format!("union field {} can't be required", field.name)));
+        }
+        let set: Vec<String> = fields.iter()
+            .map(|field| format!("self.{}.is_some() as u8", snake_case(&field.name)))
+            .collect();
+        Ok(set.join(" + "))
+    }
+
+    /// Functions of `service` and the services it extends, with the document defining each
+    fn service_functions<'b>(&'b self, document: &'b Document, service: &'b Service,
+                         functions: &mut Vec<(&'b Document, &'b Service, &'b Function)>)
+                         -> Result<(), Error> {
+        if let Some(ref extends) = service.extends {
+            let resolved = try!(self.resolve(document, extends));
+            match *resolved.definition {
+                Definition::Service(ref base) => try!(self.service_functions(resolved.document, base, functions)),
+                _ => return Err(Error::Resolve(format!("{} is not a service", extends))),
+            }
+        }
+        for function in &service.functions {
+            functions.retain(|&(_, _, existing)| existing.name != function.name);
+            functions.push((document, service, function));
+        }
+        Ok(())
+    }
+
+    fn is_function_supported<'b>(&'b self, document: &'b Document, function: &'b Function) -> bool {
+        function.return_type.as_ref().is_none_or(|return_type| self.is_supported(document, return_type)) &&
+        function.args.iter().chain(function.throws.iter()).all(|field| self.is_supported(document, &field.field_type))
+    }
+
+    fn service<'b>(&'b self, out: &mut String, document: &'b Document, service: &'b Service) -> Result<(), Error> {
+        // Args and Result structs of the functions declared here, inherited ones live with their service
+        for function in &service.functions {
+            if !self.is_function_supported(document, function) {
+                continue;
+            }
+            let args: Vec<&Field> = function.args.iter().collect();
+            let mut orderable = true;
+            for field in &args {
+                orderable &= try!(self.is_orderable(document, &field.field_type, &mut BTreeSet::new()));
+            }
+            try!(self.struct_with_fields(out,
+                                         document,
+                                         &format!("{}{}Args", service.name, camel_case(&function.name)),
+                                         StructKind::Struct,
+                                         &args,
+                                         orderable));
+            if function.oneway {
+                continue;
+            }
+            let mut results: Vec<&Field> = function.throws.iter().collect();
+            let success;
+            if let Some(ref return_type) = function.return_type {
+                success = Field {
+                    id: 0,
+                    name: "success".to_string(),
+                    field_type: return_type.clone(),
+                    requiredness: Requiredness::Optional,
+                    default: None,
+                };
+                results.insert(0, &success);
+            }
+            let results: Vec<Field> = results.into_iter()
+                .map(|field| Field { requiredness: Requiredness::Optional, default: None, ..field.clone() })
+                .collect();
+            let mut orderable = true;
+            for field in &results {
+                orderable &= try!(self.is_orderable(document, &field.field_type, &mut BTreeSet::new()));
+            }
+            try!(self.struct_with_fields(out,
+                                         document,
+                                         &format!("{}{}Result", service.name, camel_case(&function.name)),
+                                         StructKind::Struct,
+                                         &results.iter().collect::<Vec<_>>(),
+                                         orderable));
+        }
+
+        let mut functions = Vec::new();
+        try!(self.service_functions(document, service, &mut functions));
+
+        let client = format!("{}Client", service.name);
+        out.push_str(&format!("
+    pub struct {client}<T, P = runtime::protocol::binary_protocol::BinaryProtocol> {{
+        transport: T,
+        protocol: P,
+        sequence_id: i32,
+    }}
+
+    impl<T: runtime::Transport> {client}<T> {{
+        pub fn new(transport: T) -> {client}<T> {{
+            {client}::with_protocol(transport, runtime::protocol::binary_protocol::BinaryProtocol::new())
+        }}
+    }}
+
+    impl<T, P> {client}<T, P>
+    where T: runtime::Transport, P: runtime::Protocol {{
+        pub fn with_protocol(transport: T, protocol: P) -> {client}<T, P> {{
+            {client} {{
+                transport: transport,
+                protocol: protocol,
+                sequence_id: 0,
+            }}
+        }}
+
+        pub fn transport(&self) -> &T {{
+            &self.transport
+        }}
+
+        pub fn transport_mut(&mut self) -> &mut T {{
+            &mut self.transport
+        }}
+
+        pub fn into_transport(self) -> T {{
+            self.transport
+        }}
+",
+                              client = client));
+
+        for &(function_document, function_service, function) in &functions {
+            try!(self.client_method(out, document, function_document, function_service, function));
+        }
+        out.push_str("    }\n");
+        Ok(())
+    }
+
+    fn client_method<'b>(&'b self, out: &mut String, document: &'b Document, function_document: &'b Document,
+                     service: &'b Service, function: &'b Function)
+                     -> Result<(), Error> {
+        if !self.is_function_supported(function_document, function) {
+            out.push_str(&format!("\n        // {} uses stream or sink types, which are not supported\n",
+                                  function.name));
+            return Ok(());
+        }
+
+        let prefix = if function_document.name == document.name {
+            String::new()
+        } else {
+            format!("super::{}::", module_name(function_document))
+        };
+        let args_type = format!("{}{}{}Args", prefix, service.name, camel_case(&function.name));
+        let result_type = format!("{}{}{}Result", prefix, service.name, camel_case(&function.name));
+
+        let mut parameters = String::new();
+        let mut assignments = String::new();
+        for arg in &function.args {
+            let arg_name = snake_case(&arg.name);
+            parameters.push_str(&format!(", {}: {}",
+                                         arg_name,
+                                         try!(self.rust_type_in(document, function_document, &arg.field_type))));
+            if arg.requiredness == Requiredness::Required {
+                assignments.push_str(&format!("                {}: {},\n", arg_name, arg_name));
+            } else {
+                assignments.push_str(&format!("                {}: Some({}),\n", arg_name, arg_name));
+            }
+        }
+        let return_type = match function.return_type {
+            Some(ref return_type) => try!(self.rust_type_in(document, function_document, return_type)),
+            None => "()".to_string(),
+        };
+
+        out.push_str(&format!("
+        pub fn {name}(&mut self{parameters}) -> runtime::Result<{return_type}> {{
+            self.sequence_id = self.sequence_id.wrapping_add(1);
+            let args = {args_type} {{
+{assignments}            }};
+            runtime::protocol::helpers::send_message(&mut self.protocol, &mut self.transport, {method:?},
+                                                     runtime::protocol::MessageType::{message_type},
+                                                     self.sequence_id, &args)?;
+",
+                              name = snake_case(&function.name),
+                              parameters = parameters,
+                              return_type = return_type,
+                              args_type = args_type,
+                              assignments = assignments,
+                              method = function.name,
+                              message_type = if function.oneway { "Oneway" } else { "Call" }));
+        if function.oneway {
+            out.push_str("            Ok(())\n        }\n");
+            return Ok(());
+        }
+
+        out.push_str(&format!("            let mut result = {}::default();
+            runtime::protocol::helpers::receive_reply(&mut self.protocol, &mut self.transport, {:?},
+                                                      self.sequence_id, &mut result)?;
+",
+                              result_type,
+                              function.name));
+        for exception in &function.throws {
+            match try!(self.resolve_type(function_document, &exception.field_type)) {
+                (_, _, Some(Resolved { definition: Definition::Struct(definition), .. }))
+                    if definition.kind == StructKind::Exception => {}
+                _ => {
+                    return Err(Error::Resolve(format!("{} throws {:?}, which is not an exception",
+                                                      function.name,
+                                                      exception.field_type)))
+                }
+            }
+            out.push_str(&format!("            if let Some(exception) = result.{}.take() {{
+                return Err(runtime::Error::Exception(Box::new(exception)));
+            }}
+",
+                                  snake_case(&exception.name)));
+        }
+        if function.return_type.is_some() {
+            out.push_str("            match result.success {
+                Some(success) => Ok(success),
+                None => Err(runtime::Error::from(runtime::protocol::Error::ProtocolViolation)),
+            }
+        }
+");
+        } else {
+            out.push_str("            Ok(())\n        }\n");
+        }
+        Ok(())
+    }
+}
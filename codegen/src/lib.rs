@@ -0,0 +1,221 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Generates rust types and clients for `torc_fboss_client` from thrift IDL files.
+//!
+//! Meant to be called from a build script:
+//!
+//! ```no_run
+//! extern crate torc_thrift_codegen;
+//!
+//! fn main() {
+//!     torc_thrift_codegen::Builder::new()
+//!         .file("thrift/ctrl.thrift")
+//!         .include_dir("thrift")
+//!         .generate()
+//!         .unwrap();
+//! }
+//! ```
+//!
+//! and included with `include!(concat!(env!("OUT_DIR"), "/ctrl.rs"));`, which declares one module
+//! per IDL file, `ctrl` and the modules of everything it includes.
+
+use std::{env, error, fmt, fs, io};
+use std::path::{Path, PathBuf};
+
+pub mod ast;
+pub mod parser;
+pub mod generator;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading an IDL file or writing the generated code failed
+    Io(PathBuf, io::Error),
+
+    /// An IDL file is not valid thrift
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+
+    /// A referenced include, type or constant doesn't exist or can't be generated
+    Resolve(String),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::Parse { ref path, line, ref message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::Resolve(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+pub struct Builder {
+    files: Vec<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+    runtime_crate: String,
+    output: Option<PathBuf>,
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder {
+            files: Vec::new(),
+            include_dirs: Vec::new(),
+            runtime_crate: "::torc_fboss_client".to_string(),
+            output: None,
+        }
+    }
+
+    /// Adds an IDL file to generate code for, the files it includes are generated as well
+    pub fn file<F: AsRef<Path>>(&mut self, file: F) -> &mut Builder {
+        self.files.push(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds a directory to search includes in, after the directory of the including file
+    pub fn include_dir<D: AsRef<Path>>(&mut self, dir: D) -> &mut Builder {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Path of the runtime crate in the generated code, `::torc_fboss_client` by default
+    pub fn runtime_crate(&mut self, path: &str) -> &mut Builder {
+        self.runtime_crate = path.to_string();
+        self
+    }
+
+    /// File to write, `$OUT_DIR/<name of the first file>.rs` by default
+    pub fn output<F: AsRef<Path>>(&mut self, output: F) -> &mut Builder {
+        self.output = Some(output.as_ref().to_path_buf());
+        self
+    }
+
+    /// Parses the files with their includes and returns the generated code
+    pub fn generate_string(&self) -> Result<String, Error> {
+        let documents = try!(self.load());
+        generator::generate(&documents, &self.runtime_crate)
+    }
+
+    /// Writes the generated code and returns the path it was written to
+    pub fn generate(&self) -> Result<PathBuf, Error> {
+        let documents = try!(self.load());
+        let code = try!(generator::generate(&documents, &self.runtime_crate));
+
+        let output = match self.output {
+            Some(ref output) => output.clone(),
+            None => {
+                let out_dir = match env::var_os("OUT_DIR") {
+                    Some(out_dir) => PathBuf::from(out_dir),
+                    None => return Err(Error::Resolve("OUT_DIR is not set, use output()".to_string())),
+                };
+                let name = match self.files.first() {
+                    Some(file) => file.file_stem().map(|stem| stem.to_string_lossy().into_owned()),
+                    None => None,
+                };
+                out_dir.join(format!("{}.rs", name.unwrap_or_else(|| "thrift".to_string())))
+            }
+        };
+
+        try!(fs::write(&output, code).map_err(|err| Error::Io(output.clone(), err)));
+        for document in &documents {
+            println!("cargo:rerun-if-changed={}", document.path.display());
+        }
+        Ok(output)
+    }
+
+    /// Parses the files and, recursively, their includes
+    fn load(&self) -> Result<Vec<ast::Document>, Error> {
+        if self.files.is_empty() {
+            return Err(Error::Resolve("no IDL file given".to_string()));
+        }
+
+        let mut documents = Vec::new();
+        for file in &self.files {
+            try!(self.load_file(file, &mut Vec::new(), &mut documents));
+        }
+        Ok(documents)
+    }
+
+    /// `loading` holds the files whose includes are being loaded, to report include cycles
+    fn load_file(&self, path: &Path, loading: &mut Vec<PathBuf>, documents: &mut Vec<ast::Document>)
+                 -> Result<(), Error> {
+        let path = try!(path.canonicalize().map_err(|err| Error::Io(path.to_path_buf(), err)));
+        if let Some(start) = loading.iter().position(|file| *file == path) {
+            let cycle: Vec<String> = loading[start..]
+                .iter()
+                .chain(Some(&path))
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(Error::Resolve(format!("include cycle {}", cycle.join(" -> "))));
+        }
+        if documents.iter().any(|document| document.path == path) {
+            return Ok(());
+        }
+
+        let content = try!(fs::read_to_string(&path).map_err(|err| Error::Io(path.clone(), err)));
+        let document = try!(parser::parse(&path, &content));
+        if let Some(existing) = documents.iter().find(|existing| existing.name == document.name) {
+            return Err(Error::Resolve(format!("{} and {} would generate the same module",
+                                              existing.path.display(),
+                                              path.display())));
+        }
+
+        loading.push(path.clone());
+        for include in &document.includes {
+            let include_path = try!(self.find_include(&path, include));
+            try!(self.load_file(&include_path, loading, documents));
+        }
+        loading.pop();
+        documents.push(document);
+        Ok(())
+    }
+
+    fn find_include(&self, from: &Path, include: &str) -> Result<PathBuf, Error> {
+        let parent = from.parent().map(|parent| parent.to_path_buf()).unwrap_or_default();
+        for dir in Some(&parent).into_iter().chain(self.include_dirs.iter()) {
+            let candidate = dir.join(include);
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+        Err(Error::Resolve(format!("include {} of {} not found", include, from.display())))
+    }
+}
@@ -0,0 +1,659 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Parser of thrift IDL, including the annotations fbthrift adds to it
+
+use std::path::Path;
+use std::str::FromStr;
+
+use ast::{Document, Definition, Const, Typedef, Enum, Struct, StructKind, Field, Requiredness, Service, Function,
+          FieldType, ConstValue};
+use Error;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Int(i64),
+    Double(f64),
+    Literal(String),
+    Symbol(char),
+    Eof,
+}
+
+struct Lexer<'a> {
+    input: &'a [u8],
+    position: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Lexer<'a> {
+        Lexer {
+            input: input.as_bytes(),
+            position: 0,
+            line: 1,
+        }
+    }
+
+    fn peek_byte(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.position + offset).cloned()
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek_byte(0) {
+                Some(b'\n') => {
+                    self.line += 1;
+                    self.position += 1;
+                }
+                Some(byte) if (byte as char).is_whitespace() => self.position += 1,
+                Some(b'#') => self.skip_line(),
+                Some(b'/') if self.peek_byte(1) == Some(b'/') => self.skip_line(),
+                Some(b'/') if self.peek_byte(1) == Some(b'*') => {
+                    self.position += 2;
+                    loop {
+                        match self.peek_byte(0) {
+                            Some(b'*') if self.peek_byte(1) == Some(b'/') => {
+                                self.position += 2;
+                                break;
+                            }
+                            Some(byte) => {
+                                if byte == b'\n' {
+                                    self.line += 1;
+                                }
+                                self.position += 1;
+                            }
+                            None => return Err("unterminated comment".to_string()),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(byte) = self.peek_byte(0) {
+            if byte == b'\n' {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, String> {
+        try!(self.skip_whitespace_and_comments());
+
+        let start = self.position;
+        let byte = match self.peek_byte(0) {
+            Some(byte) => byte,
+            None => return Ok(Token::Eof),
+        };
+
+        if byte == b'"' || byte == b'\'' {
+            self.position += 1;
+            let mut value = Vec::new();
+            loop {
+                match self.peek_byte(0) {
+                    Some(b'\\') => {
+                        let escaped = match self.peek_byte(1) {
+                            Some(b'n') => b'\n',
+                            Some(b't') => b'\t',
+                            Some(b'r') => b'\r',
+                            Some(escaped) => escaped,
+                            None => return Err("unterminated string literal".to_string()),
+                        };
+                        value.push(escaped);
+                        self.position += 2;
+                    }
+                    Some(quote) if quote == byte => {
+                        self.position += 1;
+                        break;
+                    }
+                    Some(other) => {
+                        if other == b'\n' {
+                            self.line += 1;
+                        }
+                        value.push(other);
+                        self.position += 1;
+                    }
+                    None => return Err("unterminated string literal".to_string()),
+                }
+            }
+            return String::from_utf8(value)
+                .map(Token::Literal)
+                .map_err(|_| "string literal is not valid UTF-8".to_string());
+        }
+
+        let is_number_start = (byte as char).is_ascii_digit() ||
+                              ((byte == b'-' || byte == b'+') &&
+                               self.peek_byte(1).is_some_and(|next| (next as char).is_ascii_digit()));
+        if is_number_start {
+            self.position += 1;
+            while let Some(next) = self.peek_byte(0) {
+                let next = next as char;
+                if next.is_ascii_alphanumeric() || next == '.' ||
+                   ((next == '-' || next == '+') && (self.input[self.position - 1] | 0x20) == b'e') {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+            let text = String::from_utf8_lossy(&self.input[start..self.position]).into_owned();
+            return parse_number(&text);
+        }
+
+        if (byte as char).is_ascii_alphabetic() || byte == b'_' {
+            while let Some(next) = self.peek_byte(0) {
+                let next = next as char;
+                if next.is_ascii_alphanumeric() || next == '_' || next == '.' {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+            let text = String::from_utf8_lossy(&self.input[start..self.position]).into_owned();
+            return Ok(Token::Identifier(text));
+        }
+
+        self.position += 1;
+        Ok(Token::Symbol(byte as char))
+    }
+}
+
+fn parse_number(text: &str) -> Result<Token, String> {
+    let (negative, digits) = if let Some(digits) = text.strip_prefix('-') {
+        (true, digits)
+    } else {
+        (false, text.trim_start_matches('+'))
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else {
+        i64::from_str(digits).ok()
+    };
+
+    match value {
+        Some(value) => Ok(Token::Int(if negative { -value } else { value })),
+        None => {
+            f64::from_str(text)
+                .map(Token::Double)
+                .map_err(|_| format!("invalid number {}", text))
+        }
+    }
+}
+
+/// Parses the content of the IDL file at `path`, includes are not followed
+pub fn parse(path: &Path, input: &str) -> Result<Document, Error> {
+    let mut parser = Parser {
+        lexer: Lexer::new(input),
+        token: Token::Eof,
+        line: 1,
+    };
+
+    match parser.document(path) {
+        Ok(document) => Ok(document),
+        Err(message) => {
+            Err(Error::Parse {
+                path: path.to_path_buf(),
+                line: parser.line,
+                message: message,
+            })
+        }
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    token: Token,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn advance(&mut self) -> Result<Token, String> {
+        let token = try!(self.lexer.next_token());
+        self.line = self.lexer.line;
+        Ok(::std::mem::replace(&mut self.token, token))
+    }
+
+    fn is_symbol(&self, symbol: char) -> bool {
+        self.token == Token::Symbol(symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.token {
+            Token::Identifier(ref identifier) => identifier == keyword,
+            _ => false,
+        }
+    }
+
+    fn accept_symbol(&mut self, symbol: char) -> Result<bool, String> {
+        if self.is_symbol(symbol) {
+            try!(self.advance());
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> Result<bool, String> {
+        if self.is_keyword(keyword) {
+            try!(self.advance());
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), String> {
+        if try!(self.accept_symbol(symbol)) {
+            return Ok(());
+        }
+        Err(format!("expected '{}', found {}", symbol, describe(&self.token)))
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, String> {
+        match try!(self.advance()) {
+            Token::Identifier(identifier) => Ok(identifier),
+            token => Err(format!("expected identifier, found {}", describe(&token))),
+        }
+    }
+
+    fn expect_literal(&mut self) -> Result<String, String> {
+        match try!(self.advance()) {
+            Token::Literal(literal) => Ok(literal),
+            token => Err(format!("expected string literal, found {}", describe(&token))),
+        }
+    }
+
+    fn expect_int(&mut self) -> Result<i64, String> {
+        match try!(self.advance()) {
+            Token::Int(value) => Ok(value),
+            token => Err(format!("expected integer, found {}", describe(&token))),
+        }
+    }
+
+    fn list_separator(&mut self) -> Result<(), String> {
+        if !try!(self.accept_symbol(',')) {
+            try!(self.accept_symbol(';'));
+        }
+        Ok(())
+    }
+
+    /// Skips `(key = "value", ...)` annotations
+    fn annotations(&mut self) -> Result<(), String> {
+        if self.is_symbol('(') {
+            try!(self.skip_balanced('(', ')'));
+        }
+        Ok(())
+    }
+
+    /// Skips fbthrift's structured annotations like `@cpp.Type{name = "x"}`
+    fn structured_annotations(&mut self) -> Result<(), String> {
+        while try!(self.accept_symbol('@')) {
+            try!(self.expect_identifier());
+            if self.is_symbol('{') {
+                try!(self.skip_balanced('{', '}'));
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_balanced(&mut self, open: char, close: char) -> Result<(), String> {
+        let mut depth = 0;
+        loop {
+            match try!(self.advance()) {
+                Token::Symbol(symbol) if symbol == open => depth += 1,
+                Token::Symbol(symbol) if symbol == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Token::Eof => return Err(format!("unbalanced '{}'", open)),
+                _ => {}
+            }
+        }
+    }
+
+    fn document(&mut self, path: &Path) -> Result<Document, String> {
+        try!(self.advance());
+
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => stem.to_string(),
+            None => return Err(format!("invalid file name {}", path.display())),
+        };
+        let mut document = Document {
+            name: name,
+            path: path.to_path_buf(),
+            includes: Vec::new(),
+            definitions: Vec::new(),
+        };
+
+        loop {
+            try!(self.structured_annotations());
+            if self.token == Token::Eof {
+                return Ok(document);
+            }
+
+            let keyword = try!(self.expect_identifier());
+            match &keyword[..] {
+                "include" => document.includes.push(try!(self.expect_literal())),
+                "cpp_include" | "hs_include" | "package" => {
+                    try!(self.expect_literal());
+                }
+                "namespace" => {
+                    if !try!(self.accept_symbol('*')) {
+                        try!(self.expect_identifier());
+                    }
+                    match try!(self.advance()) {
+                        Token::Identifier(_) | Token::Literal(_) => {}
+                        token => return Err(format!("expected namespace, found {}", describe(&token))),
+                    }
+                }
+                "const" => document.definitions.push(Definition::Const(try!(self.constant()))),
+                "typedef" => document.definitions.push(Definition::Typedef(try!(self.typedef()))),
+                "enum" => document.definitions.push(Definition::Enum(try!(self.enumeration()))),
+                "struct" => document.definitions.push(Definition::Struct(try!(self.structure(StructKind::Struct)))),
+                "union" => document.definitions.push(Definition::Struct(try!(self.structure(StructKind::Union)))),
+                "exception" => {
+                    document.definitions.push(Definition::Struct(try!(self.structure(StructKind::Exception))))
+                }
+                "safe" | "transient" | "stateful" | "server" | "client" | "permanent" => {
+                    // fbthrift exception qualifiers
+                }
+                "service" => document.definitions.push(Definition::Service(try!(self.service()))),
+                "interaction" => {
+                    try!(self.expect_identifier());
+                    try!(self.skip_balanced('{', '}'));
+                }
+                _ => return Err(format!("unexpected {}", keyword)),
+            }
+            try!(self.list_separator());
+        }
+    }
+
+    fn constant(&mut self) -> Result<Const, String> {
+        let field_type = try!(self.field_type());
+        let name = try!(self.expect_identifier());
+        try!(self.expect_symbol('='));
+        let value = try!(self.const_value());
+        Ok(Const {
+            name: name,
+            field_type: field_type,
+            value: value,
+        })
+    }
+
+    fn typedef(&mut self) -> Result<Typedef, String> {
+        let field_type = try!(self.field_type());
+        let name = try!(self.expect_identifier());
+        try!(self.annotations());
+        Ok(Typedef {
+            name: name,
+            field_type: field_type,
+        })
+    }
+
+    fn enumeration(&mut self) -> Result<Enum, String> {
+        let name = try!(self.expect_identifier());
+        try!(self.expect_symbol('{'));
+
+        let mut values = Vec::new();
+        let mut next_value = 0;
+        loop {
+            try!(self.structured_annotations());
+            if try!(self.accept_symbol('}')) {
+                break;
+            }
+            let value_name = try!(self.expect_identifier());
+            if try!(self.accept_symbol('=')) {
+                next_value = try!(self.expect_int());
+            }
+            if next_value < i32::MIN as i64 || next_value > i32::MAX as i64 {
+                return Err(format!("value of {} is out of range", value_name));
+            }
+            values.push((value_name, next_value as i32));
+            next_value += 1;
+            try!(self.annotations());
+            try!(self.list_separator());
+        }
+        try!(self.annotations());
+
+        Ok(Enum {
+            name: name,
+            values: values,
+        })
+    }
+
+    fn structure(&mut self, kind: StructKind) -> Result<Struct, String> {
+        let name = try!(self.expect_identifier());
+        try!(self.accept_keyword("xsd_all"));
+        try!(self.expect_symbol('{'));
+        let fields = try!(self.fields('}'));
+        try!(self.annotations());
+
+        Ok(Struct {
+            kind: kind,
+            name: name,
+            fields: fields,
+        })
+    }
+
+    /// Fields up to the `end` symbol, implicit ids count down from -1 like the reference compiler
+    fn fields(&mut self, end: char) -> Result<Vec<Field>, String> {
+        let mut fields = Vec::new();
+        let mut next_implicit_id = -1;
+        loop {
+            try!(self.structured_annotations());
+            if try!(self.accept_symbol(end)) {
+                return Ok(fields);
+            }
+
+            let id = match self.token {
+                Token::Int(id) => {
+                    try!(self.advance());
+                    try!(self.expect_symbol(':'));
+                    if id < i16::MIN as i64 || id > i16::MAX as i64 {
+                        return Err(format!("field id {} is out of range", id));
+                    }
+                    id as i16
+                }
+                _ => {
+                    next_implicit_id -= 1;
+                    next_implicit_id + 1
+                }
+            };
+
+            let requiredness = if try!(self.accept_keyword("required")) {
+                Requiredness::Required
+            } else if try!(self.accept_keyword("optional")) {
+                Requiredness::Optional
+            } else {
+                Requiredness::Default
+            };
+
+            let field_type = try!(self.field_type());
+            try!(self.accept_symbol('&'));
+            let name = try!(self.expect_identifier());
+            let default = if try!(self.accept_symbol('=')) {
+                Some(try!(self.const_value()))
+            } else {
+                None
+            };
+            try!(self.accept_keyword("xsd_optional"));
+            try!(self.accept_keyword("xsd_nillable"));
+            try!(self.annotations());
+            try!(self.list_separator());
+
+            fields.push(Field {
+                id: id,
+                name: name,
+                field_type: field_type,
+                requiredness: requiredness,
+                default: default,
+            });
+        }
+    }
+
+    fn service(&mut self) -> Result<Service, String> {
+        let name = try!(self.expect_identifier());
+        let extends = if try!(self.accept_keyword("extends")) {
+            Some(try!(self.expect_identifier()))
+        } else {
+            None
+        };
+        try!(self.expect_symbol('{'));
+
+        let mut functions = Vec::new();
+        loop {
+            try!(self.structured_annotations());
+            if try!(self.accept_symbol('}')) {
+                break;
+            }
+            functions.push(try!(self.function()));
+        }
+        try!(self.annotations());
+
+        Ok(Service {
+            name: name,
+            extends: extends,
+            functions: functions,
+        })
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        let mut oneway = false;
+        loop {
+            if try!(self.accept_keyword("oneway")) {
+                oneway = true;
+            } else if !try!(self.accept_keyword("readonly")) && !try!(self.accept_keyword("idempotent")) {
+                break;
+            }
+        }
+
+        let return_type = if try!(self.accept_keyword("void")) {
+            None
+        } else {
+            Some(try!(self.field_type()))
+        };
+        let name = try!(self.expect_identifier());
+        try!(self.expect_symbol('('));
+        let args = try!(self.fields(')'));
+        let throws = if try!(self.accept_keyword("throws")) {
+            try!(self.expect_symbol('('));
+            try!(self.fields(')'))
+        } else {
+            Vec::new()
+        };
+        try!(self.annotations());
+        try!(self.list_separator());
+
+        Ok(Function {
+            name: name,
+            oneway: oneway,
+            return_type: return_type,
+            args: args,
+            throws: throws,
+        })
+    }
+
+    fn field_type(&mut self) -> Result<FieldType, String> {
+        let name = try!(self.expect_identifier());
+        let field_type = match &name[..] {
+            "bool" => FieldType::Bool,
+            "byte" | "i8" => FieldType::Byte,
+            "i16" => FieldType::I16,
+            "i32" => FieldType::I32,
+            "i64" => FieldType::I64,
+            "double" | "float" => FieldType::Double,
+            "string" | "slist" => FieldType::String,
+            "binary" => FieldType::Binary,
+            "list" => {
+                try!(self.expect_symbol('<'));
+                let element = try!(self.field_type());
+                try!(self.expect_symbol('>'));
+                FieldType::List(Box::new(element))
+            }
+            "set" => {
+                try!(self.expect_symbol('<'));
+                let element = try!(self.field_type());
+                try!(self.expect_symbol('>'));
+                FieldType::Set(Box::new(element))
+            }
+            "map" => {
+                try!(self.expect_symbol('<'));
+                let key = try!(self.field_type());
+                try!(self.expect_symbol(','));
+                let value = try!(self.field_type());
+                try!(self.expect_symbol('>'));
+                FieldType::Map(Box::new(key), Box::new(value))
+            }
+            "stream" | "sink" => {
+                try!(self.skip_balanced('<', '>'));
+                FieldType::Unsupported(name)
+            }
+            _ => FieldType::Named(name),
+        };
+        try!(self.annotations());
+        Ok(field_type)
+    }
+
+    fn const_value(&mut self) -> Result<ConstValue, String> {
+        match try!(self.advance()) {
+            Token::Int(value) => Ok(ConstValue::Int(value)),
+            Token::Double(value) => Ok(ConstValue::Double(value)),
+            Token::Literal(value) => Ok(ConstValue::String(value)),
+            Token::Identifier(value) => Ok(ConstValue::Identifier(value)),
+            Token::Symbol('[') => {
+                let mut values = Vec::new();
+                while !try!(self.accept_symbol(']')) {
+                    values.push(try!(self.const_value()));
+                    try!(self.list_separator());
+                }
+                Ok(ConstValue::List(values))
+            }
+            Token::Symbol('{') => {
+                let mut entries = Vec::new();
+                while !try!(self.accept_symbol('}')) {
+                    let key = try!(self.const_value());
+                    try!(self.expect_symbol(':'));
+                    let value = try!(self.const_value());
+                    entries.push((key, value));
+                    try!(self.list_separator());
+                }
+                Ok(ConstValue::Map(entries))
+            }
+            token => Err(format!("expected constant, found {}", describe(&token))),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match *token {
+        Token::Identifier(ref identifier) => format!("'{}'", identifier),
+        Token::Int(value) => format!("{}", value),
+        Token::Double(value) => format!("{}", value),
+        Token::Literal(ref literal) => format!("\"{}\"", literal),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+        Token::Eof => "end of file".to_string(),
+    }
+}
@@ -0,0 +1,141 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Generates code for the IDL files in tests/idl and builds it against torc_fboss_client
+
+extern crate torc_thrift_codegen;
+
+use std::{env, fs, io};
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use torc_thrift_codegen::{Builder, Error};
+
+fn idl(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/idl").join(name)
+}
+
+fn generate(name: &str) -> Result<String, Error> {
+    Builder::new().file(idl(name)).generate_string()
+}
+
+#[test]
+fn generated_code_compiles_and_runs() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let project = Path::new(env!("CARGO_TARGET_TMPDIR")).join("generated");
+    fs::create_dir_all(project.join("src")).unwrap();
+
+    let runtime = manifest_dir.parent().unwrap();
+    fs::write(project.join("Cargo.toml"),
+              format!("[package]\nname = \"generated\"\nversion = \"0.1.0\"\n\n[workspace]\n\n[dependencies]\n\
+                       torc_fboss_client = {{ path = {:?} }}\n",
+                      runtime.display().to_string()))
+        .unwrap();
+    // pin the versions the workspace was built with, so the build doesn't need the registry
+    if let Ok(lock) = fs::read(runtime.join("Cargo.lock")) {
+        fs::write(project.join("Cargo.lock"), lock).unwrap();
+    }
+    Builder::new().file(idl("switch.thrift")).output(project.join("src/switch.rs")).generate().unwrap();
+    fs::copy(idl("check_generated.rs"), project.join("src/main.rs")).unwrap();
+
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .arg("run")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(project.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", project.join("target"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(),
+            "generated code failed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn boxes_recursive_fields_only() {
+    let code = generate("switch.thrift").unwrap();
+    assert!(code.contains("pub parent: Option<Box<PortInfoThrift>>,"));
+    assert!(code.contains("pub lag: Option<Box<LagThrift>>,"));
+    assert!(code.contains("pub primary: Option<Box<PortInfoThrift>>,"));
+    assert!(code.contains("pub members: Option<Vec<PortInfoThrift>>,"));
+}
+
+#[test]
+fn rejects_required_recursive_field() {
+    match generate("self_required.thrift") {
+        Err(Error::Resolve(message)) => assert!(message.contains("next"), "{}", message),
+        other => panic!("expected a resolve error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn rejects_include_cycle() {
+    match generate("cycle_a.thrift") {
+        Err(Error::Resolve(message)) => {
+            assert!(message.starts_with("include cycle"), "{}", message);
+            assert!(message.contains("cycle_b.thrift"), "{}", message);
+        }
+        other => panic!("expected a resolve error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn loads_file_included_through_different_paths_once() {
+    // switch.thrift includes common.thrift from its own directory, `idl/../idl/common.thrift` is the same file
+    let code = Builder::new()
+        .file(idl("switch.thrift"))
+        .file(idl("../idl/common.thrift"))
+        .generate_string()
+        .unwrap();
+    assert_eq!(code.matches("pub mod common {").count(), 1);
+}
+
+#[test]
+fn reports_missing_file() {
+    let err = generate("missing.thrift").map(|_| ()).unwrap_err();
+    match err {
+        Error::Io(ref path, _) => assert!(path.ends_with("missing.thrift")),
+        ref other => panic!("expected an io error, got {:?}", other),
+    }
+    let source = err.source().and_then(|source| source.downcast_ref::<io::Error>()).unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn rejects_required_union_field() {
+    match generate("required_union.thrift") {
+        Err(Error::Resolve(message)) => assert!(message.contains("number"), "{}", message),
+        other => panic!("expected a resolve error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn checks_element_types_of_containers() {
+    let code = generate("switch.thrift").unwrap();
+    assert!(code.contains("check_element_type(runtime::protocol::Type::I32, type_0, size_0)?;"));
+    assert!(code.contains("check_element_type(runtime::protocol::Type::String, type_0, size_0)?;"));
+    assert!(code.contains("check_element_type(runtime::protocol::Type::List, value_type_0, size_0)?;"));
+    assert!(code.contains("check_element_type(runtime::protocol::Type::String, type_1, size_1)?;"));
+}
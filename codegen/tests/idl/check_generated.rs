@@ -0,0 +1,192 @@
+// Checks the code generated for switch.thrift, built and run by tests/generate.rs
+
+extern crate torc_fboss_client;
+
+include!("switch.rs");
+
+use torc_fboss_client::Error;
+use std::collections::BTreeMap;
+
+use torc_fboss_client::protocol::{self, ApplicationException, Encode, Decode, MessageType, Protocol, Type, Value,
+                                  write_value};
+use torc_fboss_client::protocol::binary_protocol::BinaryProtocol;
+use torc_fboss_client::protocol::helpers;
+use torc_fboss_client::transport::memory_transport::MemoryTransport;
+
+fn port(port_id: i32) -> switch::PortInfoThrift {
+    let mut port = switch::PortInfoThrift::default();
+    port.port_id = port_id;
+    port
+}
+
+fn reply<R: Encode>(method: &str, result: &R) -> MemoryTransport {
+    let mut reply = MemoryTransport::new();
+    helpers::send_message(&mut BinaryProtocol::new(), &mut reply, method, MessageType::Reply, 1, result).unwrap();
+    MemoryTransport::with_input(reply.take_written())
+}
+
+fn keeps_defaults_and_constants() {
+    assert_eq!(port(1).status, Some(switch::PortStatus::Up));
+    assert_eq!(switch::DEFAULT_PORT, 5909);
+    assert_eq!(switch::labels(), vec![1, 2, 3]);
+}
+
+fn round_trips_recursive_structs() {
+    let mut lag = switch::LagThrift::default();
+    lag.name = Some("lag0".to_string());
+    lag.primary = Some(Box::new(port(3)));
+
+    let mut original = port(1);
+    original.parent = Some(Box::new(port(2)));
+    original.members = Some(vec![port(3), port(4)]);
+    original.lag = Some(Box::new(lag));
+    original.counters = Some(vec![("in_bytes".to_string(), 42)].into_iter().collect());
+
+    let mut transport = MemoryTransport::new();
+    original.encode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+    let mut transport = MemoryTransport::with_input(transport.take_written());
+    let mut decoded = switch::PortInfoThrift::default();
+    decoded.decode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+    assert_eq!(decoded, original);
+}
+
+fn returns_declared_exceptions() {
+    let mut result = switch::SwitchCtrlGetPortInfoResult::default();
+    result.error = Some(common::FbossBaseError { message: Some("no such port".to_string()) });
+    let mut client = switch::SwitchCtrlClient::new(reply("getPortInfo", &result));
+    match client.get_port_info(7) {
+        Err(Error::Exception(exception)) => {
+            let exception = exception.downcast_ref::<common::FbossBaseError>().unwrap();
+            assert_eq!(exception.message, Some("no such port".to_string()));
+        }
+        other => panic!("expected FbossBaseError, got {:?}", other),
+    }
+}
+
+fn returns_application_exceptions() {
+    let exception = ApplicationException {
+        message: "Method name getPortInfo not found".to_string(),
+        kind: ApplicationException::UNKNOWN_METHOD,
+    };
+    let mut reply = MemoryTransport::new();
+    helpers::send_message(&mut BinaryProtocol::new(), &mut reply, "getPortInfo", MessageType::Exception, 1,
+                          &exception)
+        .unwrap();
+    let mut client = switch::SwitchCtrlClient::new(MemoryTransport::with_input(reply.take_written()));
+    match client.get_port_info(7) {
+        Err(Error::ApplicationException(ref received)) if *received == exception => {}
+        other => panic!("expected the application exception, got {:?}", other),
+    }
+}
+
+fn returns_success() {
+    let mut result = switch::SwitchCtrlGetPortInfoResult::default();
+    result.success = Some(port(7));
+    let mut client = switch::SwitchCtrlClient::new(reply("getPortInfo", &result));
+    assert_eq!(client.get_port_info(7).unwrap(), port(7));
+
+    let result = switch::SwitchCtrlSetPortStatusResult::default();
+    let mut client = switch::SwitchCtrlClient::new(reply("setPortStatus", &result));
+    client.set_port_status(7, switch::PortStatus::Down).unwrap();
+}
+
+fn decode<D: Decode + Default>(bytes: Vec<u8>) -> torc_fboss_client::Result<D> {
+    let mut decoded = D::default();
+    decoded.decode(&mut BinaryProtocol::new(), &mut MemoryTransport::with_input(bytes)).map(|_| decoded)
+}
+
+fn encode_value(value: &Value) -> Vec<u8> {
+    let mut transport = MemoryTransport::new();
+    write_value(&mut BinaryProtocol::new(), &mut transport, value).unwrap();
+    transport.take_written()
+}
+
+fn round_trips_containers_and_unions() {
+    let mut selector = switch::PortSelector::default();
+    selector.ports = Some(vec![1, 2]);
+    let mut group = switch::PortGroup::default();
+    group.ports = Some(vec![3, 4]);
+    group.tags = Some(vec!["uplink".to_string(), "spine".to_string()].into_iter().collect());
+    group.selector = Some(selector);
+    group.aliases = Some(vec![(3, vec!["eth1/3/1".to_string()])].into_iter().collect());
+
+    let mut transport = MemoryTransport::new();
+    group.encode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+    assert_eq!(decode::<switch::PortGroup>(transport.take_written()).unwrap(), group);
+
+    let mut result = switch::SwitchCtrlGetPortGroupResult::default();
+    result.success = Some(group.clone());
+    let mut client = switch::SwitchCtrlClient::new(reply("getPortGroup", &result));
+    assert_eq!(client.get_port_group(switch::PortSelector::default()).unwrap(), group);
+}
+
+fn rejects_unions_with_several_fields() {
+    let mut selector = switch::PortSelector::default();
+    selector.port_id = Some(1);
+    selector.name = Some("eth1/1/1".to_string());
+    let mut transport = MemoryTransport::new();
+    match selector.encode(&mut BinaryProtocol::new(), &mut transport) {
+        Err(Error::InvalidArgument(_)) => {}
+        other => panic!("expected InvalidArgument, got {:?}", other),
+    }
+    assert!(transport.written().is_empty());
+
+    let mut client = switch::SwitchCtrlClient::new(MemoryTransport::new());
+    assert!(client.get_port_group(selector).is_err());
+
+    let fields = vec![(1, Value::I32(1)), (2, Value::from("eth1/1/1"))];
+    let bytes = encode_value(&Value::Struct(fields.into_iter().collect::<BTreeMap<_, _>>()));
+    match decode::<switch::PortSelector>(bytes) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+}
+
+fn rejects_mismatched_element_types() {
+    let strings = vec![Value::from("1")];
+    let mismatches = vec![(1, Value::List(Type::String, strings.clone())),
+                          (2, Value::Set(Type::I32, vec![Value::I32(1)])),
+                          (4, Value::Map(Type::String, Type::List, vec![(Value::from("3"),
+                                                                        Value::List(Type::String, vec![]))])),
+                          (4, Value::Map(Type::I32, Type::Set, vec![(Value::I32(3),
+                                                                     Value::Set(Type::String, strings.clone()))])),
+                          (4, Value::Map(Type::I32, Type::List, vec![(Value::I32(3),
+                                                                      Value::List(Type::I32, vec![Value::I32(1)]))]))];
+    for (id, value) in mismatches {
+        let group = Value::Struct(vec![(id, value.clone())].into_iter().collect::<BTreeMap<_, _>>());
+        match decode::<switch::PortGroup>(encode_value(&group)) {
+            Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+            other => panic!("expected ProtocolViolation for {}, got {:?}", value, other),
+        }
+    }
+
+    // the element types of empty containers don't matter
+    let empty = vec![(1, Value::List(Type::String, vec![])), (4, Value::Map(Type::String, Type::Bool, vec![]))];
+    let group = decode::<switch::PortGroup>(encode_value(&Value::Struct(empty.into_iter().collect()))).unwrap();
+    assert_eq!(group.ports, Some(vec![]));
+}
+
+fn sends_oneway_calls_without_reading_a_reply() {
+    let mut client = switch::SwitchCtrlClient::new(MemoryTransport::new());
+    client.flush_counters(vec![1, 2].into_iter().collect()).unwrap();
+
+    let mut transport = MemoryTransport::with_input(client.into_transport().take_written());
+    let mut protocol = BinaryProtocol::new();
+    let (name, message_type, _) = protocol.read_message_begin(&mut transport).unwrap();
+    assert_eq!((&name[..], message_type), ("flushCounters", MessageType::Oneway));
+    let args = torc_fboss_client::protocol::read_value(&mut protocol, &mut transport, Type::Struct).unwrap();
+    let ports = Value::Set(Type::I32, vec![Value::I32(1), Value::I32(2)]);
+    assert_eq!(args, Value::Struct(vec![(1, ports)].into_iter().collect()));
+}
+
+fn main() {
+    keeps_defaults_and_constants();
+    round_trips_recursive_structs();
+    returns_declared_exceptions();
+    returns_application_exceptions();
+    returns_success();
+    round_trips_containers_and_unions();
+    rejects_unions_with_several_fields();
+    rejects_mismatched_element_types();
+    sends_oneway_calls_without_reading_a_reply();
+}
@@ -0,0 +1,5 @@
+namespace cpp2 facebook.fboss
+
+exception FbossBaseError {
+  1: string message
+}
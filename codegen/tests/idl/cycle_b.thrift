@@ -0,0 +1,5 @@
+include "cycle_a.thrift"
+
+struct B {
+  1: i32 b
+}
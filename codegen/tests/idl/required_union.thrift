@@ -0,0 +1,4 @@
+union Choice {
+  1: required i32 number,
+  2: string name
+}
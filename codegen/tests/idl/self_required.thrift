@@ -0,0 +1,3 @@
+struct Node {
+  1: required Node next
+}
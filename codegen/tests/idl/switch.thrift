@@ -0,0 +1,48 @@
+namespace cpp2 facebook.fboss
+
+include "common.thrift"
+
+const i32 DEFAULT_PORT = 5909
+const list<i32> LABELS = [1, 2, 3]
+
+typedef i32 PortID
+typedef list<PortID> PortList
+
+enum PortStatus {
+  DOWN = 0,
+  UP = 1,
+}
+
+struct PortInfoThrift {
+  1: required PortID portId,
+  2: PortStatus status = PortStatus.UP,
+  3: optional PortInfoThrift parent,
+  4: list<PortInfoThrift> members,
+  5: optional LagThrift lag,
+  6: map<string, i64> counters,
+}
+
+struct LagThrift {
+  1: string name,
+  2: optional PortInfoThrift primary,
+}
+
+union PortSelector {
+  1: PortID portId,
+  2: string name,
+  3: PortList ports,
+}
+
+struct PortGroup {
+  1: PortList ports,
+  2: set<string> tags,
+  3: PortSelector selector,
+  4: map<PortID, list<string>> aliases,
+}
+
+service SwitchCtrl {
+  PortInfoThrift getPortInfo(1: PortID portId) throws (1: common.FbossBaseError error)
+  void setPortStatus(1: PortID portId, 2: PortStatus status) throws (1: common.FbossBaseError error)
+  PortGroup getPortGroup(1: PortSelector selector)
+  oneway void flushCounters(1: set<PortID> ports)
+}
@@ -0,0 +1,538 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! `#[derive(ThriftStruct)]` implements `ThriftTyped`, `Encode` and `Decode` of `torc_fboss_client` for
+//! structs with named fields:
+//!
+//! ```ignore
+//! #[derive(Default, ThriftStruct)]
+//! struct PortStatus {
+//!     #[thrift(id = 1, required)]
+//!     enabled: bool,
+//!     #[thrift(id = 2)]
+//!     up: bool,
+//!     #[thrift(id = 3, name = "speedMbps")]
+//!     speed_mbps: Option<i64>,
+//!     #[thrift(id = 4, enum)]
+//!     admin_state: PortAdminState,
+//!     #[thrift(id = 5, default = "vec![1]")]
+//!     lanes: Vec<i32>,
+//! }
+//! ```
+//!
+//! Field attributes:
+//!
+//! * `id = N` the thrift field id, mandatory unless the field is `skip`ped
+//! * `required` decoding fails with `ProtocolViolation` if the field is missing
+//! * `default = "expr"` value the field gets if it is missing
+//! * `enum` the field, or the elements of the collection, is a fieldless enum read through `FromNum`
+//!   and written with `as i32`, the enum has to be `Copy`
+//! * `name = "..."` name of the field in the IDL, the rust name by default
+//! * `skip` the field is neither encoded nor decoded
+//!
+//! `Option` fields are optional, they are written only if they are `Some`, all other fields are always
+//! written. `bool`, `i8`, `i16`, `i32`, `i64`, `f64`, `String`, `Vec<u8>` (binary), `Vec`, `HashSet`,
+//! `BTreeSet`, `HashMap` and `BTreeMap` map to their thrift types, any other type has to implement
+//! `Encode` and `Decode` itself, like nested structs deriving `ThriftStruct`.
+//!
+//! The struct has to implement `Default`, the generated code refers to the runtime as `::torc_fboss_client`,
+//! `#[thrift(crate = "path")]` on the struct changes that.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Data, DeriveInput, Expr, Fields, GenericArgument, Ident, LitInt, LitStr, Path, PathArguments, Type};
+use syn::spanned::Spanned;
+
+#[proc_macro_derive(ThriftStruct, attributes(thrift))]
+pub fn derive_thrift_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => {
+            // syn's to_compile_error refers to ::core, which 2015 edition crates can't resolve
+            let errors = err.into_iter().map(|err| {
+                let message = err.to_string();
+                quote_spanned!(err.span()=> compile_error!(#message);)
+            });
+            quote!(#(#errors)*).into()
+        }
+    }
+}
+
+/// Thrift representation of a rust type
+enum Kind {
+    Bool,
+    Byte,
+    I16,
+    I32,
+    I64,
+    Double,
+    String,
+    Binary,
+    Enum,
+    /// Anything else, encoded and decoded through its own `Encode` and `Decode` implementations
+    Struct,
+    List(Box<Kind>),
+    Set(Box<Kind>),
+    Map(Box<Kind>, Box<Kind>),
+}
+
+struct Field<'a> {
+    ident: &'a Ident,
+    /// Type of the field, the type inside the `Option` for optional fields
+    ty: &'a Type,
+    kind: Kind,
+    id: i16,
+    name: String,
+    required: bool,
+    optional: bool,
+    default: Option<Expr>,
+}
+
+#[derive(Default)]
+struct FieldAttributes {
+    id: Option<i16>,
+    name: Option<String>,
+    required: bool,
+    is_enum: bool,
+    skip: bool,
+    default: Option<Expr>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<Tokens> {
+    let mut runtime: Path = parse_quote!(::torc_fboss_client);
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("thrift")) {
+        try!(attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                let path: LitStr = try!(try!(meta.value()).parse());
+                runtime = try!(path.parse());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported thrift attribute, expected `crate`"))
+            }
+        }));
+    }
+
+    let named = match input.data {
+        Data::Struct(ref data) => {
+            match data.fields {
+                Fields::Named(ref named) => named,
+                _ => return Err(syn::Error::new(input.span(), "ThriftStruct requires named fields")),
+            }
+        }
+        _ => return Err(syn::Error::new(input.span(), "ThriftStruct can only be derived for structs")),
+    };
+
+    let mut fields = Vec::new();
+    for field in &named.named {
+        let ident = field.ident.as_ref().unwrap();
+        let attributes = try!(field_attributes(field));
+        if attributes.skip {
+            continue;
+        }
+        let id = match attributes.id {
+            Some(id) => id,
+            None => return Err(syn::Error::new(field.span(), "missing #[thrift(id = N)]")),
+        };
+        if let Some(other) = fields.iter().find(|other: &&Field| other.id == id) {
+            return Err(syn::Error::new(field.span(), format!("field id {} is already used by {}", id, other.ident)));
+        }
+
+        let (ty, optional) = match generic_argument(&field.ty, "Option") {
+            Some(inner) => (inner, true),
+            None => (&field.ty, false),
+        };
+        if optional && attributes.required {
+            return Err(syn::Error::new(field.span(), "Option fields can't be required"));
+        }
+
+        fields.push(Field {
+            ident: ident,
+            ty: ty,
+            kind: try!(kind(ty, attributes.is_enum)),
+            id: id,
+            name: attributes.name.unwrap_or_else(|| ident.to_string()),
+            required: attributes.required,
+            optional: optional,
+            default: attributes.default,
+        });
+    }
+
+    let name = &input.ident;
+    let wire_name = name.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let encode_fields = fields.iter().map(|field| encode_field(&runtime, field));
+
+    let tracked: Vec<&Field> = fields.iter().filter(|field| field.required || field.default.is_some()).collect();
+    let seen: Vec<Ident> = tracked.iter().map(|field| format_ident!("has_{}", field.ident)).collect();
+    let decode_arms = fields.iter().map(|field| {
+        let ident = field.ident;
+        let id = field.id;
+        let wire_type = wire_type(&runtime, field.ty, &field.kind);
+        let value = decode_value(&runtime, field.ty, &field.kind, 0);
+        let assignment = if field.optional {
+            quote!(self.#ident = Some(#value);)
+        } else {
+            quote!(self.#ident = #value;)
+        };
+        let mark = if field.required || field.default.is_some() {
+            let seen = format_ident!("has_{}", ident);
+            quote!(#seen = true;)
+        } else {
+            quote!()
+        };
+        quote! {
+            (#id, field_type) if field_type == #wire_type => {
+                #assignment
+                #mark
+            }
+        }
+    });
+    let checks = tracked.iter().zip(seen.iter()).map(|(field, seen)| {
+        let ident = field.ident;
+        match field.default {
+            Some(ref default) if !field.required => {
+                let value = if field.optional { quote!(Some(#default)) } else { quote!(#default) };
+                quote! {
+                    if !#seen {
+                        self.#ident = #value;
+                    }
+                }
+            }
+            _ => {
+                quote! {
+                    if !#seen {
+                        return Err(#runtime::Error::from(#runtime::protocol::Error::ProtocolViolation));
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #runtime::protocol::ThriftTyped for #name #ty_generics #where_clause {
+            fn typ() -> #runtime::protocol::Type {
+                #runtime::protocol::Type::Struct
+            }
+        }
+
+        impl #impl_generics #runtime::protocol::Encode for #name #ty_generics #where_clause {
+            fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> #runtime::Result<()>
+            where P: #runtime::Protocol, T: #runtime::Transport {
+                protocol.write_struct_begin(transport, #wire_name)?;
+                #(#encode_fields)*
+                protocol.write_field_stop(transport)?;
+                protocol.write_struct_end(transport)
+            }
+        }
+
+        impl #impl_generics #runtime::protocol::Decode for #name #ty_generics #where_clause {
+            #[allow(unused_mut)]
+            fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> #runtime::Result<()>
+            where P: #runtime::Protocol, T: #runtime::Transport {
+                #(let mut #seen = false;)*
+                protocol.read_struct_begin(transport)?;
+                loop {
+                    let (_, field_type, field_id) = protocol.read_field_begin(transport)?;
+                    if field_type == #runtime::protocol::Type::Stop {
+                        break;
+                    }
+                    match (field_id, field_type) {
+                        #(#decode_arms)*
+                        _ => protocol.skip(transport, field_type)?,
+                    }
+                    protocol.read_field_end(transport)?;
+                }
+                protocol.read_struct_end(transport)?;
+                #(#checks)*
+                Ok(())
+            }
+        }
+    })
+}
+
+fn field_attributes(field: &syn::Field) -> syn::Result<FieldAttributes> {
+    let mut attributes = FieldAttributes::default();
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("thrift")) {
+        try!(attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                let id: LitInt = try!(try!(meta.value()).parse());
+                attributes.id = Some(try!(id.base10_parse()));
+            } else if meta.path.is_ident("name") {
+                let name: LitStr = try!(try!(meta.value()).parse());
+                attributes.name = Some(name.value());
+            } else if meta.path.is_ident("default") {
+                let default: LitStr = try!(try!(meta.value()).parse());
+                attributes.default = Some(try!(default.parse()));
+            } else if meta.path.is_ident("required") {
+                attributes.required = true;
+            } else if meta.path.is_ident("enum") {
+                attributes.is_enum = true;
+            } else if meta.path.is_ident("skip") {
+                attributes.skip = true;
+            } else {
+                return Err(meta.error("unsupported thrift attribute, expected one of `id`, `name`, `default`, \
+                                       `required`, `enum`, `skip`"));
+            }
+            Ok(())
+        }));
+    }
+    Ok(attributes)
+}
+
+/// Last segment of a type path, with its generic type arguments
+fn segment(ty: &Type) -> Option<(&Ident, Vec<&Type>)> {
+    let path = match *ty {
+        Type::Path(ref path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    let arguments = match segment.arguments {
+        PathArguments::AngleBracketed(ref arguments) => {
+            arguments.args
+                .iter()
+                .filter_map(|argument| match *argument {
+                    GenericArgument::Type(ref ty) => Some(ty),
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    Some((&segment.ident, arguments))
+}
+
+fn generic_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    match segment(ty) {
+        Some((ident, ref arguments)) if ident == name && arguments.len() == 1 => Some(arguments[0]),
+        _ => None,
+    }
+}
+
+fn kind(ty: &Type, is_enum: bool) -> syn::Result<Kind> {
+    let (ident, arguments) = match segment(ty) {
+        Some(segment) => segment,
+        None => return Err(syn::Error::new(ty.span(), "unsupported thrift field type")),
+    };
+
+    let name = ident.to_string();
+    Ok(match (name.as_str(), arguments.len()) {
+        ("bool", 0) => Kind::Bool,
+        ("i8", 0) => Kind::Byte,
+        ("i16", 0) => Kind::I16,
+        ("i32", 0) => Kind::I32,
+        ("i64", 0) => Kind::I64,
+        ("f64", 0) => Kind::Double,
+        ("String", 0) => Kind::String,
+        ("Vec", 1) if generic_argument(ty, "Vec").is_some_and(|element| is_ident(element, "u8")) => Kind::Binary,
+        ("Vec", 1) => Kind::List(Box::new(try!(kind(arguments[0], is_enum)))),
+        ("HashSet", 1) | ("BTreeSet", 1) => Kind::Set(Box::new(try!(kind(arguments[0], is_enum)))),
+        ("HashMap", 2) | ("BTreeMap", 2) => {
+            Kind::Map(Box::new(try!(kind(arguments[0], is_enum))),
+                      Box::new(try!(kind(arguments[1], is_enum))))
+        }
+        ("u8", 0) | ("u16", 0) | ("u32", 0) | ("u64", 0) | ("usize", 0) | ("isize", 0) | ("f32", 0) |
+        ("Option", _) => {
+            return Err(syn::Error::new(ty.span(), "type has no thrift representation"));
+        }
+        _ if is_enum => Kind::Enum,
+        _ => Kind::Struct,
+    })
+}
+
+fn is_ident(ty: &Type, name: &str) -> bool {
+    match segment(ty) {
+        Some((ident, ref arguments)) => ident == name && arguments.is_empty(),
+        None => false,
+    }
+}
+
+fn wire_type(runtime: &Path, ty: &Type, kind: &Kind) -> Tokens {
+    match *kind {
+        Kind::Bool => quote!(#runtime::protocol::Type::Bool),
+        Kind::Byte => quote!(#runtime::protocol::Type::Byte),
+        Kind::I16 => quote!(#runtime::protocol::Type::I16),
+        Kind::I32 | Kind::Enum => quote!(#runtime::protocol::Type::I32),
+        Kind::I64 => quote!(#runtime::protocol::Type::I64),
+        Kind::Double => quote!(#runtime::protocol::Type::Double),
+        Kind::String | Kind::Binary => quote!(#runtime::protocol::Type::String),
+        Kind::List(_) => quote!(#runtime::protocol::Type::List),
+        Kind::Set(_) => quote!(#runtime::protocol::Type::Set),
+        Kind::Map(_, _) => quote!(#runtime::protocol::Type::Map),
+        Kind::Struct => quote!(<#ty as #runtime::protocol::ThriftTyped>::typ()),
+    }
+}
+
+/// Type arguments of a collection type, in order
+fn element_types(ty: &Type) -> Vec<&Type> {
+    segment(ty).map(|(_, arguments)| arguments).unwrap_or_default()
+}
+
+fn encode_field(runtime: &Path, field: &Field) -> Tokens {
+    let ident = field.ident;
+    let name = &field.name;
+    let id = field.id;
+    let wire_type = wire_type(runtime, field.ty, &field.kind);
+
+    if field.optional {
+        let value = encode_value(runtime, field.ty, &field.kind, quote!(value), 0);
+        quote! {
+            if let Some(ref value) = self.#ident {
+                protocol.write_field_begin(transport, #name, #wire_type, #id)?;
+                #value
+                protocol.write_field_end(transport)?;
+            }
+        }
+    } else {
+        let value = encode_value(runtime, field.ty, &field.kind, quote!(&self.#ident), 0);
+        quote! {
+            protocol.write_field_begin(transport, #name, #wire_type, #id)?;
+            #value
+            protocol.write_field_end(transport)?;
+        }
+    }
+}
+
+/// Statements writing the value `value`, a reference, points to
+fn encode_value(runtime: &Path, ty: &Type, kind: &Kind, value: Tokens, depth: usize) -> Tokens {
+    match *kind {
+        Kind::Bool => quote!(protocol.write_bool(transport, *#value)?;),
+        Kind::Byte => quote!(protocol.write_byte(transport, *#value)?;),
+        Kind::I16 => quote!(protocol.write_i16(transport, *#value)?;),
+        Kind::I32 => quote!(protocol.write_i32(transport, *#value)?;),
+        Kind::I64 => quote!(protocol.write_i64(transport, *#value)?;),
+        Kind::Double => quote!(protocol.write_double(transport, *#value)?;),
+        Kind::String => quote!(protocol.write_str(transport, #value)?;),
+        Kind::Binary => quote!(protocol.write_binary(transport, #value)?;),
+        Kind::Enum => quote!(protocol.write_i32(transport, *#value as i32)?;),
+        Kind::Struct => quote!(#runtime::protocol::Encode::encode(#value, protocol, transport)?;),
+        Kind::List(ref element) |
+        Kind::Set(ref element) => {
+            let element_type = element_types(ty)[0];
+            let element_wire_type = wire_type(runtime, element_type, element);
+            let item = format_ident!("item_{}", depth);
+            let write_element = encode_value(runtime, element_type, element, quote!(#item), depth + 1);
+            let (begin, end) = if let Kind::List(_) = *kind {
+                (quote!(write_list_begin), quote!(write_list_end))
+            } else {
+                (quote!(write_set_begin), quote!(write_set_end))
+            };
+            quote! {
+                protocol.#begin(transport, #element_wire_type, (#value).len())?;
+                for #item in #value {
+                    #write_element
+                }
+                protocol.#end(transport)?;
+            }
+        }
+        Kind::Map(ref key_kind, ref value_kind) => {
+            let types = element_types(ty);
+            let key_wire_type = wire_type(runtime, types[0], key_kind);
+            let value_wire_type = wire_type(runtime, types[1], value_kind);
+            let key = format_ident!("key_{}", depth);
+            let item = format_ident!("value_{}", depth);
+            let write_key = encode_value(runtime, types[0], key_kind, quote!(#key), depth + 1);
+            let write_value = encode_value(runtime, types[1], value_kind, quote!(#item), depth + 1);
+            quote! {
+                protocol.write_map_begin(transport, #key_wire_type, #value_wire_type, (#value).len())?;
+                for (#key, #item) in #value {
+                    #write_key
+                    #write_value
+                }
+                protocol.write_map_end(transport)?;
+            }
+        }
+    }
+}
+
+/// Expression reading a value of type `ty`
+fn decode_value(runtime: &Path, ty: &Type, kind: &Kind, depth: usize) -> Tokens {
+    match *kind {
+        Kind::Bool => quote!(protocol.read_bool(transport)?),
+        Kind::Byte => quote!(protocol.read_byte(transport)?),
+        Kind::I16 => quote!(protocol.read_i16(transport)?),
+        Kind::I32 => quote!(protocol.read_i32(transport)?),
+        Kind::I64 => quote!(protocol.read_i64(transport)?),
+        Kind::Double => quote!(protocol.read_double(transport)?),
+        Kind::String => quote!(protocol.read_string(transport)?),
+        Kind::Binary => quote!(protocol.read_binary(transport)?),
+        Kind::Enum => quote!(#runtime::protocol::helpers::read_enum(protocol, transport)?),
+        Kind::Struct => {
+            quote!({
+                let mut value: #ty = ::std::default::Default::default();
+                #runtime::protocol::Decode::decode(&mut value, protocol, transport)?;
+                value
+            })
+        }
+        Kind::List(ref element) |
+        Kind::Set(ref element) => {
+            let element_type = element_types(ty)[0];
+            let element_wire_type = wire_type(runtime, element_type, element);
+            let read_element = decode_value(runtime, element_type, element, depth + 1);
+            let items = format_ident!("items_{}", depth);
+            let (begin, insert, end) = if let Kind::List(_) = *kind {
+                (quote!(read_list_begin), quote!(push), quote!(read_list_end))
+            } else {
+                (quote!(read_set_begin), quote!(insert), quote!(read_set_end))
+            };
+            quote!({
+                let (element_type, size) = protocol.#begin(transport)?;
+                #runtime::protocol::check_element_type(#element_wire_type, element_type, size)?;
+                let mut #items: #ty = ::std::default::Default::default();
+                for _ in 0..size {
+                    #items.#insert(#read_element);
+                }
+                protocol.#end(transport)?;
+                #items
+            })
+        }
+        Kind::Map(ref key_kind, ref value_kind) => {
+            let types = element_types(ty);
+            let key_wire_type = wire_type(runtime, types[0], key_kind);
+            let value_wire_type = wire_type(runtime, types[1], value_kind);
+            let read_key = decode_value(runtime, types[0], key_kind, depth + 1);
+            let read_value = decode_value(runtime, types[1], value_kind, depth + 1);
+            let items = format_ident!("items_{}", depth);
+            quote!({
+                let (key_type, value_type, size) = protocol.read_map_begin(transport)?;
+                #runtime::protocol::check_element_type(#key_wire_type, key_type, size)?;
+                #runtime::protocol::check_element_type(#value_wire_type, value_type, size)?;
+                let mut #items: #ty = ::std::default::Default::default();
+                for _ in 0..size {
+                    let key = #read_key;
+                    let value = #read_value;
+                    #items.insert(key, value);
+                }
+                protocol.read_map_end(transport)?;
+                #items
+            })
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Round trips structs deriving ThriftStruct through the binary and compact protocols
+
+extern crate torc_fboss_client;
+
+use std::collections::BTreeMap;
+
+use torc_fboss_client::{Error, ThriftStruct};
+use torc_fboss_client::protocol::{self, Decode, Encode, FromNum, Protocol, Type, Value};
+use torc_fboss_client::protocol::binary_protocol::BinaryProtocol;
+use torc_fboss_client::protocol::compact_protocol::CompactProtocol;
+use torc_fboss_client::transport::memory_transport::MemoryTransport;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PortAdminState {
+    Disabled = 0,
+    Enabled = 1,
+}
+
+impl Default for PortAdminState {
+    fn default() -> PortAdminState {
+        PortAdminState::Disabled
+    }
+}
+
+impl FromNum for PortAdminState {
+    fn from_num(num: i32) -> Option<PortAdminState> {
+        match num {
+            0 => Some(PortAdminState::Disabled),
+            1 => Some(PortAdminState::Enabled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, ThriftStruct)]
+struct PortCounters {
+    #[thrift(id = 1, required)]
+    bytes: i64,
+    #[thrift(id = 2, name = "ucastPkts")]
+    unicast_pkts: i64,
+    #[thrift(id = 3)]
+    discards: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, ThriftStruct)]
+struct PortStat {
+    #[thrift(id = 1, required, name = "portId")]
+    port_id: i32,
+    #[thrift(id = 2)]
+    name: String,
+    #[thrift(id = 3, enum, name = "adminState")]
+    admin_state: PortAdminState,
+    #[thrift(id = 4)]
+    input: PortCounters,
+    #[thrift(id = 5)]
+    description: Option<String>,
+    #[thrift(id = 6, default = "vec![1]")]
+    lanes: Vec<i32>,
+    #[thrift(id = 7)]
+    counters: BTreeMap<String, i64>,
+    #[thrift(id = 8, enum)]
+    history: Vec<PortAdminState>,
+    #[thrift(id = 9)]
+    speed: f64,
+    #[thrift(skip)]
+    polled: u32,
+}
+
+fn port_stat() -> PortStat {
+    PortStat {
+        port_id: 12,
+        name: "eth1/3/1".to_string(),
+        admin_state: PortAdminState::Enabled,
+        input: PortCounters {
+            bytes: 1 << 40,
+            unicast_pkts: -1,
+            discards: Some(3),
+        },
+        description: None,
+        lanes: vec![4, 5],
+        counters: vec![("in_errors".to_string(), 7), ("out_errors".to_string(), 0)].into_iter().collect(),
+        history: vec![PortAdminState::Disabled, PortAdminState::Enabled],
+        speed: 100_000.5,
+        polled: 0,
+    }
+}
+
+fn round_trip<P: Protocol, V: Encode + Decode + Default>(mut protocol: P, value: &V) -> V {
+    let mut transport = MemoryTransport::new();
+    value.encode(&mut protocol, &mut transport).unwrap();
+    let mut transport = MemoryTransport::with_input(transport.take_written());
+    let mut decoded = V::default();
+    decoded.decode(&mut protocol, &mut transport).unwrap();
+    assert!(transport.remaining().is_empty());
+    decoded
+}
+
+/// Decodes a `PortStat` from the fields of `value`, written with the binary protocol
+fn decode_fields(fields: Vec<(i16, Value)>) -> Result<PortStat, Error> {
+    let mut transport = MemoryTransport::new();
+    let value = Value::Struct(fields.into_iter().collect());
+    protocol::write_value(&mut BinaryProtocol::new(), &mut transport, &value).unwrap();
+    let mut transport = MemoryTransport::with_input(transport.take_written());
+    let mut decoded = PortStat::default();
+    try!(decoded.decode(&mut BinaryProtocol::new(), &mut transport));
+    Ok(decoded)
+}
+
+#[test]
+fn round_trips_port_stat() {
+    let original = port_stat();
+    assert_eq!(round_trip(BinaryProtocol::new(), &original), original);
+    assert_eq!(round_trip(CompactProtocol::new(), &original), original);
+}
+
+#[test]
+fn writes_field_ids_and_skips_skipped_fields() {
+    let mut original = port_stat();
+    original.polled = 99;
+    let mut transport = MemoryTransport::new();
+    original.encode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+    let mut transport = MemoryTransport::with_input(transport.take_written());
+    let value = protocol::read_value(&mut BinaryProtocol::new(), &mut transport, Type::Struct).unwrap();
+    match value {
+        Value::Struct(fields) => {
+            assert_eq!(fields.keys().cloned().collect::<Vec<i16>>(), vec![1, 2, 3, 4, 6, 7, 8, 9]);
+            assert_eq!(fields[&1], Value::I32(12));
+            assert_eq!(fields[&3], Value::I32(1));
+        }
+        other => panic!("expected a struct, got {:?}", other),
+    }
+    assert_eq!(round_trip(BinaryProtocol::new(), &original).polled, 0);
+}
+
+#[test]
+fn fills_in_defaults_of_missing_fields() {
+    let decoded = decode_fields(vec![(1, Value::I32(12))]).unwrap();
+    assert_eq!(decoded.port_id, 12);
+    assert_eq!(decoded.lanes, vec![1]);
+    assert_eq!(decoded.description, None);
+    assert_eq!(decoded.input, PortCounters::default());
+}
+
+#[test]
+fn rejects_missing_required_field() {
+    match decode_fields(vec![(2, Value::Binary(b"eth1/3/1".to_vec()))]) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_missing_required_field_of_nested_struct() {
+    let input = Value::Struct(vec![(2, Value::I64(5))].into_iter().collect());
+    match decode_fields(vec![(1, Value::I32(12)), (4, input)]) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+}
+
+#[test]
+fn skips_fields_with_unexpected_types() {
+    // a required field of the wrong type doesn't count as present
+    match decode_fields(vec![(1, Value::Binary(b"12".to_vec()))]) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+
+    let decoded = decode_fields(vec![(1, Value::I32(12)),
+                                     (2, Value::I64(1)),
+                                     (100, Value::List(Type::I32, vec![Value::I32(1)]))])
+        .unwrap();
+    assert_eq!(decoded.name, "");
+}
+
+#[test]
+fn rejects_unknown_enum_values() {
+    match decode_fields(vec![(1, Value::I32(12)), (3, Value::I32(7))]) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+    let history = Value::List(Type::I32, vec![Value::I32(1), Value::I32(-1)]);
+    match decode_fields(vec![(1, Value::I32(12)), (8, history)]) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_truncated_input() {
+    let mut transport = MemoryTransport::new();
+    port_stat().encode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+    let bytes = transport.take_written();
+    for length in 0..bytes.len() {
+        let mut transport = MemoryTransport::with_input(bytes[..length].to_vec());
+        let mut decoded = PortStat::default();
+        assert!(decoded.decode(&mut BinaryProtocol::new(), &mut transport).is_err(), "{} bytes", length);
+    }
+}
+
+#[test]
+fn rejects_mismatched_element_types() {
+    let strings = Value::List(Type::String, vec![Value::Binary(b"4".to_vec())]);
+    match decode_fields(vec![(1, Value::I32(12)), (6, strings)]) {
+        Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+        other => panic!("expected ProtocolViolation, got {:?}", other),
+    }
+
+    let mismatches = vec![Value::Map(Type::I32, Type::I64, vec![(Value::I32(1), Value::I64(7))]),
+                          Value::Map(Type::String, Type::I32, vec![(Value::Binary(b"in".to_vec()), Value::I32(7))])];
+    for counters in mismatches {
+        match decode_fields(vec![(1, Value::I32(12)), (7, counters.clone())]) {
+            Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+            other => panic!("expected ProtocolViolation for {}, got {:?}", counters, other),
+        }
+    }
+
+    // the element types of empty containers don't matter
+    let decoded = decode_fields(vec![(1, Value::I32(12)),
+                                     (6, Value::List(Type::String, vec![])),
+                                     (7, Value::Map(Type::Bool, Type::Bool, vec![]))])
+        .unwrap();
+    assert!(decoded.lanes.is_empty() && decoded.counters.is_empty());
+}
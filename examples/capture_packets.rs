@@ -0,0 +1,44 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Simple example client to capture packets sent to/from the CPU of the switch
+//
+
+extern crate torc_fboss_client;
+
+use torc_fboss_client::api::{self, CaptureDirection};
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let mut fboss = "127.0.0.1:5909".to_string();
+
+    let args: Vec<_> = env::args().collect();
+    if args.len() == 2 {
+        fboss = args[1].clone();
+    }
+
+    api::start_pkt_capture(&fboss, "torc_capture", 1000, CaptureDirection::TxRx, None);
+    thread::sleep(Duration::from_secs(10));
+    api::stop_pkt_capture(&fboss, "torc_capture");
+}
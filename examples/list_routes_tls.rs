@@ -0,0 +1,54 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Simple example client to list routes over mutual TLS,
+// run with `cargo run --features tls --example list_routes_tls`
+//
+
+extern crate torc_fboss_client;
+
+use torc_fboss_client::api::FbossClient;
+use torc_fboss_client::transport::tls_transport::TlsConfig;
+use std::env;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 5 {
+        println!("Usage: {} <fboss> <ca.pem> <client-cert.pem> <client-key.pem> [server name]",
+                 args[0]);
+        return;
+    }
+
+    let mut config = TlsConfig::new()
+        .with_ca_file(&args[2])
+        .with_client_certificate(&args[3], &args[4]);
+    if args.len() == 6 {
+        config = config.with_server_name(&args[5]);
+    }
+
+    let mut client = FbossClient::connect_tls(&args[1], &config).unwrap();
+    let routes = client.get_routes().unwrap();
+    for route in &routes {
+        println!("Route {} --> {}", route.from, route.to);
+    }
+
+}
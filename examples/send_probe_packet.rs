@@ -0,0 +1,49 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Simple example client to inject a UDP probe packet, routed by the switch
+//
+
+extern crate torc_fboss_client;
+
+use torc_fboss_client::api;
+use torc_fboss_client::api::packet;
+use std::env;
+use std::net::Ipv4Addr;
+
+fn main() {
+    let mut fboss = "127.0.0.1:5909".to_string();
+
+    let args: Vec<_> = env::args().collect();
+    if args.len() == 2 {
+        fboss = args[1].clone();
+    }
+
+    let probe = packet::build_udp_packet(&Ipv4Addr::new(10, 250, 3, 1),
+                                         &Ipv4Addr::new(10, 250, 3, 94),
+                                         33434,
+                                         33434,
+                                         b"torc probe")
+        .expect("probe payload fits in a UDP packet");
+
+    api::tx_pkt_l3(&fboss, &probe);
+}
@@ -0,0 +1,36 @@
+// Feeds arbitrary input as the agent's replies to the calls of the FbossClient, which must
+// fail with an error instead of panicking. Decoding allocates no more than the limits allow
+// for what it actually read, so memory stays proportional to the input.
+//
+//     cargo fuzz run client_reply
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate torc_fboss_client;
+
+use std::collections::BTreeMap;
+
+use torc_fboss_client::api::FbossClient;
+use torc_fboss_client::protocol::{Limits, Value};
+use torc_fboss_client::protocol::binary_protocol::BinaryProtocol;
+use torc_fboss_client::transport::memory_transport::MemoryTransport;
+
+// smaller than the defaults to keep each run fast
+fn limits() -> Limits {
+    Limits::new().with_max_string_length(1 << 20).with_max_container_size(1 << 16)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let protocol = BinaryProtocol::with_limits(limits());
+    let mut client = FbossClient::with_protocol(MemoryTransport::with_input(data.to_vec()), protocol);
+    let _ = client.get_ports_stats();
+    let _ = client.get_routes();
+    let _ = client.get_running_config();
+    let _ = client.get_switch_run_state();
+    let _ = client.get_product_info();
+    let _ = client.get_acl_table();
+    let _ = client.get_mpls_route_table();
+    let _ = client.call("getFoo", Value::Struct(BTreeMap::new()));
+});
@@ -0,0 +1,35 @@
+// Decodes arbitrary input as a compact protocol message, which must fail cleanly instead of
+// panicking or exhausting memory
+//
+//     cargo fuzz run compact_protocol
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate torc_fboss_client;
+
+use torc_fboss_client::Protocol;
+use torc_fboss_client::protocol::{Limits, Type, read_value};
+use torc_fboss_client::protocol::compact_protocol::CompactProtocol;
+use torc_fboss_client::transport::memory_transport::MemoryTransport;
+
+// smaller than the defaults to keep each run fast
+fn limits() -> Limits {
+    Limits::new().with_max_string_length(1 << 20).with_max_container_size(1 << 16)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut protocol = CompactProtocol::new();
+    protocol.set_limits(limits());
+    let mut transport = MemoryTransport::with_input(data.to_vec());
+    if protocol.read_message_begin(&mut transport).is_ok() {
+        let _ = read_value(&mut protocol, &mut transport, Type::Struct);
+    }
+
+    // skipping takes its own path through the input
+    let mut transport = MemoryTransport::with_input(data.to_vec());
+    if protocol.read_message_begin(&mut transport).is_ok() {
+        let _ = protocol.skip(&mut transport, Type::Struct);
+    }
+});
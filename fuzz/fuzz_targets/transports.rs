@@ -0,0 +1,59 @@
+// Feeds arbitrary input as what the agent sends over a framed or header transport, reading it
+// must fail with an error instead of panicking, and no frame beyond the maximum frame size may
+// be buffered
+//
+//     cargo fuzz run transports
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate torc_fboss_client;
+
+use std::io::Read;
+
+use torc_fboss_client::api::FbossClient;
+use torc_fboss_client::protocol::Limits;
+use torc_fboss_client::protocol::binary_protocol::BinaryProtocol;
+use torc_fboss_client::transport::framed_transport::FramedTransport;
+use torc_fboss_client::transport::header_transport::HeaderTransport;
+use torc_fboss_client::transport::memory_transport::MemoryTransport;
+
+// smaller than the defaults to keep each run fast
+const MAX_FRAME_SIZE: usize = 1 << 20;
+
+fn limits() -> Limits {
+    Limits::new().with_max_string_length(1 << 20).with_max_container_size(1 << 16)
+}
+
+/// Reads the frames until the input runs out or is rejected
+fn read_all<R: Read>(transport: &mut R) {
+    let mut buf = [0u8; 4096];
+    loop {
+        match transport.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut framed = FramedTransport::with_max_frame_size(MemoryTransport::with_input(data.to_vec()), MAX_FRAME_SIZE);
+    read_all(&mut framed);
+
+    let mut header = HeaderTransport::new(MemoryTransport::with_input(data.to_vec()));
+    header.set_max_frame_size(MAX_FRAME_SIZE);
+    read_all(&mut header);
+
+    // replies to calls, which also write frames
+    let mut header = HeaderTransport::new(MemoryTransport::with_input(data.to_vec()));
+    header.set_max_frame_size(MAX_FRAME_SIZE);
+    let mut client = FbossClient::with_protocol(header, BinaryProtocol::with_limits(limits()));
+    let _ = client.get_routes();
+    let _ = client.get_switch_run_state();
+
+    let framed = FramedTransport::with_max_frame_size(MemoryTransport::with_input(data.to_vec()), MAX_FRAME_SIZE);
+    let mut client = FbossClient::with_protocol(framed, BinaryProtocol::with_limits(limits()));
+    let _ = client.get_routes();
+    let _ = client.get_switch_run_state();
+});
@@ -0,0 +1,779 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// FbossClient for async runtimes on top of tokio, behind the `async` feature
+//
+// Requests are encoded into memory by the same functions the blocking client uses,
+// responses are decoded by the same functions once all of their bytes arrived.
+
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+use {Protocol, Result, Error};
+use protocol::Type;
+use protocol::binary_protocol::BinaryProtocol;
+use transport::memory_transport::MemoryTransport;
+
+use super::{PortStat, Route, SwitchRunState, BootType, ProductInfo, AclEntry, MplsRoute, CaptureDirection,
+            CaptureFilter, CaptureInfo};
+use super::{send_request_for_all_ports_stats, handle_response_for_all_ports_stats, send_request_for_get_routes,
+            handle_response_for_get_routes, send_request_for_sync_fib, send_request_for_add_route,
+            send_request_for_delete_route, send_request_for_get_running_config,
+            handle_response_for_get_running_config, send_request_for_reload_config, send_request_without_args,
+            handle_response_for_switch_run_state, handle_response_for_boot_type,
+            handle_response_for_product_info, handle_response_for_get_acl_table,
+            handle_response_for_get_mpls_route_table, send_request_for_mpls_routes,
+            send_request_for_delete_mpls_routes, send_request_for_tx_pkt, send_request_for_tx_pkt_l3,
+            send_request_for_start_pkt_capture, send_request_for_stop_pkt_capture,
+            handle_response_for_get_pkt_captures, handle_default_response};
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+type Decoder<P, R> = fn(&mut P, &mut ResponseReader, i32) -> Result<R>;
+
+/// Client for the FbossCtrl service of the agent on an async runtime
+///
+/// Every operation returns a future resolving to the same result as the operation of the
+/// blocking `FbossClient`. Dropping a call before it completed leaves the connection out of
+/// sync, the client has to be replaced then. Timeouts are left to the runtime, e.g.
+/// `tokio::time::timeout`.
+pub struct AsyncFbossClient<S = TcpStream, P = BinaryProtocol>
+    where S: AsyncRead + AsyncWrite + Unpin,
+          P: Protocol
+{
+    stream: S,
+    protocol: P,
+    request_number: i32,
+    input: Vec<u8>,
+}
+
+impl AsyncFbossClient {
+    /// Connects to `host:port`, other streams like tokio's `UnixStream` can be passed to `new`
+    pub fn connect(connect_string: &str) -> Connect {
+        Connect { connect: Box::pin(TcpStream::connect(connect_string.to_string())) }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncFbossClient<S> {
+    pub fn new(stream: S) -> AsyncFbossClient<S> {
+        AsyncFbossClient::with_protocol(stream, BinaryProtocol::new())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin, P: Protocol + Clone> AsyncFbossClient<S, P> {
+    pub fn with_protocol(stream: S, protocol: P) -> AsyncFbossClient<S, P> {
+        AsyncFbossClient {
+            stream: stream,
+            protocol: protocol,
+            request_number: 0,
+            input: Vec::new(),
+        }
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_stream(self) -> S {
+        self.stream
+    }
+
+    pub fn get_ports_stats(&mut self) -> Call<'_, S, P, Vec<PortStat>> {
+        self.call(send_request_for_all_ports_stats, handle_response_for_all_ports_stats)
+    }
+
+    pub fn get_routes(&mut self) -> Call<'_, S, P, Vec<Route>> {
+        self.call(send_request_for_get_routes, handle_response_for_get_routes)
+    }
+
+    pub fn sync_routes(&mut self) -> Call<'_, S, P, ()> {
+        self.call(send_request_for_sync_fib, handle_default_response)
+    }
+
+    pub fn add_route(&mut self, route_from: &str, route_to: &str) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_add_route(protocol, transport, request_number, route_from, route_to)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn delete_route(&mut self, route_from: &str) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_delete_route(protocol, transport, request_number, route_from)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn get_running_config(&mut self) -> Call<'_, S, P, String> {
+        self.call(send_request_for_get_running_config, handle_response_for_get_running_config)
+    }
+
+    pub fn reload_config(&mut self) -> Call<'_, S, P, ()> {
+        self.call(send_request_for_reload_config, handle_default_response)
+    }
+
+    pub fn get_switch_run_state(&mut self) -> Call<'_, S, P, SwitchRunState> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getSwitchRunState")
+                  },
+                  handle_response_for_switch_run_state)
+    }
+
+    pub fn get_boot_type(&mut self) -> Call<'_, S, P, BootType> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getBootType")
+                  },
+                  handle_response_for_boot_type)
+    }
+
+    pub fn get_product_info(&mut self) -> Call<'_, S, P, ProductInfo> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getProductInfo")
+                  },
+                  handle_response_for_product_info)
+    }
+
+    pub fn get_acl_table(&mut self) -> Call<'_, S, P, Vec<AclEntry>> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getAclTable")
+                  },
+                  handle_response_for_get_acl_table)
+    }
+
+    pub fn get_mpls_route_table(&mut self) -> Call<'_, S, P, Vec<MplsRoute>> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getMplsRouteTable")
+                  },
+                  handle_response_for_get_mpls_route_table)
+    }
+
+    pub fn add_mpls_routes(&mut self, routes: &[MplsRoute]) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_mpls_routes(protocol, transport, request_number, "addMplsRoutes", routes)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn delete_mpls_routes(&mut self, top_labels: &[i32]) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_delete_mpls_routes(protocol, transport, request_number, top_labels)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn sync_mpls_fib(&mut self, routes: &[MplsRoute]) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_mpls_routes(protocol, transport, request_number, "syncMplsFib", routes)
+                  },
+                  handle_default_response)
+    }
+
+    /// Sends a complete ethernet frame out of the given port
+    pub fn tx_pkt(&mut self, port: i32, data: &[u8]) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_tx_pkt(protocol, transport, request_number, Some(port), data)
+                  },
+                  handle_default_response)
+    }
+
+    /// Sends a complete ethernet frame, switched by the ASIC
+    pub fn tx_pkt_l2(&mut self, data: &[u8]) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_tx_pkt(protocol, transport, request_number, None, data)
+                  },
+                  handle_default_response)
+    }
+
+    /// Sends an IP packet, routed by the ASIC
+    pub fn tx_pkt_l3(&mut self, payload: &[u8]) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_tx_pkt_l3(protocol, transport, request_number, payload)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn start_pkt_capture(&mut self,
+                             name: &str,
+                             max_packets: i32,
+                             direction: CaptureDirection,
+                             filter: Option<CaptureFilter>)
+                             -> Call<'_, S, P, ()> {
+        let capture_info = CaptureInfo {
+            name: name.to_string(),
+            max_packets: max_packets,
+            direction: direction,
+            filter: filter,
+        };
+
+        self.start_pkt_capture_with_info(&capture_info)
+    }
+
+    pub fn start_pkt_capture_with_info(&mut self, capture_info: &CaptureInfo) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_start_pkt_capture(protocol, transport, request_number, capture_info)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn stop_pkt_capture(&mut self, name: &str) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_for_stop_pkt_capture(protocol, transport, request_number, name)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn stop_all_pkt_captures(&mut self) -> Call<'_, S, P, ()> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "stopAllPktCaptures")
+                  },
+                  handle_default_response)
+    }
+
+    pub fn get_pkt_captures(&mut self) -> Call<'_, S, P, Vec<CaptureInfo>> {
+        self.call(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getPktCaptures")
+                  },
+                  handle_response_for_get_pkt_captures)
+    }
+
+    fn call<R, E>(&mut self, encode_request: E, decode_response: Decoder<P, R>) -> Call<'_, S, P, R>
+        where E: FnOnce(&mut P, &mut MemoryTransport, i32) -> Result<()>
+    {
+        self.request_number = self.request_number.wrapping_add(1);
+        let request_number = self.request_number;
+
+        let mut transport = MemoryTransport::new();
+        let request = encode_request(&mut self.protocol, &mut transport, request_number)
+            .map(|_| transport.take_written());
+
+        Call {
+            scanner: ResponseScanner::new(self.protocol.clone()),
+            client: self,
+            request: request,
+            written: 0,
+            flushed: false,
+            request_number: request_number,
+            decode_response: decode_response,
+        }
+    }
+}
+
+/// Future resolving to the result of the `Connect` operation
+pub struct Connect {
+    connect: Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>,
+}
+
+impl Future for Connect {
+    type Output = Result<AsyncFbossClient>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<AsyncFbossClient>> {
+        match self.connect.as_mut().poll(cx) {
+            Poll::Ready(Ok(stream)) => {
+                if let Err(err) = stream.set_nodelay(true) {
+                    return Poll::Ready(Err(Error::from(err)));
+                }
+                Poll::Ready(Ok(AsyncFbossClient::new(stream)))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(Error::from(err))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future resolving to the result of one operation of the `AsyncFbossClient`
+pub struct Call<'a, S, P, R>
+    where S: AsyncRead + AsyncWrite + Unpin + 'a,
+          P: Protocol + 'a
+{
+    client: &'a mut AsyncFbossClient<S, P>,
+    request: Result<Vec<u8>>,
+    written: usize,
+    flushed: bool,
+    request_number: i32,
+    decode_response: Decoder<P, R>,
+    scanner: ResponseScanner<P>,
+}
+
+impl<'a, S, P, R> Call<'a, S, P, R>
+    where S: AsyncRead + AsyncWrite + Unpin + 'a,
+          P: Protocol + Clone + 'a
+{
+    fn poll_send(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        let request = match self.request {
+            Ok(ref request) => request,
+            Err(_) => {
+                let err = mem::replace(&mut self.request, Ok(Vec::new()));
+                return Poll::Ready(err.map(|_| ()));
+            }
+        };
+
+        while self.written < request.len() {
+            match Pin::new(&mut self.client.stream).poll_write(cx, &request[self.written..]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(Error::from(io::Error::new(io::ErrorKind::WriteZero,
+                                                                       "connection closed"))))
+                }
+                Poll::Ready(Ok(written)) => self.written += written,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::from(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        if !self.flushed {
+            match Pin::new(&mut self.client.stream).poll_flush(cx) {
+                Poll::Ready(Ok(())) => self.flushed = true,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::from(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Decodes the response once all of its bytes were received, `None` if more are needed
+    fn try_decode(&mut self) -> Option<Result<R>> {
+        let end = match self.scanner.scan(&mut self.client.input) {
+            Ok(Some(end)) => end,
+            Ok(None) => return None,
+            // the stream can't be resynchronized after a malformed response
+            Err(err) => return Some(Err(err)),
+        };
+
+        let rest = self.client.input.split_off(end);
+        let mut reader = ResponseReader {
+            input: mem::replace(&mut self.client.input, rest),
+            position: 0,
+        };
+        Some((self.decode_response)(&mut self.client.protocol, &mut reader, self.request_number))
+    }
+
+    fn poll_receive(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        let received = self.client.input.len();
+        self.client.input.resize(received + READ_CHUNK_SIZE, 0);
+
+        let result = {
+            let mut buf = ReadBuf::new(&mut self.client.input[received..]);
+            match Pin::new(&mut self.client.stream).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                Poll::Pending => Poll::Pending,
+            }
+        };
+
+        let read = match result {
+            Poll::Ready(Ok(read)) => read,
+            Poll::Ready(Err(err)) => {
+                self.client.input.truncate(received);
+                return Poll::Ready(Err(Error::from(err)));
+            }
+            Poll::Pending => {
+                self.client.input.truncate(received);
+                return Poll::Pending;
+            }
+        };
+
+        self.client.input.truncate(received + read);
+        if read == 0 {
+            return Poll::Ready(Err(Error::from(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                              "connection closed"))));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, S, P, R> Future for Call<'a, S, P, R>
+    where S: AsyncRead + AsyncWrite + Unpin + 'a,
+          P: Protocol + Clone + 'a
+{
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<R>> {
+        let call = self.get_mut();
+
+        match call.poll_send(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        loop {
+            if let Some(result) = call.try_decode() {
+                return Poll::Ready(result);
+            }
+            match call.poll_receive(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Values of a response still to be scanned, innermost last
+#[derive(Clone, Copy, Debug)]
+enum Pending {
+    Value(Type),
+    /// Fields of a struct up to its stop field
+    Fields,
+    FieldEnd,
+    /// Elements left of a list or set, the first type tells which of them it is
+    Elements(Type, Type, i32),
+    /// Keys and values left of a map, counted separately
+    Entries(Type, Type, i32),
+    MessageEnd,
+}
+
+/// Finds the end of a response without decoding it.
+///
+/// The scan advances one value at a time and keeps its position when it runs out of bytes,
+/// so every byte is scanned once, only a string that arrives in pieces is read again.
+struct ResponseScanner<P> {
+    protocol: P,
+    position: usize,
+    pending: Vec<Pending>,
+    started: bool,
+}
+
+// the scanner is never pinned, it is only used through `&mut`
+impl<P> Unpin for ResponseScanner<P> {}
+
+impl<P: Protocol + Clone> ResponseScanner<P> {
+    fn new(protocol: P) -> ResponseScanner<P> {
+        ResponseScanner {
+            protocol: protocol,
+            position: 0,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Length of the response if `input` holds all of it
+    fn scan(&mut self, input: &mut Vec<u8>) -> Result<Option<usize>> {
+        let mut reader = ResponseReader {
+            input: mem::take(input),
+            position: self.position,
+        };
+        let result = self.scan_from(&mut reader);
+        *input = reader.input;
+        result
+    }
+
+    fn scan_from(&mut self, reader: &mut ResponseReader) -> Result<Option<usize>> {
+        loop {
+            if self.started && self.pending.is_empty() {
+                return Ok(Some(self.position));
+            }
+
+            let protocol = self.protocol.clone();
+            let pending = self.pending.clone();
+            match self.step(reader) {
+                Ok(()) => self.position = reader.position,
+                Err(Error::TransportError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.protocol = protocol;
+                    self.pending = pending;
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn step(&mut self, reader: &mut ResponseReader) -> Result<()> {
+        if !self.started {
+            try!(self.protocol.read_message_begin(reader));
+            self.pending.push(Pending::MessageEnd);
+            self.pending.push(Pending::Value(Type::Struct));
+            self.started = true;
+            return Ok(());
+        }
+
+        match self.pending.pop() {
+            Some(Pending::Value(Type::Struct)) => {
+                try!(self.protocol.read_struct_begin(reader));
+                self.pending.push(Pending::Fields);
+            }
+            Some(Pending::Value(Type::List)) => {
+                let (elem_type, size) = try!(self.protocol.read_list_begin(reader));
+                self.pending.push(Pending::Elements(Type::List, elem_type, size));
+            }
+            Some(Pending::Value(Type::Set)) => {
+                let (elem_type, size) = try!(self.protocol.read_set_begin(reader));
+                self.pending.push(Pending::Elements(Type::Set, elem_type, size));
+            }
+            Some(Pending::Value(Type::Map)) => {
+                let (key_type, value_type, size) = try!(self.protocol.read_map_begin(reader));
+                self.pending.push(Pending::Entries(key_type, value_type, size.saturating_mul(2)));
+            }
+            Some(Pending::Value(value_type)) => try!(self.protocol.skip(reader, value_type)),
+            Some(Pending::Fields) => {
+                let (_, field_type, _) = try!(self.protocol.read_field_begin(reader));
+                if field_type == Type::Stop {
+                    try!(self.protocol.read_struct_end(reader));
+                } else {
+                    self.pending.push(Pending::Fields);
+                    self.pending.push(Pending::FieldEnd);
+                    self.pending.push(Pending::Value(field_type));
+                }
+            }
+            Some(Pending::FieldEnd) => try!(self.protocol.read_field_end(reader)),
+            Some(Pending::Elements(Type::Set, _, 0)) => try!(self.protocol.read_set_end(reader)),
+            Some(Pending::Elements(_, _, 0)) => try!(self.protocol.read_list_end(reader)),
+            Some(Pending::Elements(container_type, elem_type, left)) => {
+                self.pending.push(Pending::Elements(container_type, elem_type, left - 1));
+                self.pending.push(Pending::Value(elem_type));
+            }
+            Some(Pending::Entries(_, _, 0)) => try!(self.protocol.read_map_end(reader)),
+            Some(Pending::Entries(key_type, value_type, left)) => {
+                self.pending.push(Pending::Entries(key_type, value_type, left - 1));
+                self.pending.push(Pending::Value(if left % 2 == 0 { key_type } else { value_type }));
+            }
+            Some(Pending::MessageEnd) => try!(self.protocol.read_message_end(reader)),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Response received so far, running out of bytes is reported as `UnexpectedEof`
+/// so that the scan waits for more instead of failing
+struct ResponseReader {
+    input: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ResponseReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.input.len() && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "response incomplete"));
+        }
+        let read = (&self.input[self.position..]).read(buf).unwrap_or(0);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+impl Write for ResponseReader {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("responses are read only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::future::Future;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll, Waker};
+
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::{AsyncFbossClient, ResponseReader, ResponseScanner};
+    use api::{SwitchRunState, send_request_without_args, handle_response_for_switch_run_state};
+    use protocol::{MessageType, Protocol, Type, Value, write_value};
+    use protocol::binary_protocol::BinaryProtocol;
+    use transport::memory_transport::MemoryTransport;
+    use {Error, Result};
+
+    /// Stream handing out its input `chunk` bytes at a time, with a pending poll in between
+    struct ChunkedStream {
+        input: Vec<u8>,
+        position: usize,
+        chunk: usize,
+        ready: bool,
+    }
+
+    impl ChunkedStream {
+        fn new(input: Vec<u8>, chunk: usize) -> ChunkedStream {
+            ChunkedStream {
+                input: input,
+                position: 0,
+                chunk: chunk,
+                ready: false,
+            }
+        }
+    }
+
+    impl AsyncRead for ChunkedStream {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+            if !self.ready {
+                self.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.ready = false;
+            let end = ::std::cmp::min(self.position + self.chunk, self.input.len());
+            let end = ::std::cmp::min(end, self.position + buf.remaining());
+            buf.put_slice(&self.input[self.position..end]);
+            self.position = end;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for ChunkedStream {
+        fn poll_write(self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    fn reply(method: &str, sequence_id: i32, success: &Value) -> Vec<u8> {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, method, MessageType::Reply, sequence_id).unwrap();
+        let mut result = BTreeMap::new();
+        result.insert(0, success.clone());
+        write_value(&mut protocol, &mut transport, &Value::Struct(result)).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+        transport.take_written()
+    }
+
+    fn route(prefix: [u8; 4], length: i16, next_hops: Vec<[u8; 4]>) -> Value {
+        let address = |bytes: &[u8]| {
+            let mut fields = BTreeMap::new();
+            fields.insert(1, Value::Binary(bytes.to_vec()));
+            Value::Struct(fields)
+        };
+        let mut ip_prefix = BTreeMap::new();
+        ip_prefix.insert(1, address(&prefix));
+        ip_prefix.insert(2, Value::I16(length));
+        let mut fields = BTreeMap::new();
+        fields.insert(1, Value::Struct(ip_prefix));
+        fields.insert(2, Value::List(Type::Struct, next_hops.iter().map(|hop| address(hop)).collect()));
+        Value::Struct(fields)
+    }
+
+    fn route_table(routes: usize) -> Value {
+        Value::List(Type::Struct,
+                    (0..routes)
+                        .map(|n| route([10, (n / 256) as u8, (n % 256) as u8, 0], 24, vec![[10, 0, 0, 1]]))
+                        .collect())
+    }
+
+    #[test]
+    fn decodes_response_arriving_byte_by_byte() {
+        let input = reply("getRouteTable", 1, &route_table(3));
+        let mut client = AsyncFbossClient::new(ChunkedStream::new(input, 1));
+
+        let routes = block_on(client.get_routes()).unwrap();
+        assert_eq!(routes.len(), 3);
+        assert_eq!((&routes[2].from[..], &routes[2].to[..]), ("10.0.2.0/24", "10.0.0.1"));
+    }
+
+    static DECODES: AtomicUsize = AtomicUsize::new(0);
+
+    fn counting_decoder(protocol: &mut BinaryProtocol, reader: &mut ResponseReader, request_number: i32)
+        -> Result<SwitchRunState> {
+        DECODES.fetch_add(1, Ordering::SeqCst);
+        handle_response_for_switch_run_state(protocol, reader, request_number)
+    }
+
+    #[test]
+    fn decodes_response_once() {
+        let input = reply("getSwitchRunState", 1, &Value::I32(3));
+        let mut client = AsyncFbossClient::new(ChunkedStream::new(input, 1));
+
+        let call = client.call(|protocol, transport, request_number| {
+                                   send_request_without_args(protocol, transport, request_number, "getSwitchRunState")
+                               },
+                               counting_decoder);
+        assert_eq!(block_on(call).unwrap(), SwitchRunState::FibSynced);
+        assert_eq!(DECODES.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn keeps_bytes_of_next_response() {
+        let mut input = reply("getSwitchRunState", 1, &Value::I32(2));
+        input.extend(reply("getRouteTable", 2, &route_table(1)));
+        let mut client = AsyncFbossClient::new(ChunkedStream::new(input, 4096));
+
+        assert_eq!(block_on(client.get_switch_run_state()).unwrap(), SwitchRunState::Configured);
+        assert_eq!(block_on(client.get_routes()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn scan_resumes_where_input_ran_out() {
+        let response = reply("getRouteTable", 1, &route_table(300));
+        let mut scanner = ResponseScanner::new(BinaryProtocol::new());
+        let mut input = Vec::new();
+        let mut last_position = 0;
+
+        for chunk in response.chunks(7) {
+            input.extend_from_slice(chunk);
+            let end = scanner.scan(&mut input).unwrap();
+            assert!(scanner.position >= last_position);
+            // only a value cut off by the end of the input is left, none is longer than the
+            // 25 byte message header
+            assert!(input.len() - scanner.position < 25);
+            last_position = scanner.position;
+            if input.len() < response.len() {
+                assert_eq!(end, None);
+            } else {
+                assert_eq!(end, Some(response.len()));
+            }
+        }
+    }
+
+    #[test]
+    fn fails_on_malformed_response() {
+        let mut input = reply("getSwitchRunState", 1, &Value::I32(2));
+        input[0] = 0x7f;
+        let mut client = AsyncFbossClient::new(ChunkedStream::new(input, 4096));
+
+        match block_on(client.get_switch_run_state()) {
+            Err(Error::ProtocolError(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
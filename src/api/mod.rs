@@ -20,13 +20,31 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use std::net;
-
-use {Protocol, Transport};
-use protocol::{MessageType, Type};
+use {Protocol, Transport, Result, Error};
+use transport::BufferedTransport;
+use transport::socket_transport::{SocketTransport, Timeouts};
+use self::pipeline::Pipeline;
+use self::retry::RetryPolicy;
+#[cfg(feature = "tls")]
+use transport::tls_transport::{self, TlsConfig, TlsTransport};
+use protocol::{self, MessageType, Type, FromNum, Value, read_value, write_value};
+use protocol::helpers::{self, read_enum};
 use protocol::binary_protocol::BinaryProtocol;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod packet;
+pub mod pipeline;
+pub mod pool;
+pub mod retry;
 
 pub struct PortStat {
     pub id: i32,
@@ -38,341 +56,947 @@ pub struct Route {
     pub to: String,
 }
 
-pub fn get_ports_stats(connect_string: &str) -> Vec<PortStat> {
-    let mut transport = net::TcpStream::connect(connect_string).unwrap();
-    let mut protocol = BinaryProtocol;
-
-    let request_number = 99;
+#[derive(Clone, Default, Debug)]
+pub struct AclEntry {
+    pub priority: i32,
+    pub name: String,
+    pub src_ip: Option<String>,
+    pub dst_ip: Option<String>,
+    pub proto: Option<i8>,
+    pub l4_src_port: Option<i16>,
+    pub l4_dst_port: Option<i16>,
+    pub action: String,
+}
 
-    send_request_for_all_ports_stats(&mut protocol, &mut transport, request_number);
-    let result = handle_response_for_all_ports_stats(&mut protocol, &mut transport, request_number);
-    result
+#[derive(Clone, Debug, PartialEq)]
+pub enum MplsAction {
+    Push(Vec<i32>),
+    Swap(i32),
+    Php,
+    PopAndLookup,
+    Noop,
 }
 
-pub fn get_routes(connect_string: &str) -> Vec<Route> {
-    let mut transport = net::TcpStream::connect(connect_string).unwrap();
-    let mut protocol = BinaryProtocol;
+#[derive(Clone, Debug, PartialEq)]
+pub struct MplsNextHop {
+    pub address: String,
+    pub weight: i32,
+    pub action: Option<MplsAction>,
+}
 
-    let request_number = 100;
+#[derive(Clone, Debug, PartialEq)]
+pub struct MplsRoute {
+    pub top_label: i32,
+    pub next_hops: Vec<MplsNextHop>,
+}
 
-    send_request_for_get_routes(&mut protocol, &mut transport, request_number);
-    let result = handle_response_for_get_routes(&mut protocol, &mut transport, request_number);
-    result
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CaptureDirection {
+    OnlyRx = 0,
+    OnlyTx = 1,
+    TxRx = 2,
 }
 
-pub fn sync_routes(connect_string: &str) {
-    let mut transport = net::TcpStream::connect(connect_string).unwrap();
-    let mut protocol = BinaryProtocol;
+impl FromNum for CaptureDirection {
+    fn from_num(num: i32) -> Option<CaptureDirection> {
+        match num {
+            0 => Some(CaptureDirection::OnlyRx),
+            1 => Some(CaptureDirection::OnlyTx),
+            2 => Some(CaptureDirection::TxRx),
+            _ => None,
+        }
+    }
+}
 
-    let request_number = 101;
+/// Restricts a capture to packets received on the given CPU cos queues
+#[derive(Clone, Default, Debug)]
+pub struct CaptureFilter {
+    pub cos_queues: Vec<i32>,
+}
 
-    send_request_for_sync_fib(&mut protocol, &mut transport, request_number);
-    handle_default_response(&mut protocol, &mut transport, request_number);
+/// Packet capture as started on the agent, the agent writes it to `<name>.pcap`
+#[derive(Clone, Debug)]
+pub struct CaptureInfo {
+    pub name: String,
+    pub max_packets: i32,
+    pub direction: CaptureDirection,
+    pub filter: Option<CaptureFilter>,
 }
 
-pub fn add_route(connect_string: &str, route_from: &str, route_to: &str) {
-    let mut transport = net::TcpStream::connect(connect_string).unwrap();
-    let mut protocol = BinaryProtocol;
+/// Run state of the agent, ordered by boot progress, so that
+/// `state >= SwitchRunState::Configured` can be used to wait for a booting agent.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum SwitchRunState {
+    Uninitialized = 0,
+    Initialized = 1,
+    Configured = 2,
+    FibSynced = 3,
+    Exiting = 4,
+}
 
-    let request_number = 102;
+impl FromNum for SwitchRunState {
+    fn from_num(num: i32) -> Option<SwitchRunState> {
+        match num {
+            0 => Some(SwitchRunState::Uninitialized),
+            1 => Some(SwitchRunState::Initialized),
+            2 => Some(SwitchRunState::Configured),
+            3 => Some(SwitchRunState::FibSynced),
+            4 => Some(SwitchRunState::Exiting),
+            _ => None,
+        }
+    }
+}
 
-    send_request_for_add_route(&mut protocol,
-                               &mut transport,
-                               request_number,
-                               route_from,
-                               route_to);
-    handle_default_response(&mut protocol, &mut transport, request_number);
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BootType {
+    Uninitialized = 0,
+    ColdBoot = 1,
+    WarmBoot = 2,
 }
 
-pub fn delete_route(connect_string: &str, route_from: &str) {
-    let mut transport = net::TcpStream::connect(connect_string).unwrap();
-    let mut protocol = BinaryProtocol;
+impl FromNum for BootType {
+    fn from_num(num: i32) -> Option<BootType> {
+        match num {
+            0 => Some(BootType::Uninitialized),
+            1 => Some(BootType::ColdBoot),
+            2 => Some(BootType::WarmBoot),
+            _ => None,
+        }
+    }
+}
 
-    let request_number = 103;
+/// Product info as reported by the platform, `product` names the platform (e.g. "Wedge100").
+#[derive(Clone, Default, Debug)]
+pub struct ProductInfo {
+    pub oem: String,
+    pub product: String,
+    pub serial: String,
+    pub mac: String,
+    pub mgmt_mac: String,
+    pub bmc_mac: String,
+    pub mac_range_size: i64,
+    pub part_number: String,
+    pub product_version: String,
+}
 
-    send_request_for_delete_route(&mut protocol, &mut transport, request_number, route_from);
-    println!("sent, waiting for response");
-    handle_default_response(&mut protocol, &mut transport, request_number);
+/// Declared exception thrown by a method called with `FbossClient::call`, `id` is the field id
+/// of the exception in the method's `throws` clause.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallException {
+    pub id: i16,
+    pub value: Value,
 }
 
+impl fmt::Display for CallException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exception {}: {}", self.id, self.value)
+    }
+}
 
+impl StdError for CallException {}
 
-fn send_request_for_all_ports_stats<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
-    where P: Protocol,
-          T: Transport
+/// Client for the FbossCtrl service of the agent.
+///
+/// `connect` opens a buffered socket connection, `new` and `with_protocol` run the client
+/// over any caller-supplied transport, e.g. a framed or header transport.
+pub struct FbossClient<T = BufferedTransport<SocketTransport>, P = BinaryProtocol>
+    where T: Transport,
+          P: Protocol
 {
+    transport: T,
+    protocol: P,
+    request_number: i32,
+    call_timeout: Option<Duration>,
+    set_deadline: Option<fn(&mut T, Option<Instant>)>,
+    reconnect: Option<Box<dyn Fn() -> Result<T> + Send>>,
+    retry_policy: RetryPolicy,
+    broken: bool,
+}
 
-    protocol.write_message_begin(transport,
-                                 "getAllPortStats",
-                                 MessageType::Call,
-                                 request_number)
-            .unwrap();
-    protocol.write_struct_begin(transport, "getAllPortStats_args").unwrap();
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_message_end(transport).unwrap();
+impl FbossClient {
+    /// Connects to `host:port`, `[ipv6%zone]:port` or `unix:/path/to/socket`
+    pub fn connect(connect_string: &str) -> Result<FbossClient> {
+        FbossClient::connect_with_timeouts(connect_string, &Timeouts::new())
+    }
+
+    /// Connects like `connect`, calls fail with `Error::Timeout` once a timeout expires
+    pub fn connect_with_timeouts(connect_string: &str, timeouts: &Timeouts) -> Result<FbossClient> {
+        let socket = try!(SocketTransport::connect_with_timeouts(connect_string, timeouts));
+        let mut client = FbossClient::new(BufferedTransport::new(socket));
+        client.call_timeout = timeouts.call;
+        client.set_deadline = Some(|transport, deadline| transport.get_mut().set_deadline(deadline));
+
+        let connect_string = connect_string.to_string();
+        let timeouts = *timeouts;
+        client.set_reconnect(move || {
+            let socket = try!(SocketTransport::connect_with_timeouts(&connect_string, &timeouts));
+            Ok(BufferedTransport::new(socket))
+        });
+        Ok(client)
+    }
 }
 
-fn handle_response_for_all_ports_stats<P, T>(protocol: &mut P,
-                                             transport: &mut T,
-                                             _request_number: i32)
-                                             -> Vec<PortStat>
-    where P: Protocol,
-          T: Transport
-{
+#[cfg(feature = "tls")]
+impl FbossClient<BufferedTransport<TlsTransport<SocketTransport>>> {
+    /// Connects like `connect` and runs the thrift connection over TLS,
+    /// unix sockets need the server name to be set in `config`
+    pub fn connect_tls(connect_string: &str,
+                       config: &TlsConfig)
+                       -> Result<FbossClient<BufferedTransport<TlsTransport<SocketTransport>>>> {
+        FbossClient::connect_tls_with_timeouts(connect_string, config, &Timeouts::new())
+    }
+
+    /// Connects like `connect_tls`, the handshake counts against the connect timeout
+    pub fn connect_tls_with_timeouts(connect_string: &str,
+                                     config: &TlsConfig,
+                                     timeouts: &Timeouts)
+                                     -> Result<FbossClient<BufferedTransport<TlsTransport<SocketTransport>>>> {
+        let server_name = match config.server_name().or_else(|| tls_transport::server_name(connect_string)) {
+            Some(server_name) => server_name.to_string(),
+            None => {
+                return Err(Error::InvalidArgument(format!("no TLS server name for {}", connect_string)))
+            }
+        };
+
+        let transport = try!(connect_tls_transport(connect_string, &server_name, config, timeouts));
+        let mut client = FbossClient::new(transport);
+        client.call_timeout = timeouts.call;
+        client.set_deadline = Some(|transport, deadline| transport.get_mut().get_mut().set_deadline(deadline));
+
+        let connect_string = connect_string.to_string();
+        let config = config.clone();
+        let timeouts = *timeouts;
+        client.set_reconnect(move || connect_tls_transport(&connect_string, &server_name, &config, &timeouts));
+        Ok(client)
+    }
+}
+
+#[cfg(feature = "tls")]
+fn connect_tls_transport(connect_string: &str,
+                         server_name: &str,
+                         config: &TlsConfig,
+                         timeouts: &Timeouts)
+                         -> Result<BufferedTransport<TlsTransport<SocketTransport>>> {
+    let mut socket = try!(SocketTransport::connect_with_timeouts(connect_string, timeouts));
+    socket.set_deadline(timeouts.connect.map(|timeout| Instant::now() + timeout));
+    let mut tls = try!(TlsTransport::connect(config, server_name, socket));
+    tls.get_mut().set_deadline(None);
+    Ok(BufferedTransport::new(tls))
+}
 
-    let mut result: Vec<PortStat> = vec![];
+impl<T: Transport> FbossClient<T> {
+    pub fn new(transport: T) -> FbossClient<T> {
+        FbossClient::with_protocol(transport, BinaryProtocol::new())
+    }
+}
 
-    let response = protocol.read_message_begin(transport).unwrap();
-    match response {
-        (name, MessageType::Reply, _request_number) => println!("-- Response for {}", name),
-        (_, _, _) => {
-            println!("wrong response");
-            return result;
+impl<T: Transport, P: Protocol> FbossClient<T, P> {
+    pub fn with_protocol(transport: T, protocol: P) -> FbossClient<T, P> {
+        FbossClient {
+            transport: transport,
+            protocol: protocol,
+            request_number: 0,
+            call_timeout: None,
+            set_deadline: None,
+            reconnect: None,
+            retry_policy: RetryPolicy::new(),
+            broken: false,
         }
     }
 
-    protocol.read_struct_begin(transport).unwrap();
-    loop {
-        let field = protocol.read_field_begin(transport).unwrap();
-        match field {
-            (_, Type::Stop, _) => break,
-            (_, _, 0) => {
-                let (_, _, map_size) = protocol.read_map_begin(transport).unwrap();
-                for _ in 0..map_size {
-                    result.push(read_ports_stat_field(protocol, transport));
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    /// Changes the deadline of each call, only clients created by the `connect` functions
+    /// can bound their calls in time
+    pub fn set_call_timeout(&mut self, timeout: Option<Duration>) {
+        self.call_timeout = timeout;
+    }
+
+    pub fn call_timeout(&self) -> Option<Duration> {
+        self.call_timeout
+    }
+
+    /// Sets how a broken connection is replaced, clients created by the `connect` functions
+    /// reconnect to the same address
+    pub fn set_reconnect<F>(&mut self, reconnect: F)
+        where F: Fn() -> Result<T> + Send + 'static
+    {
+        self.reconnect = Some(Box::new(reconnect));
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Whether the last call left the connection unusable, the next call reconnects
+    /// if the client knows how to and fails right away otherwise
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Keeps several calls in flight on this connection, see `Pipeline`
+    pub fn pipeline(&mut self) -> Pipeline<'_, T, P> {
+        Pipeline::new(self)
+    }
+
+    /// Replaces the connection, e.g. after the agent restarted
+    pub fn reconnect(&mut self) -> Result<()> {
+        let transport = match self.reconnect {
+            Some(ref reconnect) => try!(reconnect()),
+            None => return Err(Error::InvalidArgument("client has no way to reconnect".to_string())),
+        };
+        self.transport = transport;
+        self.broken = false;
+        Ok(())
+    }
+
+    pub fn get_ports_stats(&mut self) -> Result<Vec<PortStat>> {
+        self.call_idempotent(send_request_for_all_ports_stats, handle_response_for_all_ports_stats)
+    }
+
+    pub fn get_routes(&mut self) -> Result<Vec<Route>> {
+        self.call_idempotent(send_request_for_get_routes, handle_response_for_get_routes)
+    }
+
+    pub fn sync_routes(&mut self) -> Result<()> {
+        self.call_mutating(send_request_for_sync_fib, handle_default_response)
+    }
+
+    pub fn add_route(&mut self, route_from: &str, route_to: &str) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_add_route(protocol, transport, request_number, route_from, route_to)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn delete_route(&mut self, route_from: &str) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_delete_route(protocol, transport, request_number, route_from)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn get_running_config(&mut self) -> Result<String> {
+        self.call_idempotent(send_request_for_get_running_config, handle_response_for_get_running_config)
+    }
+
+    pub fn reload_config(&mut self) -> Result<()> {
+        self.call_mutating(send_request_for_reload_config, handle_default_response)
+    }
+
+    pub fn get_switch_run_state(&mut self) -> Result<SwitchRunState> {
+        self.call_idempotent(|protocol, transport, request_number| {
+                                 send_request_without_args(protocol, transport, request_number, "getSwitchRunState")
+                             },
+                             handle_response_for_switch_run_state)
+    }
+
+    pub fn get_boot_type(&mut self) -> Result<BootType> {
+        self.call_idempotent(|protocol, transport, request_number| {
+                                 send_request_without_args(protocol, transport, request_number, "getBootType")
+                             },
+                             handle_response_for_boot_type)
+    }
+
+    pub fn get_product_info(&mut self) -> Result<ProductInfo> {
+        self.call_idempotent(|protocol, transport, request_number| {
+                                 send_request_without_args(protocol, transport, request_number, "getProductInfo")
+                             },
+                             handle_response_for_product_info)
+    }
+
+    pub fn get_acl_table(&mut self) -> Result<Vec<AclEntry>> {
+        self.call_idempotent(|protocol, transport, request_number| {
+                                 send_request_without_args(protocol, transport, request_number, "getAclTable")
+                             },
+                             handle_response_for_get_acl_table)
+    }
+
+    pub fn get_mpls_route_table(&mut self) -> Result<Vec<MplsRoute>> {
+        self.call_idempotent(|protocol, transport, request_number| {
+                                 send_request_without_args(protocol, transport, request_number, "getMplsRouteTable")
+                             },
+                             handle_response_for_get_mpls_route_table)
+    }
+
+    pub fn add_mpls_routes(&mut self, routes: &[MplsRoute]) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_mpls_routes(protocol, transport, request_number, "addMplsRoutes", routes)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn delete_mpls_routes(&mut self, top_labels: &[i32]) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_delete_mpls_routes(protocol, transport, request_number, top_labels)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn sync_mpls_fib(&mut self, routes: &[MplsRoute]) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_mpls_routes(protocol, transport, request_number, "syncMplsFib", routes)
+                           },
+                           handle_default_response)
+    }
+
+    /// Sends a complete ethernet frame out of the given port
+    pub fn tx_pkt(&mut self, port: i32, data: &[u8]) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_tx_pkt(protocol, transport, request_number, Some(port), data)
+                           },
+                           handle_default_response)
+    }
+
+    /// Sends a complete ethernet frame, switched by the ASIC
+    pub fn tx_pkt_l2(&mut self, data: &[u8]) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_tx_pkt(protocol, transport, request_number, None, data)
+                           },
+                           handle_default_response)
+    }
+
+    /// Sends an IP packet, routed by the ASIC
+    pub fn tx_pkt_l3(&mut self, payload: &[u8]) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_tx_pkt_l3(protocol, transport, request_number, payload)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn start_pkt_capture(&mut self,
+                             name: &str,
+                             max_packets: i32,
+                             direction: CaptureDirection,
+                             filter: Option<CaptureFilter>)
+                             -> Result<()> {
+        let capture_info = CaptureInfo {
+            name: name.to_string(),
+            max_packets: max_packets,
+            direction: direction,
+            filter: filter,
+        };
+
+        self.start_pkt_capture_with_info(&capture_info)
+    }
+
+    pub fn start_pkt_capture_with_info(&mut self, capture_info: &CaptureInfo) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_start_pkt_capture(protocol, transport, request_number, capture_info)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn stop_pkt_capture(&mut self, name: &str) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_for_stop_pkt_capture(protocol, transport, request_number, name)
+                           },
+                           handle_default_response)
+    }
+
+    pub fn stop_all_pkt_captures(&mut self) -> Result<()> {
+        self.call_mutating(|protocol, transport, request_number| {
+                               send_request_without_args(protocol, transport, request_number, "stopAllPktCaptures")
+                           },
+                           handle_default_response)
+    }
+
+    /// Captures currently running on the agent
+    pub fn get_pkt_captures(&mut self) -> Result<Vec<CaptureInfo>> {
+        self.call_idempotent(|protocol, transport, request_number| {
+                                 send_request_without_args(protocol, transport, request_number, "getPktCaptures")
+                             },
+                             handle_response_for_get_pkt_captures)
+    }
+
+    /// Calls any method of the agent, e.g. one this crate has no wrapper for yet
+    ///
+    /// `args` is the `Value::Struct` of the method's arguments by field id. The returned value
+    /// is the method's return value, an empty `Value::Struct` for void methods. A declared
+    /// exception fails with `Error::Exception` holding a `CallException`, a method the agent
+    /// doesn't know with `Error::ApplicationException`. The call is not retried unless the retry
+    /// policy retries mutating calls.
+    pub fn call(&mut self, method_name: &str, args: Value) -> Result<Value> {
+        if args.typ() != Type::Struct {
+            return Err(Error::InvalidArgument("arguments of a call must be a struct".to_string()));
+        }
+        self.call_mutating(|protocol, transport, request_number| {
+                               helpers::send_message(protocol, transport, method_name, MessageType::Call,
+                                                     request_number, &CallArgs(&args))
+                           },
+                           handle_response_for_call)
+    }
+
+    fn call_mutating<R, S, H>(&mut self, send_request: S, handle_response: H) -> Result<R>
+        where S: Fn(&mut P, &mut T, i32) -> Result<()>,
+              H: Fn(&mut P, &mut T, i32) -> Result<R>
+    {
+        let retries = self.retry_policy.retries_for(false);
+        self.call_with_retries(retries, send_request, handle_response)
+    }
+
+    fn call_idempotent<R, S, H>(&mut self, send_request: S, handle_response: H) -> Result<R>
+        where S: Fn(&mut P, &mut T, i32) -> Result<()>,
+              H: Fn(&mut P, &mut T, i32) -> Result<R>
+    {
+        let retries = self.retry_policy.retries_for(true);
+        self.call_with_retries(retries, send_request, handle_response)
+    }
+
+    fn call_with_retries<R, S, H>(&mut self, retries: u32, send_request: S, handle_response: H) -> Result<R>
+        where S: Fn(&mut P, &mut T, i32) -> Result<()>,
+              H: Fn(&mut P, &mut T, i32) -> Result<R>
+    {
+        if self.broken && self.reconnect.is_none() {
+            return Err(broken_connection());
+        }
+
+        let mut attempt = 0;
+        loop {
+            let result = if self.broken {
+                self.reconnect().and_then(|_| self.call_once(&send_request, &handle_response))
+            } else {
+                self.call_once(&send_request, &handle_response)
+            };
+
+            match result {
+                Err(ref err) if retry::is_connection_error(err) => {
+                    self.broken = true;
+                    if self.reconnect.is_none() || !retry::is_retryable(err) || attempt >= retries {
+                        return result;
+                    }
+                    debug!("Retrying call after {:?}", err);
                 }
+                _ => return result,
             }
-            (_, field_type, _) => protocol.skip(transport, field_type).unwrap(),
+
+            thread::sleep(self.retry_policy.backoff(attempt));
+            attempt += 1;
         }
-        protocol.read_field_end(transport).unwrap();
     }
-    protocol.read_struct_end(transport).unwrap();
 
-    result
-}
+    fn call_once<R, S, H>(&mut self, send_request: &S, handle_response: &H) -> Result<R>
+        where S: Fn(&mut P, &mut T, i32) -> Result<()>,
+              H: Fn(&mut P, &mut T, i32) -> Result<R>
+    {
+        let request_number = self.next_request_number();
 
+        self.start_deadline();
+        let result = send_request(&mut self.protocol, &mut self.transport, request_number)
+            .and_then(|_| handle_response(&mut self.protocol, &mut self.transport, request_number));
+        self.clear_deadline();
 
-fn read_ports_stat_field<P, T>(protocol: &mut P, transport: &mut T) -> PortStat
-    where P: Protocol,
-          T: Transport
-{
+        result
+    }
 
-    let key = protocol.read_i32(transport).unwrap();
-    let mut connected = false;
+    fn next_request_number(&mut self) -> i32 {
+        self.request_number = self.request_number.wrapping_add(1);
+        self.request_number
+    }
 
-    protocol.read_struct_begin(transport).unwrap();
-    loop {
-        let port_stat_field = protocol.read_field_begin(transport).unwrap();
-        match port_stat_field {
-            (_, Type::Stop, _) => break,
-            (_, _, 4) => {
-                let oper_state = protocol.read_i32(transport).unwrap();
-                connected = if oper_state == 1 {
-                    true
-                } else {
-                    false
-                };
-            }
-            (_, port_stat_field_type, _) => protocol.skip(transport, port_stat_field_type).unwrap(),
+    /// Bounds the following reads and writes by the call timeout
+    fn start_deadline(&mut self) {
+        if let (Some(set_deadline), Some(call_timeout)) = (self.set_deadline, self.call_timeout) {
+            set_deadline(&mut self.transport, Some(Instant::now() + call_timeout));
         }
-        protocol.read_field_end(transport).unwrap();
     }
-    protocol.read_struct_end(transport).unwrap();
 
-    PortStat {
-        id: key,
-        connected: connected,
+    fn clear_deadline(&mut self) {
+        if let (Some(set_deadline), Some(_)) = (self.set_deadline, self.call_timeout) {
+            set_deadline(&mut self.transport, None);
+        }
     }
 }
 
 
+/// Error of a call on a broken connection the client can't replace
+fn broken_connection() -> Error {
+    Error::TransportError(io::Error::new(io::ErrorKind::NotConnected,
+                                         "connection is broken and the client can't reconnect"))
+}
+
 
-fn send_request_for_get_routes<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+pub fn get_ports_stats(connect_string: &str) -> Vec<PortStat> {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_ports_stats()).unwrap()
+}
+
+pub fn get_routes(connect_string: &str) -> Vec<Route> {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_routes()).unwrap()
+}
+
+pub fn sync_routes(connect_string: &str) {
+    FbossClient::connect(connect_string).and_then(|mut client| client.sync_routes()).unwrap()
+}
+
+pub fn add_route(connect_string: &str, route_from: &str, route_to: &str) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.add_route(route_from, route_to))
+        .unwrap()
+}
+
+pub fn delete_route(connect_string: &str, route_from: &str) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.delete_route(route_from))
+        .unwrap()
+}
+
+pub fn get_running_config(connect_string: &str) -> String {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_running_config()).unwrap()
+}
+
+pub fn reload_config(connect_string: &str) {
+    FbossClient::connect(connect_string).and_then(|mut client| client.reload_config()).unwrap()
+}
+
+pub fn get_switch_run_state(connect_string: &str) -> SwitchRunState {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_switch_run_state()).unwrap()
+}
+
+pub fn get_boot_type(connect_string: &str) -> BootType {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_boot_type()).unwrap()
+}
+
+pub fn get_product_info(connect_string: &str) -> ProductInfo {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_product_info()).unwrap()
+}
+
+pub fn get_acl_table(connect_string: &str) -> Vec<AclEntry> {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_acl_table()).unwrap()
+}
+
+pub fn get_mpls_route_table(connect_string: &str) -> Vec<MplsRoute> {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_mpls_route_table()).unwrap()
+}
+
+pub fn add_mpls_routes(connect_string: &str, routes: &[MplsRoute]) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.add_mpls_routes(routes))
+        .unwrap()
+}
+
+pub fn delete_mpls_routes(connect_string: &str, top_labels: &[i32]) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.delete_mpls_routes(top_labels))
+        .unwrap()
+}
+
+pub fn sync_mpls_fib(connect_string: &str, routes: &[MplsRoute]) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.sync_mpls_fib(routes))
+        .unwrap()
+}
+
+/// Sends a complete ethernet frame out of the given port
+pub fn tx_pkt(connect_string: &str, port: i32, data: &[u8]) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.tx_pkt(port, data))
+        .unwrap()
+}
+
+/// Sends a complete ethernet frame, switched by the ASIC
+pub fn tx_pkt_l2(connect_string: &str, data: &[u8]) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.tx_pkt_l2(data))
+        .unwrap()
+}
+
+/// Sends an IP packet, routed by the ASIC
+pub fn tx_pkt_l3(connect_string: &str, payload: &[u8]) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.tx_pkt_l3(payload))
+        .unwrap()
+}
+
+pub fn start_pkt_capture(connect_string: &str,
+                         name: &str,
+                         max_packets: i32,
+                         direction: CaptureDirection,
+                         filter: Option<CaptureFilter>) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.start_pkt_capture(name, max_packets, direction, filter))
+        .unwrap()
+}
+
+pub fn start_pkt_capture_with_info(connect_string: &str, capture_info: &CaptureInfo) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.start_pkt_capture_with_info(capture_info))
+        .unwrap()
+}
+
+pub fn stop_pkt_capture(connect_string: &str, name: &str) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.stop_pkt_capture(name))
+        .unwrap()
+}
+
+pub fn stop_all_pkt_captures(connect_string: &str) {
+    FbossClient::connect(connect_string)
+        .and_then(|mut client| client.stop_all_pkt_captures())
+        .unwrap()
+}
+
+pub fn get_pkt_captures(connect_string: &str) -> Vec<CaptureInfo> {
+    FbossClient::connect(connect_string).and_then(|mut client| client.get_pkt_captures()).unwrap()
+}
+
+
+
+fn send_request_for_all_ports_stats<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
 
-    protocol.write_message_begin(transport,
-                                 "getRouteTable",
-                                 MessageType::Call,
-                                 request_number)
-            .unwrap();
-    protocol.write_struct_begin(transport, "getRouteTable_args").unwrap();
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_message_end(transport).unwrap();
+    try!(protocol.write_message_begin(transport,
+                                      "getAllPortStats",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "getAllPortStats_args"));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
 }
 
-fn handle_response_for_get_routes<P, T>(protocol: &mut P,
-                                        transport: &mut T,
-                                        _request_number: i32)
-                                        -> Vec<Route>
+fn handle_response_for_all_ports_stats<P, T>(protocol: &mut P,
+                                             transport: &mut T,
+                                             request_number: i32)
+                                             -> Result<Vec<PortStat>>
     where P: Protocol,
           T: Transport
 {
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, _| {
+        helpers::read::<BTreeMap<i32, PortOperState>, _, _>(protocol, transport)
+    }));
+
+    Ok(result.map(|ports| {
+            ports.into_iter()
+                .map(|(id, port)| {
+                    PortStat {
+                        id: id,
+                        connected: port.oper_state == 1,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or(vec![]))
+}
 
-    let mut result: Vec<Route> = vec![];
+/// The part of a port's `PortInfoThrift` that `PortStat` is made of
+#[derive(Default)]
+struct PortOperState {
+    oper_state: i32,
+}
 
-    let response = protocol.read_message_begin(transport).unwrap();
-    match response {
-        (name, MessageType::Reply, _request_number) => println!("-- Response for {}", name),
-        (_, _, _) => {
-            println!("wrong response");
-            return result;
-        }
+impl protocol::ThriftTyped for PortOperState {
+    fn typ() -> Type {
+        Type::Struct
     }
+}
 
-    protocol.read_struct_begin(transport).unwrap();
-    loop {
-        let field = protocol.read_field_begin(transport).unwrap();
-        match field {
-            (_, Type::Stop, _) => break,
-            (_, _, 0) => {
-                let (_, list_size) = protocol.read_list_begin(transport).unwrap();
-                for _ in 0..list_size {
-                    result.push(read_unicast_route(protocol, transport));
+impl protocol::Decode for PortOperState {
+    fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+        where P: Protocol,
+              T: Transport
+    {
+        helpers::read_struct(protocol, transport, |protocol, transport, field_id, field_type| {
+            match (field_id, field_type) {
+                (4, Type::I32) => {
+                    self.oper_state = try!(protocol.read_i32(transport));
+                    Ok(true)
                 }
-                protocol.read_list_end(transport).unwrap();
+                _ => Ok(false),
             }
-            (_, field_type, _) => protocol.skip(transport, field_type).unwrap(),
-        }
-        protocol.read_field_end(transport).unwrap();
+        })
     }
-    protocol.read_struct_end(transport).unwrap();
+}
+
+
+
+fn send_request_for_get_routes<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport,
+                                      "getRouteTable",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "getRouteTable_args"));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+fn handle_response_for_get_routes<P, T>(protocol: &mut P,
+                                        transport: &mut T,
+                                        request_number: i32)
+                                        -> Result<Vec<Route>>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, _| {
+        let mut result: Vec<Route> = vec![];
+        let (_, list_size) = try!(protocol.read_list_begin(transport));
+        for _ in 0..list_size {
+            result.push(try!(read_unicast_route(protocol, transport)));
+        }
+        try!(protocol.read_list_end(transport));
+        Ok(result)
+    }));
 
-    result
+    Ok(result.unwrap_or(vec![]))
 }
 
-fn read_unicast_route<P, T>(protocol: &mut P, transport: &mut T) -> Route
+fn read_unicast_route<P, T>(protocol: &mut P, transport: &mut T) -> Result<Route>
     where P: Protocol,
           T: Transport
 {
     let mut from: String = "undefined".to_string();
     let mut to: String = "undefined".to_string();
 
-    protocol.read_struct_begin(transport).unwrap();
+    try!(protocol.read_struct_begin(transport));
     loop {
-        let route_field = protocol.read_field_begin(transport).unwrap();
+        let route_field = try!(protocol.read_field_begin(transport));
         match route_field {
             (_, Type::Stop, _) => break,
             (_, _, 1) => {
-                from = read_ip_from(protocol, transport);
+                from = try!(read_ip_from(protocol, transport));
             }
             (_, _, 2) => {
-                to = read_ip_to(protocol, transport);
+                to = try!(read_ip_to(protocol, transport));
             }
-            (_, port_stat_field_type, _) => protocol.skip(transport, port_stat_field_type).unwrap(),
+            (_, port_stat_field_type, _) => try!(protocol.skip(transport, port_stat_field_type)),
         }
-        protocol.read_field_end(transport).unwrap();
+        try!(protocol.read_field_end(transport));
     }
-    protocol.read_struct_end(transport).unwrap();
+    try!(protocol.read_struct_end(transport));
 
-    Route {
+    Ok(Route {
         from: from.to_string(),
         to: to.to_string(),
-    }
+    })
 }
 
-fn read_ip_from<P, T>(protocol: &mut P, transport: &mut T) -> String
+fn read_ip_from<P, T>(protocol: &mut P, transport: &mut T) -> Result<String>
     where P: Protocol,
           T: Transport
 {
     let mut from: String = "undefined".to_string();
     let mut mask: i16 = 0;
 
-    protocol.read_struct_begin(transport).unwrap();
+    try!(protocol.read_struct_begin(transport));
     loop {
-        let route_from = protocol.read_field_begin(transport).unwrap();
+        let route_from = try!(protocol.read_field_begin(transport));
         match route_from {
             (_, Type::Stop, _) => break,
             (_, _, 1) => {
-                from = read_binary_address(protocol, transport);
+                from = try!(read_binary_address(protocol, transport));
             }
             (_, _, 2) => {
-                mask = protocol.read_i16(transport).unwrap();
+                mask = try!(protocol.read_i16(transport));
             }
-            (_, port_stat_field_type, _) => protocol.skip(transport, port_stat_field_type).unwrap(),
+            (_, port_stat_field_type, _) => try!(protocol.skip(transport, port_stat_field_type)),
         }
-        protocol.read_field_end(transport).unwrap();
+        try!(protocol.read_field_end(transport));
     }
-    protocol.read_struct_end(transport).unwrap();
+    try!(protocol.read_struct_end(transport));
 
-    format!("{}/{}", from, mask).to_string()
+    Ok(format!("{}/{}", from, mask).to_string())
 }
 
-fn read_ip_to<P, T>(protocol: &mut P, transport: &mut T) -> String
+fn read_ip_to<P, T>(protocol: &mut P, transport: &mut T) -> Result<String>
     where P: Protocol,
           T: Transport
 {
     let mut to_address: String = "".to_string();
 
-    let to_list = protocol.read_list_begin(transport).unwrap();
+    let to_list = try!(protocol.read_list_begin(transport));
     match to_list {
         (_, size) => {
             // we handle one next hop only
             for _ in 0..size {
-                to_address = read_binary_address(protocol, transport);
+                to_address = try!(read_binary_address(protocol, transport));
             }
         }
     }
 
-    protocol.read_list_end(transport).unwrap();
+    try!(protocol.read_list_end(transport));
 
-    to_address.to_string()
+    Ok(to_address.to_string())
 }
 
 
-fn read_binary_address<P, T>(protocol: &mut P, transport: &mut T) -> String
+fn read_binary_address<P, T>(protocol: &mut P, transport: &mut T) -> Result<String>
     where P: Protocol,
           T: Transport
 {
     let mut from_address: String = "undefined".to_string();
 
-    protocol.read_struct_begin(transport).unwrap();
+    try!(protocol.read_struct_begin(transport));
     loop {
-        let route_from = protocol.read_field_begin(transport).unwrap();
+        let route_from = try!(protocol.read_field_begin(transport));
         match route_from {
             (_, Type::Stop, _) => break,
             (_, _, 1) => {
-                let address = protocol.read_binary(transport).unwrap();
-
-                if address.len() == 4 {
-                    let ipv4 = Ipv4Addr::new(address[0], address[1], address[2], address[3]);
-                    from_address = format!("{:?}", ipv4);
-                } else {
-                    let ipv6 = Ipv6Addr::new(((address[0] as u16) << 8) + (address[1] as u16),
-                                             ((address[2] as u16) << 8) + (address[3] as u16),
-                                             ((address[4] as u16) << 8) + (address[5] as u16),
-                                             ((address[6] as u16) << 8) + (address[7] as u16),
-                                             ((address[9] as u16) << 8) + (address[8] as u16),
-                                             ((address[11] as u16) << 8) + (address[10] as u16),
-                                             ((address[13] as u16) << 8) + (address[12] as u16),
-                                             ((address[15] as u16) << 8) + (address[14] as u16));
-                    from_address = format!("{:?}", ipv6);
-                }
+                let address = try!(protocol.read_binary(transport));
+                from_address = try!(format_address(&address));
             }
-            (_, port_stat_field_type, _) => protocol.skip(transport, port_stat_field_type).unwrap(),
+            (_, port_stat_field_type, _) => try!(protocol.skip(transport, port_stat_field_type)),
         }
-        protocol.read_field_end(transport).unwrap();
+        try!(protocol.read_field_end(transport));
     }
-    protocol.read_struct_end(transport).unwrap();
+    try!(protocol.read_struct_end(transport));
 
-    from_address.to_string()
+    Ok(from_address.to_string())
+}
+
+
+/// Formats the bytes of a BinaryAddress, which are 4 bytes for IPv4 and 16 for IPv6
+fn format_address(address: &[u8]) -> Result<String> {
+    match address.len() {
+        4 => Ok(format!("{}", Ipv4Addr::new(address[0], address[1], address[2], address[3]))),
+        16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(address);
+            Ok(format!("{}", Ipv6Addr::from(octets)))
+        }
+        _ => Err(Error::from(protocol::Error::ProtocolViolation)),
+    }
 }
 
 
 fn send_request_for_sync_fib<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
 
-    protocol.write_message_begin(transport, "syncFib", MessageType::Call, request_number)
-            .unwrap();
-    protocol.write_struct_begin(transport, "syncFib_args").unwrap();
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_message_end(transport).unwrap();
+    try!(protocol.write_message_begin(transport, "syncFib", MessageType::Call, request_number));
+    try!(protocol.write_struct_begin(transport, "syncFib_args"));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
 }
 
 
@@ -381,66 +1005,68 @@ fn send_request_for_add_route<P, T>(protocol: &mut P,
                                     request_number: i32,
                                     route_from: &str,
                                     route_to: &str)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
-
-    protocol.write_message_begin(transport,
-                                 "addUnicastRoute",
-                                 MessageType::Call,
-                                 request_number)
-            .unwrap();
-    protocol.write_struct_begin(transport, "addUnicastRoute_args").unwrap();
-    handle_write_add_route_args(protocol, transport, route_from, route_to);
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_message_end(transport).unwrap();
+    let (addr_from, prefix_length) = try!(parse_ipv4_prefix(route_from));
+    let addr_to = try!(parse_ipv4_address(route_to));
+
+    try!(protocol.write_message_begin(transport,
+                                      "addUnicastRoute",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "addUnicastRoute_args"));
+    try!(handle_write_add_route_args(protocol, transport, &addr_from, prefix_length, &addr_to));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
 }
 
 fn handle_write_add_route_args<P, T>(protocol: &mut P,
                                      transport: &mut T,
-                                     route_from: &str,
-                                     route_to: &str)
+                                     addr_from: &Ipv4Addr,
+                                     prefix_length: i16,
+                                     addr_to: &Ipv4Addr)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
-    let route_elements: Vec<&str> = route_from.split("/").collect();
-    let addr_from = Ipv4Addr::from_str(route_elements[0]).unwrap();
-    let prefix_length = i16::from_str(route_elements[1]).unwrap();
-
-    let addr_to = Ipv4Addr::from_str(route_to).unwrap();
+    try!(protocol.write_field_begin(transport, "clientId", Type::I16, 1));
+    try!(protocol.write_i16(transport, 1));
+    try!(protocol.write_field_end(transport));
 
-    protocol.write_field_begin(transport, "clientId", Type::I16, 1).unwrap();
-    protocol.write_i16(transport, 1).unwrap();
-    protocol.write_field_end(transport).unwrap();
+    try!(protocol.write_field_begin(transport, "r", Type::Struct, 2));
+    try!(protocol.write_struct_begin(transport, "UnicastRoute"));
+    try!(protocol.write_field_begin(transport, "dest", Type::Struct, 1));
+    try!(protocol.write_struct_begin(transport, "IpPrefix"));
 
-    protocol.write_field_begin(transport, "r", Type::Struct, 2).unwrap();
-    protocol.write_struct_begin(transport, "UnicastRoute").unwrap();
-    protocol.write_field_begin(transport, "dest", Type::Struct, 1).unwrap();
-    protocol.write_struct_begin(transport, "IpPrefix").unwrap();
+    try!(protocol.write_field_begin(transport, "ip", Type::Struct, 1));
+    try!(handle_write_binary_address(protocol, transport, &IpAddr::V4(*addr_from)));
+    try!(protocol.write_field_end(transport));
 
-    protocol.write_field_begin(transport, "ip", Type::Struct, 1).unwrap();
-    handle_write_binary_address(protocol, transport, &addr_from);
-    protocol.write_field_end(transport).unwrap();
+    try!(protocol.write_field_begin(transport, "prefixLength", Type::I16, 2));
+    try!(protocol.write_i16(transport, prefix_length));
+    try!(protocol.write_field_end(transport));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_field_end(transport));
 
-    protocol.write_field_begin(transport, "prefixLength", Type::I16, 2).unwrap();
-    protocol.write_i16(transport, prefix_length).unwrap();
-    protocol.write_field_end(transport).unwrap();
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_field_end(transport).unwrap();
+    try!(protocol.write_field_begin(transport, "nextHopAddrs", Type::List, 2));
+    try!(protocol.write_list_begin(transport, Type::Struct, 1));
 
-    protocol.write_field_begin(transport, "nextHopAddrs", Type::List, 2).unwrap();
-    protocol.write_list_begin(transport, Type::Struct, 1).unwrap();
+    try!(handle_write_binary_address(protocol, transport, &IpAddr::V4(*addr_to)));
 
-    handle_write_binary_address(protocol, transport, &addr_to);
+    try!(protocol.write_list_end(transport));
+    try!(protocol.write_field_end(transport));
 
-    protocol.write_list_end(transport).unwrap();
-    protocol.write_field_end(transport).unwrap();
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_field_end(transport));
 
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_field_end(transport).unwrap();
+    Ok(())
 }
 
 
@@ -448,79 +1074,1592 @@ fn send_request_for_delete_route<P, T>(protocol: &mut P,
                                        transport: &mut T,
                                        request_number: i32,
                                        route_from: &str)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
-    let route_elements: Vec<&str> = route_from.split("/").collect();
-    let prefix_length = i16::from_str(route_elements[1]).unwrap();
-
-    protocol.write_message_begin(transport,
-                                 "deleteUnicastRoute",
-                                 MessageType::Call,
-                                 request_number)
-            .unwrap();
-    protocol.write_struct_begin(transport, "deleteUnicastRoute_args").unwrap();
-    handle_write_delete_route_args(protocol, transport, route_elements[0], prefix_length);
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_message_end(transport).unwrap();
+    let (addr, prefix_length) = try!(parse_ipv4_prefix(route_from));
+
+    try!(protocol.write_message_begin(transport,
+                                      "deleteUnicastRoute",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "deleteUnicastRoute_args"));
+    try!(handle_write_delete_route_args(protocol, transport, &addr, prefix_length));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
 }
 
 fn handle_write_delete_route_args<P, T>(protocol: &mut P,
                                         transport: &mut T,
-                                        route_from: &str,
+                                        addr: &Ipv4Addr,
                                         prefix_length: i16)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
-    let addr = Ipv4Addr::from_str(route_from).unwrap();
+    try!(protocol.write_field_begin(transport, "clientId", Type::I16, 1));
+    try!(protocol.write_i16(transport, 1));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "r", Type::Struct, 2));
+    try!(protocol.write_struct_begin(transport, "IpPrefix"));
+
+    try!(protocol.write_field_begin(transport, "ip", Type::Struct, 1));
+    try!(handle_write_binary_address(protocol, transport, &IpAddr::V4(*addr)));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "prefixLength", Type::I16, 2));
+    try!(protocol.write_i16(transport, prefix_length));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_field_end(transport));
 
-    protocol.write_field_begin(transport, "clientId", Type::I16, 1).unwrap();
-    protocol.write_i16(transport, 1).unwrap();
-    protocol.write_field_end(transport).unwrap();
+    Ok(())
+}
 
-    protocol.write_field_begin(transport, "r", Type::Struct, 2).unwrap();
-    protocol.write_struct_begin(transport, "IpPrefix").unwrap();
 
-    protocol.write_field_begin(transport, "ip", Type::Struct, 1).unwrap();
-    handle_write_binary_address(protocol, transport, &addr);
-    protocol.write_field_end(transport).unwrap();
+fn parse_ipv4_address(address: &str) -> Result<Ipv4Addr> {
+    Ipv4Addr::from_str(address)
+        .map_err(|_| Error::InvalidArgument(format!("invalid IPv4 address {}", address)))
+}
 
-    protocol.write_field_begin(transport, "prefixLength", Type::I16, 2).unwrap();
-    protocol.write_i16(transport, prefix_length).unwrap();
-    protocol.write_field_end(transport).unwrap();
+fn parse_ipv4_prefix(prefix: &str) -> Result<(Ipv4Addr, i16)> {
+    let prefix_elements: Vec<&str> = prefix.split("/").collect();
+    if prefix_elements.len() != 2 {
+        return Err(Error::InvalidArgument(format!("invalid IPv4 prefix {}", prefix)));
+    }
 
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
-    protocol.write_field_end(transport).unwrap();
+    let address = try!(parse_ipv4_address(prefix_elements[0]));
+    let prefix_length = try!(i16::from_str(prefix_elements[1])
+        .map_err(|_| Error::InvalidArgument(format!("invalid IPv4 prefix {}", prefix))));
+    Ok((address, prefix_length))
 }
 
+fn handle_write_binary_address<P, T>(protocol: &mut P, transport: &mut T, address: &IpAddr)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    let octets = match *address {
+        IpAddr::V4(ref ipv4) => ipv4.octets().to_vec(),
+        IpAddr::V6(ref ipv6) => ipv6.octets().to_vec(),
+    };
+
+    try!(protocol.write_struct_begin(transport, "BinaryAddress"));
+    try!(protocol.write_field_begin(transport, "addr", Type::String, 1));
+    try!(protocol.write_binary(transport, &octets));
+    try!(protocol.write_field_end(transport));
+    try!(protocol.write_field_begin(transport, "port", Type::I64, 2));
+    try!(protocol.write_i64(transport, 0));
+    try!(protocol.write_field_end(transport));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+
+    Ok(())
+}
 
-fn handle_write_binary_address<P, T>(protocol: &mut P, transport: &mut T, address: &Ipv4Addr)
+fn send_request_for_get_running_config<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<()>
     where P: Protocol,
           T: Transport
 {
-    protocol.write_struct_begin(transport, "BinaryAddress").unwrap();
-    protocol.write_field_begin(transport, "addr", Type::String, 1).unwrap();
-    protocol.write_binary(transport, &address.octets()).unwrap();
-    protocol.write_field_end(transport).unwrap();
-    protocol.write_field_begin(transport, "port", Type::I64, 2).unwrap();
-    protocol.write_i64(transport, 0).unwrap();
-    protocol.write_field_end(transport).unwrap();
-    protocol.write_field_stop(transport).unwrap();
-    protocol.write_struct_end(transport).unwrap();
 
+    try!(protocol.write_message_begin(transport,
+                                      "getRunningConfig",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "getRunningConfig_args"));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
 }
 
-fn handle_default_response<P, T>(protocol: &mut P, transport: &mut T, _request_number: i32)
+fn handle_response_for_get_running_config<P, T>(protocol: &mut P,
+                                                transport: &mut T,
+                                                request_number: i32)
+                                                -> Result<String>
     where P: Protocol,
           T: Transport
 {
-    let response = protocol.read_message_begin(transport).unwrap();
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, field_type| {
+        try!(check_success_type(Type::String, field_type));
+        protocol.read_string(transport)
+    }));
+
+    required_success(result)
+}
+
+
+fn send_request_for_reload_config<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport, "reloadConfig", MessageType::Call, request_number));
+    try!(protocol.write_struct_begin(transport, "reloadConfig_args"));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+
+fn send_request_without_args<P, T>(protocol: &mut P,
+                                   transport: &mut T,
+                                   request_number: i32,
+                                   name: &str)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport, name, MessageType::Call, request_number));
+    try!(protocol.write_struct_begin(transport, &format!("{}_args", name)));
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+fn handle_response_for_enum<E, P, T>(protocol: &mut P,
+                                     transport: &mut T,
+                                     request_number: i32)
+                                     -> Result<Option<E>>
+    where E: FromNum,
+          P: Protocol,
+          T: Transport
+{
+    read_result(protocol, transport, request_number, |protocol, transport, field_type| {
+        try!(check_success_type(Type::I32, field_type));
+        read_enum(protocol, transport)
+    })
+}
+
+fn handle_response_for_switch_run_state<P, T>(protocol: &mut P,
+                                              transport: &mut T,
+                                              request_number: i32)
+                                              -> Result<SwitchRunState>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(handle_response_for_enum(protocol, transport, request_number));
+    required_success(result)
+}
+
+fn handle_response_for_boot_type<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<BootType>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(handle_response_for_enum(protocol, transport, request_number));
+    required_success(result)
+}
+
+fn handle_response_for_product_info<P, T>(protocol: &mut P,
+                                          transport: &mut T,
+                                          request_number: i32)
+                                          -> Result<ProductInfo>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, field_type| {
+        try!(check_success_type(Type::Struct, field_type));
+        read_product_info(protocol, transport)
+    }));
+
+    required_success(result)
+}
+
+fn read_product_info<P, T>(protocol: &mut P, transport: &mut T) -> Result<ProductInfo>
+    where P: Protocol,
+          T: Transport
+{
+    let mut product_info = ProductInfo::default();
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let product_info_field = try!(protocol.read_field_begin(transport));
+        match product_info_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::String, 1) => product_info.oem = try!(protocol.read_string(transport)),
+            (_, Type::String, 2) => product_info.product = try!(protocol.read_string(transport)),
+            (_, Type::String, 3) => product_info.serial = try!(protocol.read_string(transport)),
+            (_, Type::String, 4) => product_info.mac = try!(protocol.read_string(transport)),
+            (_, Type::String, 5) => product_info.mgmt_mac = try!(protocol.read_string(transport)),
+            (_, Type::String, 6) => product_info.bmc_mac = try!(protocol.read_string(transport)),
+            (_, Type::I64, 7) => product_info.mac_range_size = try!(protocol.read_i64(transport)),
+            (_, Type::String, 10) => product_info.part_number = try!(protocol.read_string(transport)),
+            (_, Type::String, 13) => product_info.product_version = try!(protocol.read_string(transport)),
+            (_, product_info_field_type, _) => try!(protocol.skip(transport, product_info_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    Ok(product_info)
+}
+
+fn handle_response_for_get_acl_table<P, T>(protocol: &mut P,
+                                           transport: &mut T,
+                                           request_number: i32)
+                                           -> Result<Vec<AclEntry>>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, _| {
+        let mut result: Vec<AclEntry> = vec![];
+        let (_, list_size) = try!(protocol.read_list_begin(transport));
+        for _ in 0..list_size {
+            result.push(try!(read_acl_entry(protocol, transport)));
+        }
+        try!(protocol.read_list_end(transport));
+        Ok(result)
+    }));
+
+    Ok(result.unwrap_or(vec![]))
+}
+
+fn read_acl_entry<P, T>(protocol: &mut P, transport: &mut T) -> Result<AclEntry>
+    where P: Protocol,
+          T: Transport
+{
+    let mut acl_entry = AclEntry::default();
+    let mut src_ip: Option<String> = None;
+    let mut src_ip_prefix_length = 0;
+    let mut dst_ip: Option<String> = None;
+    let mut dst_ip_prefix_length = 0;
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let acl_field = try!(protocol.read_field_begin(transport));
+        match acl_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::I32, 1) => acl_entry.priority = try!(protocol.read_i32(transport)),
+            (_, Type::String, 2) => acl_entry.name = try!(protocol.read_string(transport)),
+            (_, Type::Struct, 3) => src_ip = Some(try!(read_binary_address(protocol, transport))),
+            (_, Type::I32, 4) => src_ip_prefix_length = try!(protocol.read_i32(transport)),
+            (_, Type::Struct, 5) => dst_ip = Some(try!(read_binary_address(protocol, transport))),
+            (_, Type::I32, 6) => dst_ip_prefix_length = try!(protocol.read_i32(transport)),
+            (_, Type::Byte, 7) => acl_entry.proto = Some(try!(protocol.read_byte(transport))),
+            (_, Type::I16, 18) => acl_entry.l4_src_port = Some(try!(protocol.read_i16(transport))),
+            (_, Type::I16, 19) => acl_entry.l4_dst_port = Some(try!(protocol.read_i16(transport))),
+            (_, Type::String, 20) => acl_entry.action = try!(protocol.read_string(transport)),
+            (_, acl_field_type, _) => try!(protocol.skip(transport, acl_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    acl_entry.src_ip = src_ip.map(|ip| format!("{}/{}", ip, src_ip_prefix_length));
+    acl_entry.dst_ip = dst_ip.map(|ip| format!("{}/{}", ip, dst_ip_prefix_length));
+    Ok(acl_entry)
+}
+
+fn handle_response_for_get_mpls_route_table<P, T>(protocol: &mut P,
+                                                  transport: &mut T,
+                                                  request_number: i32)
+                                                  -> Result<Vec<MplsRoute>>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, _| {
+        let mut result: Vec<MplsRoute> = vec![];
+        let (_, list_size) = try!(protocol.read_list_begin(transport));
+        for _ in 0..list_size {
+            result.push(try!(read_mpls_route(protocol, transport)));
+        }
+        try!(protocol.read_list_end(transport));
+        Ok(result)
+    }));
+
+    Ok(result.unwrap_or(vec![]))
+}
+
+fn read_mpls_route<P, T>(protocol: &mut P, transport: &mut T) -> Result<MplsRoute>
+    where P: Protocol,
+          T: Transport
+{
+    let mut route = MplsRoute {
+        top_label: 0,
+        next_hops: vec![],
+    };
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let route_field = try!(protocol.read_field_begin(transport));
+        match route_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::I32, 1) => route.top_label = try!(protocol.read_i32(transport)),
+            (_, Type::List, 4) => {
+                let (_, list_size) = try!(protocol.read_list_begin(transport));
+                for _ in 0..list_size {
+                    route.next_hops.push(try!(read_mpls_next_hop(protocol, transport)));
+                }
+                try!(protocol.read_list_end(transport));
+            }
+            (_, route_field_type, _) => try!(protocol.skip(transport, route_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    Ok(route)
+}
+
+fn read_mpls_next_hop<P, T>(protocol: &mut P, transport: &mut T) -> Result<MplsNextHop>
+    where P: Protocol,
+          T: Transport
+{
+    let mut next_hop = MplsNextHop {
+        address: "undefined".to_string(),
+        weight: 0,
+        action: None,
+    };
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let next_hop_field = try!(protocol.read_field_begin(transport));
+        match next_hop_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::Struct, 1) => next_hop.address = try!(read_binary_address(protocol, transport)),
+            (_, Type::I32, 2) => next_hop.weight = try!(protocol.read_i32(transport)),
+            (_, Type::Struct, 3) => next_hop.action = try!(read_mpls_action(protocol, transport)),
+            (_, next_hop_field_type, _) => try!(protocol.skip(transport, next_hop_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    Ok(next_hop)
+}
+
+fn read_mpls_action<P, T>(protocol: &mut P, transport: &mut T) -> Result<Option<MplsAction>>
+    where P: Protocol,
+          T: Transport
+{
+    let mut action_code: i32 = -1;
+    let mut swap_label: i32 = 0;
+    let mut push_labels: Vec<i32> = vec![];
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let action_field = try!(protocol.read_field_begin(transport));
+        match action_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::I32, 1) => action_code = try!(protocol.read_i32(transport)),
+            (_, Type::I32, 2) => swap_label = try!(protocol.read_i32(transport)),
+            (_, Type::List, 3) => {
+                let (_, list_size) = try!(protocol.read_list_begin(transport));
+                for _ in 0..list_size {
+                    push_labels.push(try!(protocol.read_i32(transport)));
+                }
+                try!(protocol.read_list_end(transport));
+            }
+            (_, action_field_type, _) => try!(protocol.skip(transport, action_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    let action = match action_code {
+        0 => Some(MplsAction::Push(push_labels)),
+        1 => Some(MplsAction::Swap(swap_label)),
+        2 => Some(MplsAction::Php),
+        3 => Some(MplsAction::PopAndLookup),
+        4 => Some(MplsAction::Noop),
+        _ => None,
+    };
+    Ok(action)
+}
+
+
+fn send_request_for_mpls_routes<P, T>(protocol: &mut P,
+                                      transport: &mut T,
+                                      request_number: i32,
+                                      name: &str,
+                                      routes: &[MplsRoute])
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    // all addresses are checked before anything is written, a rejected route leaves the stream untouched
+    let addresses: Vec<Vec<IpAddr>> = try!(routes.iter()
+        .map(|route| route.next_hops.iter().map(parse_next_hop_address).collect())
+        .collect());
+
+    try!(protocol.write_message_begin(transport, name, MessageType::Call, request_number));
+    try!(protocol.write_struct_begin(transport, &format!("{}_args", name)));
+
+    try!(protocol.write_field_begin(transport, "clientId", Type::I16, 1));
+    try!(protocol.write_i16(transport, 1));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "routes", Type::List, 2));
+    try!(protocol.write_list_begin(transport, Type::Struct, routes.len()));
+    for (route, addresses) in routes.iter().zip(&addresses) {
+        try!(handle_write_mpls_route(protocol, transport, route, addresses));
+    }
+    try!(protocol.write_list_end(transport));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+/// Writes `route`, `addresses` holding the parsed address of each of its next hops
+fn handle_write_mpls_route<P, T>(protocol: &mut P, transport: &mut T, route: &MplsRoute, addresses: &[IpAddr])
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    try!(protocol.write_struct_begin(transport, "MplsRoute"));
+
+    try!(protocol.write_field_begin(transport, "topLabel", Type::I32, 1));
+    try!(protocol.write_i32(transport, route.top_label));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "nextHops", Type::List, 4));
+    try!(protocol.write_list_begin(transport, Type::Struct, route.next_hops.len()));
+    for (next_hop, address) in route.next_hops.iter().zip(addresses) {
+        try!(handle_write_mpls_next_hop(protocol, transport, next_hop, address));
+    }
+    try!(protocol.write_list_end(transport));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+
+    Ok(())
+}
+
+fn parse_next_hop_address(next_hop: &MplsNextHop) -> Result<IpAddr> {
+    IpAddr::from_str(&next_hop.address)
+        .map_err(|_| Error::InvalidArgument(format!("invalid next hop address {}", next_hop.address)))
+}
+
+fn handle_write_mpls_next_hop<P, T>(protocol: &mut P, transport: &mut T, next_hop: &MplsNextHop, address: &IpAddr)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    try!(protocol.write_struct_begin(transport, "NextHopThrift"));
+
+    try!(protocol.write_field_begin(transport, "address", Type::Struct, 1));
+    try!(handle_write_binary_address(protocol, transport, address));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "weight", Type::I32, 2));
+    try!(protocol.write_i32(transport, next_hop.weight));
+    try!(protocol.write_field_end(transport));
+
+    if let Some(ref action) = next_hop.action {
+        try!(protocol.write_field_begin(transport, "mplsAction", Type::Struct, 3));
+        try!(handle_write_mpls_action(protocol, transport, action));
+        try!(protocol.write_field_end(transport));
+    }
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+
+    Ok(())
+}
+
+fn handle_write_mpls_action<P, T>(protocol: &mut P, transport: &mut T, action: &MplsAction)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    let action_code = match *action {
+        MplsAction::Push(_) => 0,
+        MplsAction::Swap(_) => 1,
+        MplsAction::Php => 2,
+        MplsAction::PopAndLookup => 3,
+        MplsAction::Noop => 4,
+    };
+
+    try!(protocol.write_struct_begin(transport, "MplsAction"));
+
+    try!(protocol.write_field_begin(transport, "action", Type::I32, 1));
+    try!(protocol.write_i32(transport, action_code));
+    try!(protocol.write_field_end(transport));
+
+    match *action {
+        MplsAction::Swap(swap_label) => {
+            try!(protocol.write_field_begin(transport, "swapLabel", Type::I32, 2));
+            try!(protocol.write_i32(transport, swap_label));
+            try!(protocol.write_field_end(transport));
+        }
+        MplsAction::Push(ref push_labels) => {
+            try!(protocol.write_field_begin(transport, "pushLabels", Type::List, 3));
+            try!(protocol.write_list_begin(transport, Type::I32, push_labels.len()));
+            for label in push_labels {
+                try!(protocol.write_i32(transport, *label));
+            }
+            try!(protocol.write_list_end(transport));
+            try!(protocol.write_field_end(transport));
+        }
+        _ => {}
+    }
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+
+    Ok(())
+}
+
+
+fn send_request_for_delete_mpls_routes<P, T>(protocol: &mut P,
+                                             transport: &mut T,
+                                             request_number: i32,
+                                             top_labels: &[i32])
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport,
+                                      "deleteMplsRoutes",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "deleteMplsRoutes_args"));
+
+    try!(protocol.write_field_begin(transport, "clientId", Type::I16, 1));
+    try!(protocol.write_i16(transport, 1));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "topLabels", Type::List, 2));
+    try!(protocol.write_list_begin(transport, Type::I32, top_labels.len()));
+    for label in top_labels {
+        try!(protocol.write_i32(transport, *label));
+    }
+    try!(protocol.write_list_end(transport));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+fn send_request_for_tx_pkt<P, T>(protocol: &mut P,
+                                 transport: &mut T,
+                                 request_number: i32,
+                                 port: Option<i32>,
+                                 data: &[u8])
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    let name = match port {
+        Some(_) => "txPkt",
+        None => "txPktL2",
+    };
+
+    try!(protocol.write_message_begin(transport, name, MessageType::Call, request_number));
+    try!(protocol.write_struct_begin(transport, &format!("{}_args", name)));
+
+    if let Some(port) = port {
+        try!(protocol.write_field_begin(transport, "port", Type::I32, 1));
+        try!(protocol.write_i32(transport, port));
+        try!(protocol.write_field_end(transport));
+    }
+
+    try!(protocol.write_field_begin(transport, "data", Type::String, 2));
+    try!(protocol.write_binary(transport, data));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+fn send_request_for_tx_pkt_l3<P, T>(protocol: &mut P,
+                                    transport: &mut T,
+                                    request_number: i32,
+                                    payload: &[u8])
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport, "txPktL3", MessageType::Call, request_number));
+    try!(protocol.write_struct_begin(transport, "txPktL3_args"));
+
+    try!(protocol.write_field_begin(transport, "payload", Type::String, 2));
+    try!(protocol.write_binary(transport, payload));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+fn send_request_for_start_pkt_capture<P, T>(protocol: &mut P,
+                                            transport: &mut T,
+                                            request_number: i32,
+                                            capture_info: &CaptureInfo)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport,
+                                      "startPktCapture",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "startPktCapture_args"));
+
+    try!(protocol.write_field_begin(transport, "info", Type::Struct, 1));
+    try!(handle_write_capture_info(protocol, transport, capture_info));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+fn handle_write_capture_info<P, T>(protocol: &mut P, transport: &mut T, capture_info: &CaptureInfo)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    try!(protocol.write_struct_begin(transport, "CaptureInfo"));
+
+    try!(protocol.write_field_begin(transport, "name", Type::String, 1));
+    try!(protocol.write_str(transport, &capture_info.name));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "maxPackets", Type::I32, 2));
+    try!(protocol.write_i32(transport, capture_info.max_packets));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_begin(transport, "direction", Type::I32, 3));
+    try!(protocol.write_i32(transport, capture_info.direction as i32));
+    try!(protocol.write_field_end(transport));
+
+    if let Some(ref filter) = capture_info.filter {
+        try!(protocol.write_field_begin(transport, "filter", Type::Struct, 4));
+        try!(protocol.write_struct_begin(transport, "CaptureFilter"));
+
+        try!(protocol.write_field_begin(transport, "rxCaptureFilter", Type::Struct, 1));
+        try!(protocol.write_struct_begin(transport, "RxCaptureFilter"));
+        try!(protocol.write_field_begin(transport, "cosQueues", Type::List, 1));
+        try!(protocol.write_list_begin(transport, Type::I32, filter.cos_queues.len()));
+        for cos_queue in &filter.cos_queues {
+            try!(protocol.write_i32(transport, *cos_queue));
+        }
+        try!(protocol.write_list_end(transport));
+        try!(protocol.write_field_end(transport));
+        try!(protocol.write_field_stop(transport));
+        try!(protocol.write_struct_end(transport));
+        try!(protocol.write_field_end(transport));
+
+        try!(protocol.write_field_stop(transport));
+        try!(protocol.write_struct_end(transport));
+        try!(protocol.write_field_end(transport));
+    }
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+
+    Ok(())
+}
+
+fn handle_response_for_get_pkt_captures<P, T>(protocol: &mut P,
+                                             transport: &mut T,
+                                             request_number: i32)
+                                             -> Result<Vec<CaptureInfo>>
+    where P: Protocol,
+          T: Transport
+{
+    let result = try!(read_result(protocol, transport, request_number, |protocol, transport, _| {
+        let mut result: Vec<CaptureInfo> = vec![];
+        let (_, list_size) = try!(protocol.read_list_begin(transport));
+        for _ in 0..list_size {
+            result.push(try!(read_capture_info(protocol, transport)));
+        }
+        try!(protocol.read_list_end(transport));
+        Ok(result)
+    }));
+
+    Ok(result.unwrap_or(vec![]))
+}
+
+fn read_capture_info<P, T>(protocol: &mut P, transport: &mut T) -> Result<CaptureInfo>
+    where P: Protocol,
+          T: Transport
+{
+    let mut capture_info = CaptureInfo {
+        name: String::new(),
+        max_packets: 0,
+        direction: CaptureDirection::TxRx,
+        filter: None,
+    };
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let capture_field = try!(protocol.read_field_begin(transport));
+        match capture_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::String, 1) => capture_info.name = try!(protocol.read_string(transport)),
+            (_, Type::I32, 2) => capture_info.max_packets = try!(protocol.read_i32(transport)),
+            (_, Type::I32, 3) => capture_info.direction = try!(read_enum(protocol, transport)),
+            (_, Type::Struct, 4) => capture_info.filter = Some(try!(read_capture_filter(protocol, transport))),
+            (_, capture_field_type, _) => try!(protocol.skip(transport, capture_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    Ok(capture_info)
+}
+
+fn read_capture_filter<P, T>(protocol: &mut P, transport: &mut T) -> Result<CaptureFilter>
+    where P: Protocol,
+          T: Transport
+{
+    let mut filter = CaptureFilter::default();
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let filter_field = try!(protocol.read_field_begin(transport));
+        match filter_field {
+            (_, Type::Stop, _) => break,
+            (_, Type::Struct, 1) => {
+                try!(protocol.read_struct_begin(transport));
+                loop {
+                    let rx_filter_field = try!(protocol.read_field_begin(transport));
+                    match rx_filter_field {
+                        (_, Type::Stop, _) => break,
+                        (_, Type::List, 1) => {
+                            let (_, list_size) = try!(protocol.read_list_begin(transport));
+                            for _ in 0..list_size {
+                                filter.cos_queues.push(try!(protocol.read_i32(transport)));
+                            }
+                            try!(protocol.read_list_end(transport));
+                        }
+                        (_, rx_filter_field_type, _) => try!(protocol.skip(transport, rx_filter_field_type)),
+                    }
+                    try!(protocol.read_field_end(transport));
+                }
+                try!(protocol.read_struct_end(transport));
+            }
+            (_, filter_field_type, _) => try!(protocol.skip(transport, filter_field_type)),
+        }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+
+    Ok(filter)
+}
+
+fn send_request_for_stop_pkt_capture<P, T>(protocol: &mut P,
+                                           transport: &mut T,
+                                           request_number: i32,
+                                           name: &str)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+
+    try!(protocol.write_message_begin(transport,
+                                      "stopPktCapture",
+                                      MessageType::Call,
+                                      request_number));
+    try!(protocol.write_struct_begin(transport, "stopPktCapture_args"));
+
+    try!(protocol.write_field_begin(transport, "name", Type::String, 1));
+    try!(protocol.write_str(transport, name));
+    try!(protocol.write_field_end(transport));
+
+    try!(protocol.write_field_stop(transport));
+    try!(protocol.write_struct_end(transport));
+    try!(protocol.write_message_end(transport));
+
+    Ok(())
+}
+
+
+fn handle_default_response<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    // void methods only carry declared exceptions in their result
+    try!(read_result(protocol, transport, request_number, |protocol, transport, field_type| {
+        protocol.skip(transport, field_type)
+    }));
+
+    Ok(())
+}
+
+/// Arguments of a generic `call`
+struct CallArgs<'a>(&'a Value);
+
+impl<'a> protocol::ThriftTyped for CallArgs<'a> {
+    fn typ() -> Type {
+        Type::Struct
+    }
+}
+
+impl<'a> protocol::Encode for CallArgs<'a> {
+    fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+        where P: Protocol,
+              T: Transport
+    {
+        write_value(protocol, transport, self.0)
+    }
+}
+
+fn handle_response_for_call<P, T>(protocol: &mut P, transport: &mut T, request_number: i32)
+    -> Result<Value>
+    where P: Protocol,
+          T: Transport
+{
+    try!(read_response_begin(protocol, transport, request_number));
+    let result = try!(read_value(protocol, transport, Type::Struct));
+    try!(protocol.read_message_end(transport));
+
+    let mut fields = match result {
+        Value::Struct(fields) => fields,
+        _ => unreachable!(),
+    };
+    // field 0 is the return value, any other field a declared exception
+    if let Some(success) = fields.remove(&0) {
+        return Ok(success);
+    }
+    match fields.into_iter().next() {
+        Some((id, value)) => Err(Error::Exception(Box::new(CallException { id: id, value: value }))),
+        None => Ok(Value::Struct(BTreeMap::new())),
+    }
+}
+
+fn read_response_begin<P, T>(protocol: &mut P, transport: &mut T, request_number: i32) -> Result<()>
+    where P: Protocol,
+          T: Transport
+{
+    let response = try!(protocol.read_message_begin(transport));
     match response {
-        (name, MessageType::Reply, _request_number) => println!("-- Response for {}", name),
-        (_, _, _) => {
-            println!("wrong response");
+        (name, _, sequence_id) if sequence_id != request_number => {
+            debug!("-- Response for {} with sequence id {}, expected {}", name, sequence_id, request_number);
+            Err(Error::from(protocol::Error::BadSequenceId))
+        }
+        (name, MessageType::Reply, _) => {
+            debug!("-- Response for {}", name);
+            Ok(())
+        }
+        (name, MessageType::Exception, _) => {
+            let mut exception = protocol::ApplicationException::default();
+            try!(protocol::Decode::decode(&mut exception, protocol, transport));
+            try!(protocol.read_message_end(transport));
+            debug!("-- Exception for {}: {}", name, exception.message);
+            Err(Error::ApplicationException(exception))
+        }
+        (_, _, _) => Err(Error::from(protocol::Error::ProtocolViolation)),
+    }
+}
+
+/// Reads the result struct of a reply, field 0 carries the return value and is handed to
+/// `read_success`, any other field is a declared exception thrown by the agent.
+fn read_result<R, F, P, T>(protocol: &mut P,
+                           transport: &mut T,
+                           request_number: i32,
+                           mut read_success: F)
+                           -> Result<Option<R>>
+    where F: FnMut(&mut P, &mut T, Type) -> Result<R>,
+          P: Protocol,
+          T: Transport
+{
+    let mut result: Option<R> = None;
+    let mut exception = false;
+
+    try!(read_response_begin(protocol, transport, request_number));
+
+    try!(protocol.read_struct_begin(transport));
+    loop {
+        let field = try!(protocol.read_field_begin(transport));
+        match field {
+            (_, Type::Stop, _) => break,
+            (_, field_type, 0) => {
+                result = Some(try!(read_success(protocol, transport, field_type)));
+            }
+            (_, field_type, _) => {
+                try!(protocol.skip(transport, field_type));
+                exception = true;
+            }
         }
+        try!(protocol.read_field_end(transport));
+    }
+    try!(protocol.read_struct_end(transport));
+    try!(protocol.read_message_end(transport));
+
+    if exception {
+        return Err(Error::UserException);
+    }
+    Ok(result)
+}
+
+/// The return value read by `read_result`, which a reply of a non-void method has to carry
+fn required_success<R>(result: Option<R>) -> Result<R> {
+    result.ok_or(Error::from(protocol::Error::ProtocolViolation))
+}
+
+fn check_success_type(expected: Type, actual: Type) -> Result<()> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(Error::from(protocol::Error::ProtocolViolation))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::thread;
+
+    use super::{read_binary_address, read_mpls_route, handle_write_capture_info, handle_write_mpls_route,
+                parse_next_hop_address, CallException, CaptureDirection, CaptureFilter, CaptureInfo, FbossClient,
+                MplsAction, MplsNextHop, MplsRoute, SwitchRunState, BootType};
+    use protocol::{self, helpers, ApplicationException, MessageType, Protocol, Type, Value, read_value, write_value};
+    use protocol::binary_protocol::BinaryProtocol;
+    use transport::memory_transport::MemoryTransport;
+    use transport::pipe_transport::pipe;
+    use Error;
+
+    /// Reply to the first call of a client, `write_success` writes the return value
+    fn reply<F>(method: &str, success_type: Type, write_success: F) -> MemoryTransport
+        where F: Fn(&mut BinaryProtocol, &mut MemoryTransport)
+    {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, method, MessageType::Reply, 1).unwrap();
+        protocol.write_struct_begin(&mut transport, "result").unwrap();
+        protocol.write_field_begin(&mut transport, "success", success_type, 0).unwrap();
+        write_success(&mut protocol, &mut transport);
+        protocol.write_field_end(&mut transport).unwrap();
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+        MemoryTransport::with_input(transport.take_written())
+    }
+
+    fn binary_address(bytes: &[u8]) -> MemoryTransport {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_struct_begin(&mut transport, "BinaryAddress").unwrap();
+        protocol.write_field_begin(&mut transport, "addr", Type::String, 1).unwrap();
+        protocol.write_binary(&mut transport, bytes).unwrap();
+        protocol.write_field_end(&mut transport).unwrap();
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+        MemoryTransport::with_input(transport.take_written())
+    }
+
+    fn value_reply(method: &str, success: &Value) -> MemoryTransport {
+        reply(method, success.typ(), |protocol, transport| write_value(protocol, transport, success).unwrap())
+    }
+
+    fn struct_of(fields: Vec<(i16, Value)>) -> Value {
+        Value::Struct(fields.into_iter().collect::<BTreeMap<_, _>>())
+    }
+
+    fn address(bytes: &[u8]) -> Value {
+        struct_of(vec![(1, Value::Binary(bytes.to_vec()))])
+    }
+
+    /// Checks that the client sent a call of `method` without arguments
+    fn assert_call(written: &[u8], method: &str) {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::with_input(written.to_vec());
+        let (name, message_type, sequence_id) = protocol.read_message_begin(&mut transport).unwrap();
+        assert_eq!((&name[..], message_type, sequence_id), (method, MessageType::Call, 1));
+        protocol.skip(&mut transport, Type::Struct).unwrap();
+        assert!(transport.remaining().is_empty());
+    }
+
+    fn read(bytes: &[u8]) -> ::Result<String> {
+        read_binary_address(&mut BinaryProtocol::new(), &mut binary_address(bytes))
+    }
+
+    #[test]
+    fn reads_ipv4_address() {
+        assert_eq!(read(&[10, 0, 0, 1]).unwrap(), "10.0.0.1");
+    }
+
+    #[test]
+    fn reads_ipv6_address() {
+        let bytes = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+        assert_eq!(read(&bytes).unwrap(), "2001:db8::1234:5678:9abc:def0");
+    }
+
+    #[test]
+    fn rejects_addresses_of_other_lengths() {
+        for len in &[0, 7] {
+            match read(&vec![1; *len]) {
+                Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+                other => panic!("{} bytes: {:?}", len, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_pkt_captures() {
+        let captures = vec![CaptureInfo {
+                                name: "bgp".to_string(),
+                                max_packets: 100,
+                                direction: CaptureDirection::OnlyRx,
+                                filter: Some(CaptureFilter { cos_queues: vec![2, 7] }),
+                            },
+                            CaptureInfo {
+                                name: "all".to_string(),
+                                max_packets: 10,
+                                direction: CaptureDirection::TxRx,
+                                filter: None,
+                            }];
+        let transport = reply("getPktCaptures", Type::List, |protocol, transport| {
+            protocol.write_list_begin(transport, Type::Struct, captures.len()).unwrap();
+            for capture_info in &captures {
+                handle_write_capture_info(protocol, transport, capture_info).unwrap();
+            }
+            protocol.write_list_end(transport).unwrap();
+        });
+
+        let mut client = FbossClient::new(transport);
+        let read = client.get_pkt_captures().unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].name, "bgp");
+        assert_eq!(read[0].max_packets, 100);
+        assert_eq!(read[0].direction, CaptureDirection::OnlyRx);
+        assert_eq!(read[0].filter.as_ref().unwrap().cos_queues, vec![2, 7]);
+        assert_eq!(read[1].name, "all");
+        assert_eq!(read[1].direction, CaptureDirection::TxRx);
+        assert!(read[1].filter.is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_capture_direction() {
+        let transport = reply("getPktCaptures", Type::List, |protocol, transport| {
+            protocol.write_list_begin(transport, Type::Struct, 1).unwrap();
+            protocol.write_struct_begin(transport, "CaptureInfo").unwrap();
+            protocol.write_field_begin(transport, "direction", Type::I32, 3).unwrap();
+            protocol.write_i32(transport, 3).unwrap();
+            protocol.write_field_end(transport).unwrap();
+            protocol.write_field_stop(transport).unwrap();
+            protocol.write_struct_end(transport).unwrap();
+            protocol.write_list_end(transport).unwrap();
+        });
+
+        match FbossClient::new(transport).get_pkt_captures() {
+            Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_routes() {
+        let routes = Value::List(Type::Struct,
+                                 vec![struct_of(vec![(1,
+                                                      struct_of(vec![(1, address(&[10, 1, 0, 0])),
+                                                                     (2, Value::I16(16))])),
+                                                     (2, Value::List(Type::Struct, vec![address(&[10, 0, 0, 1])]))]),
+                                      struct_of(vec![(1,
+                                                      struct_of(vec![(1, address(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0,
+                                                                                   0, 0, 0, 0, 0, 0, 0, 0, 0])),
+                                                                     (2, Value::I16(32))])),
+                                                     (2, Value::List(Type::Struct, vec![])),
+                                                     (3, Value::I32(4))])]);
+        let mut client = FbossClient::new(value_reply("getRouteTable", &routes));
+
+        let routes = client.get_routes().unwrap();
+        assert_call(client.transport().written(), "getRouteTable");
+        assert_eq!(routes.len(), 2);
+        assert_eq!((&routes[0].from[..], &routes[0].to[..]), ("10.1.0.0/16", "10.0.0.1"));
+        assert_eq!((&routes[1].from[..], &routes[1].to[..]), ("2001:db8::/32", ""));
+    }
+
+    #[test]
+    fn reads_ports_stats() {
+        let port = |id: i32, oper_state: i32| {
+            (Value::I32(id),
+             struct_of(vec![(1, Value::I32(id)),
+                            (2, Value::from(format!("eth1/{}/1", id))),
+                            (3, Value::I32(1)),
+                            (4, Value::I32(oper_state))]))
+        };
+        let ports = Value::Map(Type::I32, Type::Struct, vec![port(2, 0), port(1, 1)]);
+        let mut client = FbossClient::new(value_reply("getAllPortStats", &ports));
+
+        let ports = client.get_ports_stats().unwrap();
+        assert_call(client.transport().written(), "getAllPortStats");
+        let ports: Vec<_> = ports.iter().map(|port| (port.id, port.connected)).collect();
+        assert_eq!(ports, vec![(1, true), (2, false)]);
+    }
+
+    #[test]
+    fn reads_acl_table() {
+        let acls = Value::List(Type::Struct,
+                               vec![struct_of(vec![(1, Value::I32(100)),
+                                                   (2, Value::from("drop-telnet")),
+                                                   (3, address(&[10, 0, 0, 0])),
+                                                   (4, Value::I32(8)),
+                                                   (7, Value::Byte(6)),
+                                                   (19, Value::I16(23)),
+                                                   (20, Value::from("deny"))]),
+                                    struct_of(vec![(1, Value::I32(200)),
+                                                   (2, Value::from("permit-all")),
+                                                   (20, Value::from("permit"))])]);
+        let mut client = FbossClient::new(value_reply("getAclTable", &acls));
+
+        let acls = client.get_acl_table().unwrap();
+        assert_call(client.transport().written(), "getAclTable");
+        assert_eq!(acls.len(), 2);
+        assert_eq!(acls[0].priority, 100);
+        assert_eq!(acls[0].name, "drop-telnet");
+        assert_eq!(acls[0].src_ip, Some("10.0.0.0/8".to_string()));
+        assert_eq!(acls[0].dst_ip, None);
+        assert_eq!(acls[0].proto, Some(6));
+        assert_eq!(acls[0].l4_src_port, None);
+        assert_eq!(acls[0].l4_dst_port, Some(23));
+        assert_eq!(acls[0].action, "deny");
+        assert_eq!((acls[1].priority, &acls[1].action[..]), (200, "permit"));
+    }
+
+    #[test]
+    fn rejects_reply_to_another_call() {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        // the first call of a client has sequence id 1
+        protocol.write_message_begin(&mut transport, "getAclTable", MessageType::Reply, 2).unwrap();
+        protocol.write_struct_begin(&mut transport, "result").unwrap();
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+
+        let mut client = FbossClient::new(MemoryTransport::with_input(transport.take_written()));
+        match client.get_acl_table() {
+            Err(Error::ProtocolError(protocol::Error::BadSequenceId)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    /// Reply to the first call of a client carrying `result`, the result struct of the method
+    fn result_reply(method: &str, result: &Value) -> MemoryTransport {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, method, MessageType::Reply, 1).unwrap();
+        write_value(&mut protocol, &mut transport, result).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+        MemoryTransport::with_input(transport.take_written())
+    }
+
+    fn exception_reply(method: &str, exception: &ApplicationException) -> MemoryTransport {
+        let mut transport = MemoryTransport::new();
+        helpers::send_message(&mut BinaryProtocol::new(), &mut transport, method, MessageType::Exception, 1,
+                              exception)
+            .unwrap();
+        MemoryTransport::with_input(transport.take_written())
+    }
+
+    #[test]
+    fn call_returns_success_value() {
+        let mut client = FbossClient::new(value_reply("getSwitchRunState", &Value::I32(3)));
+        assert_eq!(client.call("getSwitchRunState", struct_of(vec![])).unwrap(), Value::I32(3));
+
+        let mut client = FbossClient::new(result_reply("syncFib", &struct_of(vec![])));
+        assert_eq!(client.call("syncFib", struct_of(vec![])).unwrap(), struct_of(vec![]));
+    }
+
+    #[test]
+    fn call_returns_declared_exception() {
+        let error = struct_of(vec![(1, Value::Binary(b"no such port".to_vec()))]);
+        let mut client = FbossClient::new(result_reply("getPortInfo", &struct_of(vec![(1, error.clone())])));
+        match client.call("getPortInfo", struct_of(vec![(1, Value::I32(7))])) {
+            Err(Error::Exception(exception)) => {
+                let exception = exception.downcast_ref::<CallException>().unwrap();
+                assert_eq!(*exception, CallException { id: 1, value: error });
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_rejects_arguments_that_are_no_struct() {
+        let mut client = FbossClient::new(MemoryTransport::new());
+        match client.call("getPortInfo", Value::I32(7)) {
+            Err(Error::InvalidArgument(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(client.transport.written().is_empty());
+    }
+
+    #[test]
+    fn reads_application_exception() {
+        let exception = ApplicationException {
+            message: "Method name frobnicate not found".to_string(),
+            kind: ApplicationException::UNKNOWN_METHOD,
+        };
+        let mut client = FbossClient::new(exception_reply("frobnicate", &exception));
+        match client.call("frobnicate", struct_of(vec![])) {
+            Err(Error::ApplicationException(ref received)) if *received == exception => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        // the connection is still in sync after the exception
+        assert!(client.transport.remaining().is_empty());
+        assert!(!client.broken);
+
+        let mut client = FbossClient::new(exception_reply("getAclTable", &exception));
+        match client.get_acl_table() {
+            Err(Error::ApplicationException(ref received)) if *received == exception => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_application_exception_with_unexpected_fields() {
+        // fields of the wrong type or unknown ids are skipped
+        let exception = struct_of(vec![(1, Value::I32(5)),
+                                       (2, Value::Binary(b"6".to_vec())),
+                                       (3, Value::List(Type::I64, vec![Value::I64(1)]))]);
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, "getAclTable", MessageType::Exception, 1).unwrap();
+        write_value(&mut protocol, &mut transport, &exception).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+
+        let mut client = FbossClient::new(MemoryTransport::with_input(transport.take_written()));
+        match client.get_acl_table() {
+            Err(Error::ApplicationException(ref received)) if *received == ApplicationException::default() => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_application_exception() {
+        let exception = ApplicationException {
+            message: "Internal error".to_string(),
+            kind: ApplicationException::INTERNAL_ERROR,
+        };
+        let bytes = exception_reply("getAclTable", &exception).remaining().to_vec();
+        for length in 0..bytes.len() {
+            let mut client = FbossClient::new(MemoryTransport::with_input(bytes[..length].to_vec()));
+            match client.get_acl_table() {
+                Err(Error::TransportError(_)) | Err(Error::ProtocolError(_)) => {}
+                other => panic!("unexpected result {:?} for {} bytes", other, length),
+            }
+        }
+    }
+
+    #[test]
+    fn rejected_route_writes_nothing() {
+        let mut client = FbossClient::new(MemoryTransport::new());
+        for &(route_from, route_to) in &[("10.0.0.0/8", "10.0.0.256"), ("10.0.0.0", "10.0.0.1"),
+                                         ("10.0.0.0/x", "10.0.0.1"), ("fe80::/64", "10.0.0.1")] {
+            match client.add_route(route_from, route_to) {
+                Err(Error::InvalidArgument(_)) => {}
+                other => panic!("{} -> {}: unexpected result {:?}", route_from, route_to, other),
+            }
+            assert!(client.transport.written().is_empty());
+            assert!(!client.broken);
+        }
+
+        // the next call starts on a clean stream
+        client.add_route("10.0.0.0/8", "10.0.0.1").unwrap_err();
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::with_input(client.transport.take_written());
+        let (name, message_type, _) = protocol.read_message_begin(&mut transport).unwrap();
+        assert_eq!((&name[..], message_type), ("addUnicastRoute", MessageType::Call));
+        protocol.skip(&mut transport, Type::Struct).unwrap();
+        assert!(transport.remaining().is_empty());
+    }
+
+    fn next_hop(address: &str, action: Option<MplsAction>) -> MplsNextHop {
+        MplsNextHop {
+            address: address.to_string(),
+            weight: 2,
+            action: action,
+        }
+    }
+
+    fn mpls_routes() -> Vec<MplsRoute> {
+        vec![MplsRoute {
+                 top_label: 100,
+                 next_hops: vec![next_hop("10.0.0.1", Some(MplsAction::Push(vec![200, 300]))),
+                                 next_hop("2001:db8::1", Some(MplsAction::Swap(400)))],
+             },
+             MplsRoute {
+                 top_label: 101,
+                 next_hops: vec![next_hop("10.0.0.2", Some(MplsAction::Php)),
+                                 next_hop("10.0.0.3", Some(MplsAction::PopAndLookup)),
+                                 next_hop("10.0.0.4", Some(MplsAction::Noop)),
+                                 next_hop("10.0.0.5", None)],
+             },
+             MplsRoute {
+                 top_label: 102,
+                 next_hops: vec![],
+             }]
+    }
+
+    fn write_mpls_route(protocol: &mut BinaryProtocol, transport: &mut MemoryTransport, route: &MplsRoute) {
+        let addresses: Vec<_> = route.next_hops.iter().map(|hop| parse_next_hop_address(hop).unwrap()).collect();
+        handle_write_mpls_route(protocol, transport, route, &addresses).unwrap();
+    }
+
+    /// Decodes the call the client wrote into its method name and arguments
+    fn written_call(written: &[u8]) -> (String, Value) {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::with_input(written.to_vec());
+        let (name, message_type, _) = protocol.read_message_begin(&mut transport).unwrap();
+        assert_eq!(message_type, MessageType::Call);
+        let args = read_value(&mut protocol, &mut transport, Type::Struct).unwrap();
+        protocol.read_message_end(&mut transport).unwrap();
+        assert!(transport.remaining().is_empty());
+        (name, args)
+    }
+
+    #[test]
+    fn mpls_routes_round_trip() {
+        for route in mpls_routes() {
+            let mut protocol = BinaryProtocol::new();
+            let mut transport = MemoryTransport::new();
+            write_mpls_route(&mut protocol, &mut transport, &route);
+            let mut transport = MemoryTransport::with_input(transport.take_written());
+            assert_eq!(read_mpls_route(&mut protocol, &mut transport).unwrap(), route);
+            assert!(transport.remaining().is_empty());
+        }
+    }
+
+    #[test]
+    fn reads_mpls_route_table() {
+        let routes = mpls_routes();
+        let transport = reply("getMplsRouteTable", Type::List, |protocol, transport| {
+            protocol.write_list_begin(transport, Type::Struct, routes.len()).unwrap();
+            for route in &routes {
+                write_mpls_route(protocol, transport, route);
+            }
+            protocol.write_list_end(transport).unwrap();
+        });
+
+        let mut client = FbossClient::new(transport);
+        assert_eq!(client.get_mpls_route_table().unwrap(), routes);
+        assert_call(client.transport().written(), "getMplsRouteTable");
+    }
+
+    #[test]
+    fn reads_unknown_mpls_action_as_none() {
+        let action = struct_of(vec![(1, Value::I32(5)), (2, Value::I32(400))]);
+        let route = struct_of(vec![(1, Value::I32(100)),
+                                   (4,
+                                    Value::List(Type::Struct,
+                                                vec![struct_of(vec![(1, address(&[10, 0, 0, 1])),
+                                                                    (2, Value::I32(2)),
+                                                                    (3, action)])]))]);
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        write_value(&mut protocol, &mut transport, &route).unwrap();
+        let mut transport = MemoryTransport::with_input(transport.take_written());
+
+        let route = read_mpls_route(&mut protocol, &mut transport).unwrap();
+        assert_eq!(route.next_hops, vec![next_hop("10.0.0.1", None)]);
+    }
+
+    #[test]
+    fn writes_mpls_routes_with_thrift_field_ids() {
+        let routes = vec![MplsRoute {
+                              top_label: 100,
+                              next_hops: vec![next_hop("10.0.0.1", Some(MplsAction::Push(vec![200, 300]))),
+                                              next_hop("10.0.0.2", Some(MplsAction::Swap(400))),
+                                              next_hop("10.0.0.3", Some(MplsAction::Php))],
+                          }];
+
+        // MplsAction: action = 1, swapLabel = 2, pushLabels = 3
+        let push = struct_of(vec![(1, Value::I32(0)),
+                                  (3, Value::List(Type::I32, vec![Value::I32(200), Value::I32(300)]))]);
+        let swap = struct_of(vec![(1, Value::I32(1)), (2, Value::I32(400))]);
+        let php = struct_of(vec![(1, Value::I32(2))]);
+        // NextHopThrift: address = 1, weight = 2, mplsAction = 3 and BinaryAddress: addr = 1, port = 2
+        let hop = |last_octet: u8, action: Value| {
+            let address = struct_of(vec![(1, Value::Binary(vec![10, 0, 0, last_octet])), (2, Value::I64(0))]);
+            struct_of(vec![(1, address), (2, Value::I32(2)), (3, action)])
+        };
+        // MplsRoute: topLabel = 1, nextHops = 4
+        let route = struct_of(vec![(1, Value::I32(100)),
+                                   (4, Value::List(Type::Struct, vec![hop(1, push), hop(2, swap), hop(3, php)]))]);
+        let expected_routes = Value::List(Type::Struct, vec![route]);
+
+        for &(name, sync) in &[("addMplsRoutes", false), ("syncMplsFib", true)] {
+            let mut client = FbossClient::new(MemoryTransport::new());
+            let result = if sync { client.sync_mpls_fib(&routes) } else { client.add_mpls_routes(&routes) };
+            result.unwrap_err();
+            let (method, args) = written_call(client.transport().written());
+            assert_eq!(method, name);
+            assert_eq!(args, struct_of(vec![(1, Value::I16(1)), (2, expected_routes.clone())]));
+        }
+
+        let mut client = FbossClient::new(MemoryTransport::new());
+        client.delete_mpls_routes(&[100, 101]).unwrap_err();
+        let (method, args) = written_call(client.transport().written());
+        assert_eq!(method, "deleteMplsRoutes");
+        assert_eq!(args,
+                   struct_of(vec![(1, Value::I16(1)),
+                                  (2, Value::List(Type::I32, vec![Value::I32(100), Value::I32(101)]))]));
+    }
+
+    #[test]
+    fn rejected_mpls_route_writes_nothing() {
+        let mut routes = mpls_routes();
+        routes[1].next_hops[2].address = "10.0.0".to_string();
+
+        let mut client = FbossClient::new(MemoryTransport::new());
+        for sync in &[false, true] {
+            let result = if *sync { client.sync_mpls_fib(&routes) } else { client.add_mpls_routes(&routes) };
+            match result {
+                Err(Error::InvalidArgument(_)) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+            assert!(client.transport.written().is_empty());
+            assert!(!client.broken);
+        }
+
+        let mut pipeline = client.pipeline();
+        assert!(pipeline.add_mpls_routes(&routes).is_err());
+        assert_eq!(pipeline.in_flight(), 0);
+    }
+
+    #[test]
+    fn calls_agent_over_pipe() {
+        let (client_end, mut agent_end) = pipe();
+        let agent = thread::spawn(move || {
+            let mut protocol = BinaryProtocol::new();
+            let (name, _, sequence_id) = protocol.read_message_begin(&mut agent_end).unwrap();
+            protocol.skip(&mut agent_end, Type::Struct).unwrap();
+            protocol.read_message_end(&mut agent_end).unwrap();
+
+            protocol.write_message_begin(&mut agent_end, &name, MessageType::Reply, sequence_id).unwrap();
+            protocol.write_struct_begin(&mut agent_end, "result").unwrap();
+            protocol.write_field_begin(&mut agent_end, "success", Type::I32, 0).unwrap();
+            protocol.write_i32(&mut agent_end, 2).unwrap();
+            protocol.write_field_end(&mut agent_end).unwrap();
+            protocol.write_field_stop(&mut agent_end).unwrap();
+            protocol.write_struct_end(&mut agent_end).unwrap();
+            protocol.write_message_end(&mut agent_end).unwrap();
+            name
+        });
+
+        let mut client = FbossClient::new(client_end);
+        assert_eq!(client.get_switch_run_state().unwrap(), SwitchRunState::Configured);
+        assert_eq!(agent.join().unwrap(), "getSwitchRunState");
+    }
+
+    fn assert_violation<R: ::std::fmt::Debug>(result: ::Result<R>) {
+        match result {
+            Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_running_config() {
+        let config = r#"{"defaultVlan": 4094}"#;
+        let mut client = FbossClient::new(value_reply("getRunningConfig", &Value::from(config)));
+        assert_eq!(client.get_running_config().unwrap(), config);
+        assert_call(client.transport().written(), "getRunningConfig");
+    }
+
+    #[test]
+    fn reloads_config() {
+        let mut client = FbossClient::new(result_reply("reloadConfig", &struct_of(vec![])));
+        client.reload_config().unwrap();
+        assert_call(client.transport().written(), "reloadConfig");
+
+        let error = struct_of(vec![(1, Value::from("invalid config"))]);
+        let mut client = FbossClient::new(result_reply("reloadConfig", &struct_of(vec![(1, error)])));
+        match client.reload_config() {
+            Err(Error::UserException) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_switch_run_state_and_boot_type() {
+        for &(value, state) in &[(0, SwitchRunState::Uninitialized),
+                                 (1, SwitchRunState::Initialized),
+                                 (2, SwitchRunState::Configured),
+                                 (3, SwitchRunState::FibSynced),
+                                 (4, SwitchRunState::Exiting)] {
+            let mut client = FbossClient::new(value_reply("getSwitchRunState", &Value::I32(value)));
+            assert_eq!(client.get_switch_run_state().unwrap(), state);
+            assert_call(client.transport().written(), "getSwitchRunState");
+        }
+        for &(value, boot_type) in &[(0, BootType::Uninitialized), (1, BootType::ColdBoot), (2, BootType::WarmBoot)] {
+            let mut client = FbossClient::new(value_reply("getBootType", &Value::I32(value)));
+            assert_eq!(client.get_boot_type().unwrap(), boot_type);
+            assert_call(client.transport().written(), "getBootType");
+        }
+    }
+
+    #[test]
+    fn reads_product_info() {
+        let info = struct_of(vec![(1, Value::from("Accton")),
+                                  (2, Value::from("Wedge100")),
+                                  (3, Value::from("AB1234")),
+                                  (4, Value::from("02:00:00:00:00:01")),
+                                  (5, Value::from("02:00:00:00:00:02")),
+                                  (6, Value::from("02:00:00:00:00:03")),
+                                  (7, Value::I64(64)),
+                                  (8, Value::List(Type::I32, vec![Value::I32(1)])),
+                                  (10, Value::from("20-001234")),
+                                  (13, Value::from("2"))]);
+        let mut client = FbossClient::new(value_reply("getProductInfo", &info));
+
+        let info = client.get_product_info().unwrap();
+        assert_call(client.transport().written(), "getProductInfo");
+        assert_eq!((&info.oem[..], &info.product[..], &info.serial[..]), ("Accton", "Wedge100", "AB1234"));
+        assert_eq!((&info.mac[..], &info.mgmt_mac[..], &info.bmc_mac[..]),
+                   ("02:00:00:00:00:01", "02:00:00:00:00:02", "02:00:00:00:00:03"));
+        assert_eq!((info.mac_range_size, &info.part_number[..], &info.product_version[..]), (64, "20-001234", "2"));
+    }
+
+    #[test]
+    fn rejects_replies_without_return_value() {
+        let empty = |method| result_reply(method, &struct_of(vec![]));
+        assert_violation(FbossClient::new(empty("getRunningConfig")).get_running_config());
+        assert_violation(FbossClient::new(empty("getSwitchRunState")).get_switch_run_state());
+        assert_violation(FbossClient::new(empty("getBootType")).get_boot_type());
+        assert_violation(FbossClient::new(empty("getProductInfo")).get_product_info());
+    }
+
+    #[test]
+    fn rejects_return_values_of_wrong_type() {
+        assert_violation(FbossClient::new(value_reply("getRunningConfig", &Value::I32(1))).get_running_config());
+        assert_violation(FbossClient::new(value_reply("getSwitchRunState", &Value::I64(2))).get_switch_run_state());
+        assert_violation(FbossClient::new(value_reply("getBootType", &Value::from("warm"))).get_boot_type());
+        assert_violation(FbossClient::new(value_reply("getProductInfo", &Value::from("x"))).get_product_info());
+    }
+
+    #[test]
+    fn rejects_unknown_enum_values() {
+        assert_violation(FbossClient::new(value_reply("getSwitchRunState", &Value::I32(5))).get_switch_run_state());
+        assert_violation(FbossClient::new(value_reply("getBootType", &Value::I32(-1))).get_boot_type());
     }
 }
@@ -0,0 +1,274 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Helpers to build simple Ethernet/IPv4/UDP frames for tx_pkt, tx_pkt_l2 and tx_pkt_l3
+
+use std::net::Ipv4Addr;
+
+use podio::{WritePodExt, BigEndian};
+
+use {Result, Error};
+
+pub type MacAddr = [u8; 6];
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const IP_PROTO_UDP: u8 = 17;
+
+const DEFAULT_TTL: u8 = 64;
+
+const IPV4_HEADER_LENGTH: usize = 20;
+const UDP_HEADER_LENGTH: usize = 8;
+const MAX_IPV4_LENGTH: usize = 65535;
+
+/// Largest payload that fits in an IPv4 packet
+pub const MAX_IPV4_PAYLOAD: usize = MAX_IPV4_LENGTH - IPV4_HEADER_LENGTH;
+
+/// Largest payload that fits in a UDP datagram carried over IPv4
+pub const MAX_UDP_PAYLOAD: usize = MAX_IPV4_PAYLOAD - UDP_HEADER_LENGTH;
+
+pub fn build_ethernet_frame(dst_mac: &MacAddr,
+                            src_mac: &MacAddr,
+                            ethertype: u16,
+                            payload: &[u8])
+                            -> Vec<u8> {
+    let mut frame = Vec::with_capacity(14 + payload.len());
+    frame.extend_from_slice(dst_mac);
+    frame.extend_from_slice(src_mac);
+    frame.write_u16::<BigEndian>(ethertype).unwrap();
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub fn build_ipv4_packet(src_ip: &Ipv4Addr,
+                         dst_ip: &Ipv4Addr,
+                         protocol: u8,
+                         ttl: u8,
+                         payload: &[u8])
+                         -> Result<Vec<u8>> {
+    if payload.len() > MAX_IPV4_PAYLOAD {
+        return Err(Error::InvalidArgument(format!("IPv4 payload of {} bytes exceeds {} bytes",
+                                                  payload.len(),
+                                                  MAX_IPV4_PAYLOAD)));
+    }
+
+    let mut packet = Vec::with_capacity(IPV4_HEADER_LENGTH + payload.len());
+    packet.write_u8(0x45).unwrap(); // version 4, header length 5 words
+    packet.write_u8(0).unwrap(); // dscp, ecn
+    packet.write_u16::<BigEndian>((IPV4_HEADER_LENGTH + payload.len()) as u16).unwrap();
+    packet.write_u16::<BigEndian>(0).unwrap(); // identification
+    packet.write_u16::<BigEndian>(0x4000).unwrap(); // don't fragment
+    packet.write_u8(ttl).unwrap();
+    packet.write_u8(protocol).unwrap();
+    packet.write_u16::<BigEndian>(0).unwrap(); // checksum, filled in below
+    packet.extend_from_slice(&src_ip.octets());
+    packet.extend_from_slice(&dst_ip.octets());
+
+    let checksum = internet_checksum(&packet, 0);
+    packet[10] = (checksum >> 8) as u8;
+    packet[11] = checksum as u8;
+
+    packet.extend_from_slice(payload);
+    Ok(packet)
+}
+
+pub fn build_udp_datagram(src_ip: &Ipv4Addr,
+                          dst_ip: &Ipv4Addr,
+                          src_port: u16,
+                          dst_port: u16,
+                          payload: &[u8])
+                          -> Result<Vec<u8>> {
+    if payload.len() > MAX_UDP_PAYLOAD {
+        return Err(Error::InvalidArgument(format!("UDP payload of {} bytes exceeds {} bytes",
+                                                  payload.len(),
+                                                  MAX_UDP_PAYLOAD)));
+    }
+
+    let length = (UDP_HEADER_LENGTH + payload.len()) as u16;
+
+    let mut datagram = Vec::with_capacity(length as usize);
+    datagram.write_u16::<BigEndian>(src_port).unwrap();
+    datagram.write_u16::<BigEndian>(dst_port).unwrap();
+    datagram.write_u16::<BigEndian>(length).unwrap();
+    datagram.write_u16::<BigEndian>(0).unwrap(); // checksum, filled in below
+    datagram.extend_from_slice(payload);
+
+    let mut pseudo_header = Vec::with_capacity(12);
+    pseudo_header.extend_from_slice(&src_ip.octets());
+    pseudo_header.extend_from_slice(&dst_ip.octets());
+    pseudo_header.write_u8(0).unwrap();
+    pseudo_header.write_u8(IP_PROTO_UDP).unwrap();
+    pseudo_header.write_u16::<BigEndian>(length).unwrap();
+
+    let checksum = match internet_checksum(&datagram, ones_complement_sum(&pseudo_header)) {
+        0 => 0xffff,
+        checksum => checksum,
+    };
+    datagram[6] = (checksum >> 8) as u8;
+    datagram[7] = checksum as u8;
+
+    Ok(datagram)
+}
+
+/// IPv4/UDP packet as expected by tx_pkt_l3
+pub fn build_udp_packet(src_ip: &Ipv4Addr,
+                        dst_ip: &Ipv4Addr,
+                        src_port: u16,
+                        dst_port: u16,
+                        payload: &[u8])
+                        -> Result<Vec<u8>> {
+    let datagram = try!(build_udp_datagram(src_ip, dst_ip, src_port, dst_port, payload));
+    build_ipv4_packet(src_ip, dst_ip, IP_PROTO_UDP, DEFAULT_TTL, &datagram)
+}
+
+/// Ethernet/IPv4/UDP frame as expected by tx_pkt and tx_pkt_l2
+pub fn build_udp_frame(dst_mac: &MacAddr,
+                       src_mac: &MacAddr,
+                       src_ip: &Ipv4Addr,
+                       dst_ip: &Ipv4Addr,
+                       src_port: u16,
+                       dst_port: u16,
+                       payload: &[u8])
+                       -> Result<Vec<u8>> {
+    let packet = try!(build_udp_packet(src_ip, dst_ip, src_port, dst_port, payload));
+    Ok(build_ethernet_frame(dst_mac, src_mac, ETHERTYPE_IPV4, &packet))
+}
+
+
+fn ones_complement_sum(data: &[u8]) -> u32 {
+    let mut sum: u32 = 0;
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            ((chunk[0] as u32) << 8) | chunk[1] as u32
+        } else {
+            (chunk[0] as u32) << 8
+        };
+        sum += word;
+    }
+    sum
+}
+
+fn internet_checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = initial + ones_complement_sum(data);
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::{build_udp_packet, build_udp_frame, ones_complement_sum, MAX_UDP_PAYLOAD};
+    use Error;
+
+    fn udp_packet(payload: &[u8]) -> ::Result<Vec<u8>> {
+        build_udp_packet(&Ipv4Addr::new(10, 0, 0, 1),
+                         &Ipv4Addr::new(10, 0, 0, 2),
+                         1234,
+                         5678,
+                         payload)
+    }
+
+    #[test]
+    fn builds_udp_packet() {
+        let packet = udp_packet(b"probe").unwrap();
+        assert_eq!(packet.len(), 20 + 8 + 5);
+        assert_eq!(&packet[2..4], &[0, 33]);
+        assert_eq!(&packet[24..26], &[0, 13]);
+        assert_eq!(&packet[28..], b"probe");
+    }
+
+    /// Folds the sum of `data`, which is 0xffff for a header or datagram with a valid checksum
+    fn folded_sum(data: &[u8]) -> u32 {
+        let mut sum = ones_complement_sum(data);
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        sum
+    }
+
+    #[test]
+    fn builds_udp_frame_with_known_good_checksums() {
+        let frame = build_udp_frame(&[0x02, 0, 0, 0, 0, 0x02],
+                                    &[0x02, 0, 0, 0, 0, 0x01],
+                                    &Ipv4Addr::new(10, 0, 0, 1),
+                                    &Ipv4Addr::new(10, 0, 0, 2),
+                                    1234,
+                                    5678,
+                                    b"probe")
+            .unwrap();
+        assert_eq!(frame,
+                   vec![0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00,
+                        // IPv4 header, checksum 0x26ca
+                        0x45, 0x00, 0x00, 0x21, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x26, 0xca,
+                        0x0a, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x02,
+                        // UDP header, checksum 0x8bfc over the pseudo header and the odd length payload
+                        0x04, 0xd2, 0x16, 0x2e, 0x00, 0x0d, 0x8b, 0xfc,
+                        0x70, 0x72, 0x6f, 0x62, 0x65]);
+    }
+
+    #[test]
+    fn builds_udp_packet_with_valid_checksums() {
+        for payload in &[&b""[..], &b"x"[..], &b"probe"[..], &[0xff; 1500][..]] {
+            let packet = udp_packet(payload).unwrap();
+            assert_eq!(folded_sum(&packet[..20]), 0xffff);
+
+            let mut pseudo_datagram = vec![10, 0, 0, 1, 10, 0, 0, 2, 0, 17];
+            pseudo_datagram.extend_from_slice(&packet[24..26]);
+            pseudo_datagram.extend_from_slice(&packet[20..]);
+            assert_eq!(folded_sum(&pseudo_datagram), 0xffff);
+        }
+    }
+
+    #[test]
+    fn sends_zero_udp_checksum_as_all_ones() {
+        // A zero checksum means "no checksum" in UDP, this payload makes the computed checksum zero
+        let packet = udp_packet(&[0xd0, 0xd7]).unwrap();
+        assert_eq!(&packet[26..28], &[0xff, 0xff]);
+    }
+
+    #[test]
+    fn builds_largest_udp_packet() {
+        let packet = udp_packet(&vec![0; MAX_UDP_PAYLOAD]).unwrap();
+        assert_eq!(packet.len(), 65535);
+        assert_eq!(&packet[2..4], &[0xff, 0xff]);
+    }
+
+    #[test]
+    fn rejects_oversized_udp_payload() {
+        match udp_packet(&vec![0; MAX_UDP_PAYLOAD + 1]) {
+            Err(Error::InvalidArgument(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|packet| packet.len())),
+        }
+        match build_udp_frame(&[0; 6],
+                              &[0; 6],
+                              &Ipv4Addr::new(10, 0, 0, 1),
+                              &Ipv4Addr::new(10, 0, 0, 2),
+                              1234,
+                              5678,
+                              &vec![0; 65535]) {
+            Err(Error::InvalidArgument(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|frame| frame.len())),
+        }
+    }
+}
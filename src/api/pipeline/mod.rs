@@ -0,0 +1,515 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Pipelining of several calls on one connection of the FbossClient
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+use {Protocol, Transport, Result, Error};
+use protocol::{self, Type};
+use transport::memory_transport::MemoryTransport;
+
+use super::{FbossClient, PortStat, Route, SwitchRunState, BootType, ProductInfo, AclEntry, MplsRoute,
+            CaptureDirection, CaptureFilter, CaptureInfo};
+use super::retry;
+use super::{send_request_for_all_ports_stats, handle_response_for_all_ports_stats, send_request_for_get_routes,
+            handle_response_for_get_routes, send_request_for_sync_fib, send_request_for_add_route,
+            send_request_for_delete_route, send_request_for_get_running_config,
+            handle_response_for_get_running_config, send_request_for_reload_config, send_request_without_args,
+            handle_response_for_switch_run_state, handle_response_for_boot_type,
+            handle_response_for_product_info, handle_response_for_get_acl_table,
+            handle_response_for_get_mpls_route_table, send_request_for_mpls_routes,
+            send_request_for_delete_mpls_routes, send_request_for_tx_pkt, send_request_for_tx_pkt_l3,
+            send_request_for_start_pkt_capture, send_request_for_stop_pkt_capture,
+            handle_response_for_get_pkt_captures, handle_default_response, broken_connection};
+
+// replies are read off the connection as a whole and decoded from memory
+type ResponseHandler<P, R> = fn(&mut P, &mut MemoryTransport, i32) -> Result<R>;
+
+/// Sends requests without waiting for the replies of the previous ones
+///
+/// Every operation sends its request and returns a `PendingReply`, which `receive` turns
+/// into the result. Replies can be received in any order, whatever the order the agent
+/// answers in, replies arriving ahead of the one being received are kept until they are
+/// asked for. A reply to no request in flight fails with `protocol::Error::BadSequenceId`.
+/// Calls in a pipeline are never retried, replies still on the wire when the pipeline is
+/// dropped leave the connection to be reconnected.
+pub struct Pipeline<'a, T, P>
+    where T: Transport + 'a,
+          P: Protocol + 'a
+{
+    client: &'a mut FbossClient<T, P>,
+    in_flight: VecDeque<i32>,
+    received: HashMap<i32, Vec<u8>>,
+}
+
+/// Reply to a request sent through a `Pipeline`
+#[must_use]
+pub struct PendingReply<T, P, R> {
+    request_number: i32,
+    handle_response: ResponseHandler<P, R>,
+    transport: PhantomData<fn(&mut T)>,
+}
+
+impl<T, P, R> PendingReply<T, P, R> {
+    pub fn request_number(&self) -> i32 {
+        self.request_number
+    }
+}
+
+impl<'a, T: Transport + 'a, P: Protocol + 'a> Pipeline<'a, T, P> {
+    pub fn new(client: &'a mut FbossClient<T, P>) -> Pipeline<'a, T, P> {
+        Pipeline {
+            client: client,
+            in_flight: VecDeque::new(),
+            received: HashMap::new(),
+        }
+    }
+
+    /// Number of requests whose reply hasn't been received yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Reads the reply to `reply`, keeping the replies to other requests read on the way
+    pub fn receive<R>(&mut self, reply: PendingReply<T, P, R>) -> Result<R> {
+        let request_number = reply.request_number;
+        match self.in_flight.iter().position(|in_flight| *in_flight == request_number) {
+            Some(index) => self.in_flight.remove(index),
+            None => return Err(Error::from(protocol::Error::BadSequenceId)),
+        };
+
+        let message = match self.received.remove(&request_number) {
+            Some(message) => message,
+            None => try!(self.read_reply(request_number)),
+        };
+
+        let mut transport = MemoryTransport::with_input(message);
+        (reply.handle_response)(&mut self.client.protocol, &mut transport, request_number)
+    }
+
+    /// Reads replies off the connection up to the one to `request_number`
+    fn read_reply(&mut self, request_number: i32) -> Result<Vec<u8>> {
+        loop {
+            // the replies behind a failed one can't be told apart anymore
+            if self.client.broken {
+                return Err(Error::from(io::Error::new(io::ErrorKind::NotConnected,
+                                                      "connection broke on an earlier reply")));
+            }
+
+            self.client.start_deadline();
+            let result = self.read_message();
+            self.client.clear_deadline();
+
+            match result {
+                Ok((sequence_id, message)) => {
+                    if sequence_id == request_number {
+                        return Ok(message);
+                    }
+                    if !self.in_flight.contains(&sequence_id) || self.received.contains_key(&sequence_id) {
+                        debug!("-- Reply with sequence id {} to no request in flight", sequence_id);
+                        self.client.broken = true;
+                        return Err(Error::from(protocol::Error::BadSequenceId));
+                    }
+                    self.received.insert(sequence_id, message);
+                }
+                Err(err) => {
+                    if retry::is_connection_error(&err) {
+                        self.client.broken = true;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Reads the next message, returning its sequence id and its bytes
+    fn read_message(&mut self) -> Result<(i32, Vec<u8>)> {
+        let mut recorder = Recorder {
+            inner: &mut self.client.transport,
+            recorded: Vec::new(),
+        };
+        let protocol = &mut self.client.protocol;
+        let (_, _, sequence_id) = try!(protocol.read_message_begin(&mut recorder));
+        try!(protocol.skip(&mut recorder, Type::Struct));
+        try!(protocol.read_message_end(&mut recorder));
+        Ok((sequence_id, recorder.recorded))
+    }
+
+    pub fn get_ports_stats(&mut self) -> Result<PendingReply<T, P, Vec<PortStat>>> {
+        self.send(send_request_for_all_ports_stats, handle_response_for_all_ports_stats)
+    }
+
+    pub fn get_routes(&mut self) -> Result<PendingReply<T, P, Vec<Route>>> {
+        self.send(send_request_for_get_routes, handle_response_for_get_routes)
+    }
+
+    pub fn sync_routes(&mut self) -> Result<PendingReply<T, P, ()>> {
+        self.send(send_request_for_sync_fib, handle_default_response)
+    }
+
+    pub fn add_route(&mut self, route_from: &str, route_to: &str) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_add_route(protocol, transport, request_number, route_from, route_to)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn delete_route(&mut self, route_from: &str) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_delete_route(protocol, transport, request_number, route_from)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn get_running_config(&mut self) -> Result<PendingReply<T, P, String>> {
+        self.send(send_request_for_get_running_config, handle_response_for_get_running_config)
+    }
+
+    pub fn reload_config(&mut self) -> Result<PendingReply<T, P, ()>> {
+        self.send(send_request_for_reload_config, handle_default_response)
+    }
+
+    pub fn get_switch_run_state(&mut self) -> Result<PendingReply<T, P, SwitchRunState>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getSwitchRunState")
+                  },
+                  handle_response_for_switch_run_state)
+    }
+
+    pub fn get_boot_type(&mut self) -> Result<PendingReply<T, P, BootType>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getBootType")
+                  },
+                  handle_response_for_boot_type)
+    }
+
+    pub fn get_product_info(&mut self) -> Result<PendingReply<T, P, ProductInfo>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getProductInfo")
+                  },
+                  handle_response_for_product_info)
+    }
+
+    pub fn get_acl_table(&mut self) -> Result<PendingReply<T, P, Vec<AclEntry>>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getAclTable")
+                  },
+                  handle_response_for_get_acl_table)
+    }
+
+    pub fn get_mpls_route_table(&mut self) -> Result<PendingReply<T, P, Vec<MplsRoute>>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getMplsRouteTable")
+                  },
+                  handle_response_for_get_mpls_route_table)
+    }
+
+    pub fn add_mpls_routes(&mut self, routes: &[MplsRoute]) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_mpls_routes(protocol, transport, request_number, "addMplsRoutes", routes)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn delete_mpls_routes(&mut self, top_labels: &[i32]) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_delete_mpls_routes(protocol, transport, request_number, top_labels)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn sync_mpls_fib(&mut self, routes: &[MplsRoute]) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_mpls_routes(protocol, transport, request_number, "syncMplsFib", routes)
+                  },
+                  handle_default_response)
+    }
+
+    /// Sends a complete ethernet frame out of the given port
+    pub fn tx_pkt(&mut self, port: i32, data: &[u8]) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_tx_pkt(protocol, transport, request_number, Some(port), data)
+                  },
+                  handle_default_response)
+    }
+
+    /// Sends a complete ethernet frame, switched by the ASIC
+    pub fn tx_pkt_l2(&mut self, data: &[u8]) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_tx_pkt(protocol, transport, request_number, None, data)
+                  },
+                  handle_default_response)
+    }
+
+    /// Sends an IP packet, routed by the ASIC
+    pub fn tx_pkt_l3(&mut self, payload: &[u8]) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_tx_pkt_l3(protocol, transport, request_number, payload)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn start_pkt_capture(&mut self,
+                             name: &str,
+                             max_packets: i32,
+                             direction: CaptureDirection,
+                             filter: Option<CaptureFilter>)
+                             -> Result<PendingReply<T, P, ()>> {
+        let capture_info = CaptureInfo {
+            name: name.to_string(),
+            max_packets: max_packets,
+            direction: direction,
+            filter: filter,
+        };
+
+        self.start_pkt_capture_with_info(&capture_info)
+    }
+
+    pub fn start_pkt_capture_with_info(&mut self, capture_info: &CaptureInfo) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_start_pkt_capture(protocol, transport, request_number, capture_info)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn stop_pkt_capture(&mut self, name: &str) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_for_stop_pkt_capture(protocol, transport, request_number, name)
+                  },
+                  handle_default_response)
+    }
+
+    pub fn stop_all_pkt_captures(&mut self) -> Result<PendingReply<T, P, ()>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "stopAllPktCaptures")
+                  },
+                  handle_default_response)
+    }
+
+    pub fn get_pkt_captures(&mut self) -> Result<PendingReply<T, P, Vec<CaptureInfo>>> {
+        self.send(|protocol, transport, request_number| {
+                      send_request_without_args(protocol, transport, request_number, "getPktCaptures")
+                  },
+                  handle_response_for_get_pkt_captures)
+    }
+
+    fn send<R, S>(&mut self,
+                  send_request: S,
+                  handle_response: ResponseHandler<P, R>)
+                  -> Result<PendingReply<T, P, R>>
+        where S: FnOnce(&mut P, &mut T, i32) -> Result<()>
+    {
+        if self.client.broken {
+            // replies still in flight are lost with the connection, so it is only replaced between batches
+            if self.client.reconnect.is_none() || !self.in_flight.is_empty() {
+                return Err(broken_connection());
+            }
+            try!(self.client.reconnect());
+        }
+
+        let request_number = self.client.next_request_number();
+
+        self.client.start_deadline();
+        let result = send_request(&mut self.client.protocol, &mut self.client.transport, request_number);
+        self.client.clear_deadline();
+
+        if let Err(err) = result {
+            if retry::is_connection_error(&err) {
+                self.client.broken = true;
+            }
+            return Err(err);
+        }
+
+        self.in_flight.push_back(request_number);
+        Ok(PendingReply {
+            request_number: request_number,
+            handle_response: handle_response,
+            transport: PhantomData,
+        })
+    }
+}
+
+impl<'a, T: Transport + 'a, P: Protocol + 'a> Drop for Pipeline<'a, T, P> {
+    fn drop(&mut self) {
+        // replies read already don't leave the connection out of sync
+        let on_the_wire = self.in_flight.len() - self.received.len();
+        if on_the_wire > 0 {
+            debug!("Dropping pipeline with {} replies in flight", on_the_wire);
+            self.client.broken = true;
+        }
+    }
+}
+
+/// Reads from the connection and keeps a copy of everything read
+struct Recorder<'t, T: 't> {
+    inner: &'t mut T,
+    recorded: Vec<u8>,
+}
+
+impl<'t, T: Read + 't> Read for Recorder<'t, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(self.inner.read(buf));
+        self.recorded.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<'t, T: 't> Write for Recorder<'t, T> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("replies are read only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::{BootType, FbossClient, SwitchRunState};
+    use protocol::{self, MessageType, Protocol, Type};
+    use protocol::binary_protocol::BinaryProtocol;
+    use transport::memory_transport::MemoryTransport;
+    use Error;
+
+    /// Replies to `(method, sequence id, i32 result)` calls, in the given order
+    fn replying_client(replies: &[(&str, i32, i32)]) -> FbossClient<MemoryTransport> {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        for &(method, sequence_id, result) in replies {
+            protocol.write_message_begin(&mut transport, method, MessageType::Reply, sequence_id).unwrap();
+            protocol.write_struct_begin(&mut transport, "result").unwrap();
+            protocol.write_field_begin(&mut transport, "success", Type::I32, 0).unwrap();
+            protocol.write_i32(&mut transport, result).unwrap();
+            protocol.write_field_end(&mut transport).unwrap();
+            protocol.write_field_stop(&mut transport).unwrap();
+            protocol.write_struct_end(&mut transport).unwrap();
+            protocol.write_message_end(&mut transport).unwrap();
+        }
+        FbossClient::new(MemoryTransport::with_input(transport.take_written()))
+    }
+
+    #[test]
+    fn receives_replies_in_request_order() {
+        let mut client = replying_client(&[("getSwitchRunState", 1, 2), ("getBootType", 2, 1)]);
+        {
+            let mut pipeline = client.pipeline();
+            let run_state = pipeline.get_switch_run_state().unwrap();
+            let boot_type = pipeline.get_boot_type().unwrap();
+            assert_eq!(pipeline.in_flight(), 2);
+            assert_eq!(pipeline.receive(run_state).unwrap(), SwitchRunState::Configured);
+            assert_eq!(pipeline.receive(boot_type).unwrap(), BootType::ColdBoot);
+            assert_eq!(pipeline.in_flight(), 0);
+        }
+        assert!(!client.is_broken());
+    }
+
+    #[test]
+    fn keeps_replies_arriving_out_of_order() {
+        let mut client = replying_client(&[("getBootType", 3, 2),
+                                           ("getSwitchRunState", 1, 3),
+                                           ("getSwitchRunState", 2, 1)]);
+        {
+            let mut pipeline = client.pipeline();
+            let first = pipeline.get_switch_run_state().unwrap();
+            let second = pipeline.get_switch_run_state().unwrap();
+            let boot_type = pipeline.get_boot_type().unwrap();
+            assert_eq!(pipeline.receive(first).unwrap(), SwitchRunState::FibSynced);
+            assert_eq!(pipeline.receive(boot_type).unwrap(), BootType::WarmBoot);
+            assert_eq!(pipeline.receive(second).unwrap(), SwitchRunState::Initialized);
+        }
+        assert!(!client.is_broken());
+        assert!(client.transport().remaining().is_empty());
+    }
+
+    #[test]
+    fn receives_replies_in_any_order() {
+        let mut client = replying_client(&[("getSwitchRunState", 1, 1),
+                                           ("getSwitchRunState", 2, 2),
+                                           ("getBootType", 3, 1)]);
+        {
+            let mut pipeline = client.pipeline();
+            let first = pipeline.get_switch_run_state().unwrap();
+            let second = pipeline.get_switch_run_state().unwrap();
+            let boot_type = pipeline.get_boot_type().unwrap();
+            assert_eq!(pipeline.receive(boot_type).unwrap(), BootType::ColdBoot);
+            assert_eq!(pipeline.in_flight(), 2);
+            assert_eq!(pipeline.receive(second).unwrap(), SwitchRunState::Configured);
+            assert_eq!(pipeline.receive(first).unwrap(), SwitchRunState::Initialized);
+        }
+        assert!(!client.is_broken());
+    }
+
+    #[test]
+    fn rejected_route_writes_nothing() {
+        let mut client = replying_client(&[]);
+        {
+            let mut pipeline = client.pipeline();
+            match pipeline.add_route("10.0.0.0/8", "no address") {
+                Err(Error::InvalidArgument(_)) => {}
+                other => panic!("unexpected result {:?}", other.map(|_| ())),
+            }
+            assert_eq!(pipeline.in_flight(), 0);
+        }
+        assert!(!client.is_broken());
+        assert!(client.transport().written().is_empty());
+    }
+
+    #[test]
+    fn rejects_reply_to_no_request_in_flight() {
+        let mut client = replying_client(&[("getSwitchRunState", 7, 1)]);
+        {
+            let mut pipeline = client.pipeline();
+            let run_state = pipeline.get_switch_run_state().unwrap();
+            match pipeline.receive(run_state) {
+                Err(Error::ProtocolError(protocol::Error::BadSequenceId)) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        assert!(client.is_broken());
+    }
+
+    #[test]
+    fn dropping_pipeline_with_replies_on_the_wire_breaks_connection() {
+        let mut client = replying_client(&[("getSwitchRunState", 2, 1), ("getSwitchRunState", 1, 2)]);
+        {
+            let mut pipeline = client.pipeline();
+            let first = pipeline.get_switch_run_state().unwrap();
+            let _second = pipeline.get_switch_run_state().unwrap();
+            pipeline.receive(first).unwrap();
+        }
+        // the reply to the second request was read while waiting for the first one
+        assert!(!client.is_broken());
+
+        let mut client = replying_client(&[("getSwitchRunState", 1, 2), ("getSwitchRunState", 2, 1)]);
+        {
+            let mut pipeline = client.pipeline();
+            let first = pipeline.get_switch_run_state().unwrap();
+            let _second = pipeline.get_switch_run_state().unwrap();
+            pipeline.receive(first).unwrap();
+        }
+        assert!(client.is_broken());
+    }
+}
@@ -0,0 +1,456 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Pool of FbossClient connections shared by many threads
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use {Protocol, Transport, Result, Error};
+use api::FbossClient;
+use api::retry::RetryPolicy;
+use protocol::binary_protocol::BinaryProtocol;
+use transport::BufferedTransport;
+use transport::socket_transport::SocketTransport;
+
+/// Sizing and checkout behavior of a `ClientPool`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolConfig {
+    min_size: usize,
+    max_size: usize,
+    checkout_timeout: Option<Duration>,
+    health_check: bool,
+}
+
+impl PoolConfig {
+    /// Up to 8 connections, opened on demand, health checked on checkout
+    pub fn new() -> PoolConfig {
+        PoolConfig {
+            min_size: 0,
+            max_size: 8,
+            checkout_timeout: None,
+            health_check: true,
+        }
+    }
+
+    /// Connections kept open, they are opened when the pool is created and reopened
+    /// when a broken or detached connection leaves the pool
+    pub fn with_min_size(mut self, min_size: usize) -> PoolConfig {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Upper bound of open connections, checkouts wait while all of them are in use
+    pub fn with_max_size(mut self, max_size: usize) -> PoolConfig {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Limit for waiting on a free connection, `checkout` fails with `Error::Timeout` after it
+    pub fn with_checkout_timeout(mut self, checkout_timeout: Duration) -> PoolConfig {
+        self.checkout_timeout = Some(checkout_timeout);
+        self
+    }
+
+    /// Calls `getSwitchRunState` on idle connections before handing them out,
+    /// without retries or reconnects, a failing connection is replaced by a new one
+    pub fn with_health_check(mut self, health_check: bool) -> PoolConfig {
+        self.health_check = health_check;
+        self
+    }
+
+    pub fn min_size(&self) -> usize {
+        self.min_size
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig::new()
+    }
+}
+
+type Connector<T, P> = Box<dyn Fn() -> Result<FbossClient<T, P>> + Send + Sync>;
+
+struct PoolState<T: Transport, P: Protocol> {
+    idle: Vec<FbossClient<T, P>>,
+    size: usize,
+}
+
+struct PoolInner<T: Transport, P: Protocol> {
+    connect: Connector<T, P>,
+    config: PoolConfig,
+    state: Mutex<PoolState<T, P>>,
+    released: Condvar,
+}
+
+impl<T: Transport, P: Protocol> PoolInner<T, P> {
+    fn lock(&self) -> MutexGuard<'_, PoolState<T, P>> {
+        // a panicking caller can't leave the state inconsistent, all updates are single steps
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn release(&self, client: FbossClient<T, P>) {
+        let mut state = self.lock();
+        if client.is_broken() {
+            debug!("Evicting broken connection from pool");
+            state.size -= 1;
+            drop(state);
+            self.released.notify_one();
+            self.replenish();
+        } else {
+            state.idle.push(client);
+            self.released.notify_one();
+        }
+    }
+
+    fn discard(&self) {
+        let mut state = self.lock();
+        state.size -= 1;
+        self.released.notify_one();
+    }
+
+    /// Opens idle connections until `min_size` connections are open again,
+    /// the agent being unreachable is left to the next checkout to report
+    fn replenish(&self) {
+        loop {
+            {
+                let mut state = self.lock();
+                if state.size >= self.config.min_size {
+                    return;
+                }
+                state.size += 1;
+            }
+
+            match (self.connect)() {
+                Ok(client) => self.release(client),
+                Err(err) => {
+                    debug!("Failed to replenish pool: {:?}", err);
+                    self.discard();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Whether an idle connection still talks to the agent, the client's retries and
+    /// reconnects are suspended so a dead connection fails fast and gets replaced
+    fn is_healthy(client: &mut FbossClient<T, P>) -> bool {
+        let retry_policy = *client.retry_policy();
+        client.set_retry_policy(RetryPolicy::no_retry());
+        let healthy = client.get_switch_run_state().is_ok();
+        client.set_retry_policy(retry_policy);
+        healthy && !client.is_broken()
+    }
+}
+
+/// Thread-safe pool of connections to one agent
+///
+/// Cloning the pool is cheap and shares the connections.
+pub struct ClientPool<T = BufferedTransport<SocketTransport>, P = BinaryProtocol>
+    where T: Transport,
+          P: Protocol
+{
+    inner: Arc<PoolInner<T, P>>,
+}
+
+impl ClientPool {
+    /// Pool of clients created by `FbossClient::connect`
+    pub fn connect(connect_string: &str, config: PoolConfig) -> Result<ClientPool> {
+        let connect_string = connect_string.to_string();
+        ClientPool::with_connector(config, move || FbossClient::connect(&connect_string))
+    }
+}
+
+impl<T: Transport, P: Protocol> ClientPool<T, P> {
+    /// Pool of clients created by `connect`, e.g. with timeouts, TLS or a custom retry policy
+    pub fn with_connector<F>(config: PoolConfig, connect: F) -> Result<ClientPool<T, P>>
+        where F: Fn() -> Result<FbossClient<T, P>> + Send + Sync + 'static
+    {
+        if config.max_size == 0 || config.min_size > config.max_size {
+            return Err(Error::InvalidArgument(format!("invalid pool size {}..{}", config.min_size, config.max_size)));
+        }
+
+        let mut idle = Vec::with_capacity(config.max_size);
+        for _ in 0..config.min_size {
+            idle.push(try!(connect()));
+        }
+
+        let inner = PoolInner {
+            connect: Box::new(connect),
+            config: config,
+            state: Mutex::new(PoolState {
+                size: idle.len(),
+                idle: idle,
+            }),
+            released: Condvar::new(),
+        };
+        Ok(ClientPool { inner: Arc::new(inner) })
+    }
+
+    /// Hands out an idle connection that passes the health check, opens a new one
+    /// if none is idle, or waits for one to be returned once `max_size` is reached
+    pub fn checkout(&self) -> Result<PooledClient<T, P>> {
+        let deadline = self.inner.config.checkout_timeout.map(|timeout| Instant::now() + timeout);
+        self.inner.replenish();
+
+        loop {
+            let mut state = self.inner.lock();
+
+            if let Some(mut client) = state.idle.pop() {
+                drop(state);
+                if self.inner.config.health_check && !PoolInner::is_healthy(&mut client) {
+                    debug!("Evicting connection from pool after failed health check");
+                    self.inner.discard();
+                    continue;
+                }
+                return Ok(self.pooled(client));
+            }
+
+            if state.size < self.inner.config.max_size {
+                state.size += 1;
+                drop(state);
+                return match (self.inner.connect)() {
+                    Ok(client) => Ok(self.pooled(client)),
+                    Err(err) => {
+                        self.inner.discard();
+                        Err(err)
+                    }
+                };
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::Timeout);
+                    }
+                    let (_state, _) = self.inner
+                        .released
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+                None => {
+                    let _state = self.inner.released.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+            }
+        }
+    }
+
+    /// Number of open connections, idle or checked out
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    pub fn idle_count(&self) -> usize {
+        self.inner.lock().idle.len()
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.inner.config
+    }
+
+    fn pooled(&self, client: FbossClient<T, P>) -> PooledClient<T, P> {
+        PooledClient {
+            client: Some(client),
+            pool: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Transport, P: Protocol> Clone for ClientPool<T, P> {
+    fn clone(&self) -> ClientPool<T, P> {
+        ClientPool { inner: self.inner.clone() }
+    }
+}
+
+/// Connection checked out of a `ClientPool`, returned to the pool on drop
+/// unless the connection broke
+pub struct PooledClient<T: Transport, P: Protocol> {
+    client: Option<FbossClient<T, P>>,
+    pool: Arc<PoolInner<T, P>>,
+}
+
+impl<T: Transport, P: Protocol> PooledClient<T, P> {
+    /// Takes the client out of the pool, which opens a new connection in its place when needed
+    pub fn detach(mut self) -> FbossClient<T, P> {
+        self.pool.discard();
+        self.pool.replenish();
+        self.client.take().unwrap()
+    }
+}
+
+impl<T: Transport, P: Protocol> Deref for PooledClient<T, P> {
+    type Target = FbossClient<T, P>;
+
+    fn deref(&self) -> &FbossClient<T, P> {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl<T: Transport, P: Protocol> DerefMut for PooledClient<T, P> {
+    fn deref_mut(&mut self) -> &mut FbossClient<T, P> {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl<T: Transport, P: Protocol> Drop for PooledClient<T, P> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.release(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{ClientPool, PoolConfig};
+    use api::{FbossClient, SwitchRunState};
+    use api::retry::RetryPolicy;
+    use protocol::{MessageType, Protocol, Type};
+    use protocol::binary_protocol::BinaryProtocol;
+    use transport::memory_transport::MemoryTransport;
+    use Error;
+
+    /// Reply to the first `getSwitchRunState` call of a connection
+    fn configured_reply() -> Vec<u8> {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, "getSwitchRunState", MessageType::Reply, 1).unwrap();
+        protocol.write_struct_begin(&mut transport, "result").unwrap();
+        protocol.write_field_begin(&mut transport, "success", Type::I32, 0).unwrap();
+        protocol.write_i32(&mut transport, SwitchRunState::Configured as i32).unwrap();
+        protocol.write_field_end(&mut transport).unwrap();
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+        transport.take_written()
+    }
+
+    /// Pool of clients that answer one call, except for the first `dead` ones which answer none,
+    /// and connection attempt `unreachable` which fails, along with the number of connection
+    /// attempts and reconnects made
+    fn pool(config: PoolConfig,
+            dead: usize,
+            unreachable: Option<usize>)
+            -> (ClientPool<MemoryTransport>, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let reconnects = Arc::new(AtomicUsize::new(0));
+        let (pool_connects, pool_reconnects) = (connects.clone(), reconnects.clone());
+        let pool = ClientPool::with_connector(config, move || {
+                let attempt = pool_connects.fetch_add(1, Ordering::SeqCst);
+                if Some(attempt) == unreachable {
+                    return Err(Error::Timeout);
+                }
+                let input = if attempt < dead {
+                    vec![]
+                } else {
+                    configured_reply()
+                };
+                let mut client = FbossClient::new(MemoryTransport::with_input(input));
+                let reconnects = pool_reconnects.clone();
+                client.set_reconnect(move || {
+                    reconnects.fetch_add(1, Ordering::SeqCst);
+                    Ok(MemoryTransport::with_input(configured_reply()))
+                });
+                Ok(client)
+            })
+            .unwrap();
+        (pool, connects, reconnects)
+    }
+
+    #[test]
+    fn replenishes_min_size_after_broken_connection() {
+        let config = PoolConfig::new().with_min_size(2).with_health_check(false);
+        let (pool, connects, _) = pool(config, 0, None);
+        assert_eq!((pool.size(), pool.idle_count()), (2, 2));
+
+        {
+            let mut client = pool.checkout().unwrap();
+            client.set_retry_policy(RetryPolicy::no_retry());
+            assert_eq!(client.get_switch_run_state().unwrap(), SwitchRunState::Configured);
+            // the connection has no more replies, the call breaks it
+            assert!(client.get_switch_run_state().is_err());
+            assert!(client.is_broken());
+        }
+
+        assert_eq!((pool.size(), pool.idle_count()), (2, 2));
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn replenishes_min_size_after_detach() {
+        let config = PoolConfig::new().with_min_size(1).with_health_check(false);
+        let (pool, connects, _) = pool(config, 0, None);
+
+        let client = pool.checkout().unwrap().detach();
+        assert_eq!((pool.size(), pool.idle_count()), (1, 1));
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+        drop(client);
+    }
+
+    #[test]
+    fn replenishes_min_size_on_checkout() {
+        let config = PoolConfig::new().with_min_size(1).with_health_check(false);
+        // the replacement for the detached connection can't be opened
+        let (pool, connects, _) = pool(config, 0, Some(1));
+        let client = pool.checkout().unwrap().detach();
+        assert_eq!(pool.size(), 0);
+
+        let _client = pool.checkout().unwrap();
+        assert_eq!((pool.size(), pool.idle_count()), (1, 0));
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+        drop(client);
+    }
+
+    #[test]
+    fn health_check_keeps_live_connection() {
+        let (pool, connects, reconnects) = pool(PoolConfig::new().with_min_size(1), 0, None);
+
+        let client = pool.checkout().unwrap();
+        assert!(!client.is_broken());
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(reconnects.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn health_check_replaces_dead_connection_without_reconnecting() {
+        let (pool, connects, reconnects) = pool(PoolConfig::new().with_min_size(1), 1, None);
+
+        let mut client = pool.checkout().unwrap();
+        assert_eq!(reconnects.load(Ordering::SeqCst), 0);
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+        // the replacement is fresh and was not health checked
+        assert_eq!(client.get_switch_run_state().unwrap(), SwitchRunState::Configured);
+        // the retry policy of the client is restored after the health check
+        assert_eq!(client.retry_policy().max_retries(), 3);
+        drop(client);
+        assert_eq!(pool.size(), 1);
+    }
+}
@@ -0,0 +1,404 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2015 AT&T
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// Reconnect and retry policy of the FbossClient
+
+use std::cmp;
+use std::time::Duration;
+
+use Error;
+
+/// Governs how often and how patiently a call is repeated after the connection broke
+///
+/// Calls that only read state of the agent are retried on a fresh connection,
+/// calls that change state are only retried with `with_retry_mutating(true)`,
+/// as the agent may have applied them before the connection broke.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+    retry_mutating: bool,
+}
+
+impl RetryPolicy {
+    /// Three retries, backing off from 100ms up to 2s
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2,
+            retry_mutating: false,
+        }
+    }
+
+    /// Reconnects broken connections, but never repeats a call
+    pub fn no_retry() -> RetryPolicy {
+        RetryPolicy::new().with_max_retries(0)
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> RetryPolicy {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Backoff before the first retry, multiplied by `multiplier` for every further retry
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration, multiplier: u32) -> RetryPolicy {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_retry_mutating(mut self, retry_mutating: bool) -> RetryPolicy {
+        self.retry_mutating = retry_mutating;
+        self
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    pub fn retry_mutating(&self) -> bool {
+        self.retry_mutating
+    }
+
+    /// Number of retries allowed for a call
+    pub fn retries_for(&self, idempotent: bool) -> u32 {
+        if idempotent || self.retry_mutating {
+            self.max_retries
+        } else {
+            0
+        }
+    }
+
+    /// Time to wait before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let mut backoff = self.initial_backoff;
+        for _ in 0..retry {
+            backoff = match backoff.checked_mul(self.multiplier) {
+                Some(backoff) => backoff,
+                None => return self.max_backoff,
+            };
+            if backoff >= self.max_backoff {
+                break;
+            }
+        }
+        cmp::min(backoff, self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+/// Whether the connection is unusable after `err`, e.g. after EOF, a reset or a malformed reply
+pub fn is_connection_error(err: &Error) -> bool {
+    matches!(*err, Error::TransportError(_) | Error::ProtocolError(_) | Error::Timeout)
+}
+
+/// Whether a call failing with `err` may succeed on a fresh connection
+pub fn is_retryable(err: &Error) -> bool {
+    matches!(*err, Error::TransportError(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::{is_connection_error, is_retryable, RetryPolicy};
+    use api::{FbossClient, SwitchRunState};
+    use protocol::{self, MessageType, Protocol, Type};
+    use protocol::binary_protocol::BinaryProtocol;
+    use transport::memory_transport::MemoryTransport;
+    use {Error, Result};
+
+    /// Connection replaying canned replies, or failing every read with the given error
+    enum Scripted {
+        Replies(MemoryTransport),
+        Failing(io::ErrorKind),
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match *self {
+                Scripted::Replies(ref mut transport) => transport.read(buf),
+                Scripted::Failing(kind) => Err(io::Error::new(kind, "scripted failure")),
+            }
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match *self {
+                Scripted::Replies(ref mut transport) => transport.write(buf),
+                Scripted::Failing(_) => Ok(buf.len()),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reply with sequence id `sequence_id`, carrying `success` or nothing for a void method
+    fn reply(sequence_id: i32, success: Option<i32>) -> Scripted {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, "method", MessageType::Reply, sequence_id).unwrap();
+        protocol.write_struct_begin(&mut transport, "result").unwrap();
+        if let Some(success) = success {
+            protocol.write_field_begin(&mut transport, "success", Type::I32, 0).unwrap();
+            protocol.write_i32(&mut transport, success).unwrap();
+            protocol.write_field_end(&mut transport).unwrap();
+        }
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+        Scripted::Replies(MemoryTransport::with_input(transport.take_written()))
+    }
+
+    fn closed() -> Scripted {
+        Scripted::Replies(MemoryTransport::new())
+    }
+
+    fn refused() -> Result<Scripted> {
+        Err(Error::TransportError(io::Error::new(io::ErrorKind::ConnectionRefused, "refused")))
+    }
+
+    /// Client on `connection` reconnecting to `reconnects` in turn, along with a count of its reconnects
+    fn scripted_client(connection: Scripted,
+                       reconnects: Vec<Result<Scripted>>,
+                       policy: RetryPolicy)
+                       -> (FbossClient<Scripted>, Arc<Mutex<u32>>) {
+        let reconnects = Mutex::new(reconnects.into_iter().collect::<VecDeque<_>>());
+        let count = Arc::new(Mutex::new(0));
+        let reconnect_count = count.clone();
+
+        let mut client = FbossClient::new(connection);
+        client.set_retry_policy(policy);
+        client.set_reconnect(move || {
+            *reconnect_count.lock().unwrap() += 1;
+            reconnects.lock().unwrap().pop_front().unwrap_or_else(refused)
+        });
+        (client, count)
+    }
+
+    fn without_backoff() -> RetryPolicy {
+        RetryPolicy::new().with_backoff(Duration::from_millis(0), Duration::from_millis(0), 2)
+    }
+
+    fn written(client: &FbossClient<Scripted>) -> usize {
+        match client.transport {
+            Scripted::Replies(ref transport) => transport.written().len(),
+            Scripted::Failing(_) => 0,
+        }
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max() {
+        let policy = RetryPolicy::new();
+        let backoffs: Vec<_> = (0..8).map(|retry| policy.backoff(retry).as_millis()).collect();
+        assert_eq!(backoffs, vec![100, 200, 400, 800, 1600, 2000, 2000, 2000]);
+        assert_eq!(policy.backoff(u32::max_value()), Duration::from_secs(2));
+
+        let policy = RetryPolicy::new().with_backoff(Duration::from_millis(10), Duration::from_secs(1), 1);
+        assert_eq!(policy.backoff(5), Duration::from_millis(10));
+        let policy = RetryPolicy::new().with_backoff(Duration::from_millis(10), Duration::from_secs(1), 0);
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(1), Duration::from_millis(0));
+    }
+
+    #[test]
+    fn backoff_saturates_on_overflow() {
+        let huge = Duration::from_secs(u64::max_value() / 2);
+        let policy = RetryPolicy::new().with_backoff(huge, Duration::from_secs(u64::max_value()), 4);
+        assert_eq!(policy.backoff(0), huge);
+        assert_eq!(policy.backoff(1), Duration::from_secs(u64::max_value()));
+        assert_eq!(policy.backoff(100), Duration::from_secs(u64::max_value()));
+    }
+
+    #[test]
+    fn retries_only_idempotent_calls_by_default() {
+        assert_eq!(RetryPolicy::new().retries_for(true), 3);
+        assert_eq!(RetryPolicy::new().retries_for(false), 0);
+        assert_eq!(RetryPolicy::new().with_retry_mutating(true).retries_for(false), 3);
+        assert_eq!(RetryPolicy::no_retry().retries_for(true), 0);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let transport_error = Error::TransportError(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+        assert!(is_connection_error(&transport_error) && is_retryable(&transport_error));
+        for err in &[Error::ProtocolError(protocol::Error::BadSequenceId), Error::Timeout] {
+            assert!(is_connection_error(err) && !is_retryable(err), "{:?}", err);
+        }
+        for err in &[Error::UserException, Error::InvalidArgument("route".to_string())] {
+            assert!(!is_connection_error(err) && !is_retryable(err), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn retries_idempotent_call_on_fresh_connection() {
+        let (mut client, reconnects) = scripted_client(closed(), vec![Ok(reply(2, Some(2)))], without_backoff());
+        assert_eq!(client.get_switch_run_state().unwrap(), SwitchRunState::Configured);
+        assert_eq!(*reconnects.lock().unwrap(), 1);
+        assert!(!client.is_broken());
+    }
+
+    #[test]
+    fn retries_after_failed_reconnect() {
+        // the failed reconnect sends no request, the retry on the next connection is request 2
+        let (mut client, reconnects) = scripted_client(closed(),
+                                                       vec![refused(), Ok(reply(2, Some(2)))],
+                                                       without_backoff());
+        assert_eq!(client.get_switch_run_state().unwrap(), SwitchRunState::Configured);
+        assert_eq!(*reconnects.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let (mut client, reconnects) = scripted_client(closed(),
+                                                       vec![Ok(closed()), Ok(closed()), Ok(reply(4, Some(2)))],
+                                                       without_backoff().with_max_retries(2));
+        match client.get_switch_run_state() {
+            Err(Error::TransportError(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(*reconnects.lock().unwrap(), 2);
+        assert!(client.is_broken());
+    }
+
+    #[test]
+    fn does_not_retry_mutating_call() {
+        let (mut client, reconnects) = scripted_client(closed(), vec![Ok(reply(2, None))], without_backoff());
+        match client.reload_config() {
+            Err(Error::TransportError(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(*reconnects.lock().unwrap(), 0);
+        assert!(client.is_broken());
+
+        // the next call replaces the broken connection first
+        client.reload_config().unwrap();
+        assert_eq!(*reconnects.lock().unwrap(), 1);
+        assert!(!client.is_broken());
+    }
+
+    #[test]
+    fn retries_mutating_call_when_allowed() {
+        let (mut client, reconnects) = scripted_client(closed(),
+                                                       vec![Ok(reply(2, None))],
+                                                       without_backoff().with_retry_mutating(true));
+        client.reload_config().unwrap();
+        assert_eq!(*reconnects.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn does_not_retry_protocol_errors_or_timeouts() {
+        let (mut client, reconnects) = scripted_client(reply(7, Some(2)),
+                                                       vec![Ok(reply(2, Some(2)))],
+                                                       without_backoff());
+        match client.get_switch_run_state() {
+            Err(Error::ProtocolError(protocol::Error::BadSequenceId)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(*reconnects.lock().unwrap(), 0);
+        assert!(client.is_broken());
+
+        let (mut client, reconnects) = scripted_client(Scripted::Failing(io::ErrorKind::TimedOut),
+                                                       vec![Ok(reply(2, Some(2)))],
+                                                       without_backoff());
+        match client.get_switch_run_state() {
+            Err(Error::Timeout) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(*reconnects.lock().unwrap(), 0);
+        assert!(client.is_broken());
+    }
+
+    #[test]
+    fn declared_exception_keeps_connection() {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, "getSwitchRunState", MessageType::Reply, 1).unwrap();
+        protocol.write_struct_begin(&mut transport, "result").unwrap();
+        protocol.write_field_begin(&mut transport, "error", Type::Struct, 1).unwrap();
+        protocol.write_struct_begin(&mut transport, "FbossBaseError").unwrap();
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+        protocol.write_field_end(&mut transport).unwrap();
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+        let connection = Scripted::Replies(MemoryTransport::with_input(transport.take_written()));
+
+        let (mut client, reconnects) = scripted_client(connection, vec![], without_backoff());
+        match client.get_switch_run_state() {
+            Err(Error::UserException) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(*reconnects.lock().unwrap(), 0);
+        assert!(!client.is_broken());
+    }
+
+    #[test]
+    fn backs_off_between_retries() {
+        let policy = RetryPolicy::new()
+            .with_max_retries(2)
+            .with_backoff(Duration::from_millis(20), Duration::from_secs(1), 2);
+        let (mut client, reconnects) = scripted_client(closed(), vec![Ok(closed()), Ok(closed())], policy);
+        let start = Instant::now();
+        assert!(client.get_switch_run_state().is_err());
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert_eq!(*reconnects.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn broken_client_without_reconnect_fails_fast() {
+        let mut client = FbossClient::new(closed());
+        assert!(client.get_switch_run_state().is_err());
+        assert!(client.is_broken());
+        let sent = written(&client);
+
+        for _ in 0..2 {
+            match client.get_switch_run_state() {
+                Err(Error::TransportError(ref err)) if err.kind() == io::ErrorKind::NotConnected => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        match client.pipeline().get_boot_type() {
+            Err(Error::TransportError(ref err)) if err.kind() == io::ErrorKind::NotConnected => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        // nothing more was sent on the poisoned stream
+        assert_eq!(written(&client), sent);
+        assert!(client.is_broken());
+    }
+}
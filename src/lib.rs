@@ -21,6 +21,19 @@
 // THE SOFTWARE.
 
 extern crate podio;
+extern crate bufstream;
+
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(feature = "tls")]
+extern crate openssl;
+
+#[cfg(feature = "async")]
+extern crate tokio;
+
+#[cfg(feature = "derive")]
+extern crate torc_fboss_client_derive;
 
 #[macro_use]
 extern crate log;
@@ -30,6 +43,8 @@ use std::error::Error as StdError;
 
 pub use protocol::Protocol;
 pub use transport::Transport;
+#[cfg(feature = "derive")]
+pub use torc_fboss_client_derive::ThriftStruct;
 
 pub mod protocol;
 pub mod transport;
@@ -47,6 +62,19 @@ pub enum Error {
 
 	/// The server code threw a user-defined exception
 	UserException,
+
+	/// The server failed to handle the call, e.g. because it doesn't know the method
+	ApplicationException(protocol::ApplicationException),
+
+	/// The server code threw an exception declared in the IDL, generated clients return the
+	/// exception struct, which can be recovered with `downcast_ref`
+	Exception(Box<dyn StdError + Send + Sync>),
+
+	/// An argument passed to the client can't be converted to its thrift representation
+	InvalidArgument(String),
+
+	/// Connecting to or waiting on the server took longer than the configured timeout
+	Timeout,
 }
 
 impl From<protocol::Error> for Error {
@@ -57,7 +85,10 @@ impl From<protocol::Error> for Error {
 
 impl From<io::Error> for Error {
 	fn from(err: io::Error) -> Error {
-			Error::TransportError(err)
+			match err.kind() {
+				io::ErrorKind::TimedOut => Error::Timeout,
+				_ => Error::TransportError(err),
+			}
 	}
 }
 
@@ -70,6 +101,7 @@ impl StdError for Error {
 		match *self {
 				Error::TransportError(ref err) => Some(err),
 				Error::ProtocolError(ref err) => Some(err),
+				Error::Exception(ref err) => Some(&**err),
 				_ => None
 			}
 	}
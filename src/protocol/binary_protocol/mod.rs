@@ -17,18 +17,61 @@
  * under the License.
  */
 
-use protocol::{self, MessageType, Protocol, Type};
+use protocol::{self, Limits, MessageType, Protocol, Type, read_value};
+use protocol::limits::{check_element_type, read_bytes};
 use transport::Transport;
 use {Result, Error};
 
+use log::Level;
 use podio::{ReadPodExt, WritePodExt, BigEndian};
 
 static BINARY_PROTOCOL_VERSION_1: u16 = 0x8001;
 
+/// Thrift's binary protocol, the protocol FBOSS agents speak by default
+///
+/// The protocol keeps its `Limits` and the nesting depth of what it reads, so it is no longer a unit
+/// struct: construct it with `BinaryProtocol::new()`, `BinaryProtocol::default()` or `with_limits`.
 #[derive(Copy, Clone, Debug)]
-pub struct BinaryProtocol;
+pub struct BinaryProtocol {
+    limits: Limits,
+    depth: usize,
+}
 
 impl BinaryProtocol {
+    /// A protocol with the default `Limits`
+    pub fn new() -> BinaryProtocol {
+        BinaryProtocol::with_limits(Limits::new())
+    }
+
+    pub fn with_limits(limits: Limits) -> BinaryProtocol {
+        BinaryProtocol {
+            limits: limits,
+            depth: 0,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        self.limits.check_depth(self.depth)
+    }
+
+    fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    fn read_size<T: Transport>(&mut self, transport: &mut T) -> Result<i32> {
+        let size = try!(self.read_i32(transport));
+        self.limits.check_container_size(size as i64)
+    }
+
     fn write_type<T: Transport>(&mut self, transport: &mut T, type_: Type) -> Result<()> {
         self.write_byte(transport, type_ as i8)
     }
@@ -42,6 +85,12 @@ impl BinaryProtocol {
     }
 }
 
+impl Default for BinaryProtocol {
+    fn default() -> BinaryProtocol {
+        BinaryProtocol::new()
+    }
+}
+
 impl Protocol for BinaryProtocol {
     fn write_message_begin<T: Transport>(
         &mut self,
@@ -56,8 +105,8 @@ impl Protocol for BinaryProtocol {
         self.write_i32(transport, sequence_id)
     }
 
-    fn write_message_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
-        Ok(())
+    fn write_message_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        Ok(try!(transport.flush()))
     }
 
     fn write_struct_begin<T: Transport>(&mut self, _transport: &mut T, _name: &str) -> Result<()> {
@@ -159,6 +208,8 @@ impl Protocol for BinaryProtocol {
     }
 
     fn read_message_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(String, MessageType, i32)> {
+        // a new message starts at the top, even if the last one failed halfway
+        self.depth = 0;
         let header = try!(self.read_i32(transport));
         let version = (header >> 16) as u16;
         if version != BINARY_PROTOCOL_VERSION_1 {
@@ -179,10 +230,12 @@ impl Protocol for BinaryProtocol {
     }
 
     fn read_struct_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<String> {
+        try!(self.enter());
         Ok(String::new())
     }
 
     fn read_struct_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
         Ok(())
     }
 
@@ -200,33 +253,43 @@ impl Protocol for BinaryProtocol {
     }
 
     fn read_map_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, Type, i32)> {
+        try!(self.enter());
         let key_type = try!(self.read_type(transport));
         let value_type = try!(self.read_type(transport));
-        let size = try!(self.read_i32(transport));
+        let size = try!(self.read_size(transport));
+        try!(check_element_type(key_type, size));
+        try!(check_element_type(value_type, size));
         Ok((key_type, value_type, size))
     }
 
     fn read_map_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
         Ok(())
     }
 
     fn read_list_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        try!(self.enter());
         let elem_type = try!(self.read_type(transport));
-        let size = try!(self.read_i32(transport));
+        let size = try!(self.read_size(transport));
+        try!(check_element_type(elem_type, size));
         Ok((elem_type, size))
     }
 
     fn read_list_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
         Ok(())
     }
 
     fn read_set_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        try!(self.enter());
         let elem_type = try!(self.read_type(transport));
-        let size = try!(self.read_i32(transport));
+        let size = try!(self.read_size(transport));
+        try!(check_element_type(elem_type, size));
         Ok((elem_type, size))
     }
 
     fn read_set_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
         Ok(())
     }
 
@@ -263,11 +326,18 @@ impl Protocol for BinaryProtocol {
     }
 
     fn read_binary<T: Transport>(&mut self, transport: &mut T) -> Result<Vec<u8>> {
-        let len = try!(self.read_i32(transport)) as usize;
-        Ok(try!(ReadPodExt::read_exact(transport, len)))
+        let len = try!(self.read_i32(transport));
+        let len = try!(self.limits.check_string_length(len as i64));
+        read_bytes(transport, len)
     }
 
     fn skip<T: Transport>(&mut self, transport: &mut T, type_: Type) -> Result<()> {
+        if log_enabled!(Level::Debug) && type_ != Type::Stop && type_ != Type::Void {
+            let value = try!(read_value(self, transport, type_));
+            debug!("Skipped {:?} {}", type_, value);
+            return Ok(());
+        }
+
         match type_ {
             Type::Bool => { try!(self.read_bool(transport)); }
             Type::Byte => { try!(self.read_byte(transport)); }
@@ -0,0 +1,716 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::cmp;
+
+use protocol::{self, Limits, MessageType, Protocol, Type, read_value};
+use protocol::limits::{check_element_type, read_bytes};
+use transport::Transport;
+use {Result, Error};
+
+use log::Level;
+use podio::{ReadPodExt, WritePodExt, BigEndian, LittleEndian};
+
+static COMPACT_PROTOCOL_ID: u8 = 0x82;
+static COMPACT_PROTOCOL_VERSION_1: u8 = 0x01;
+// fbthrift variant of version 1, doubles are sent big endian
+static COMPACT_PROTOCOL_VERSION_2: u8 = 0x02;
+static COMPACT_VERSION_MASK: u8 = 0x1f;
+static COMPACT_TYPE_SHIFT: u8 = 5;
+
+static COMPACT_BOOLEAN_TRUE: u8 = 0x01;
+static COMPACT_BOOLEAN_FALSE: u8 = 0x02;
+
+#[derive(Clone, Debug)]
+pub struct CompactProtocol {
+    version: u8,
+    last_field_id: i16,
+    last_field_ids: Vec<i16>,
+    pending_write_bool_field: Option<i16>,
+    pending_read_bool: Option<bool>,
+    limits: Limits,
+    depth: usize,
+}
+
+impl CompactProtocol {
+    pub fn new() -> CompactProtocol {
+        CompactProtocol::with_version(COMPACT_PROTOCOL_VERSION_1)
+    }
+
+    /// Version 2 is the fbthrift flavour, which sends doubles big endian
+    pub fn with_version(version: u8) -> CompactProtocol {
+        CompactProtocol {
+            version: version,
+            last_field_id: 0,
+            last_field_ids: Vec::new(),
+            pending_write_bool_field: None,
+            pending_read_bool: None,
+            limits: Limits::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        self.limits.check_depth(self.depth)
+    }
+
+    fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Sizes are sent as unsigned varints, too large ones are cut to fail the size check
+    fn read_size<T: Transport>(&mut self, transport: &mut T) -> Result<u64> {
+        let size = try!(self.read_varint(transport));
+        Ok(cmp::min(size, i64::MAX as u64))
+    }
+
+    fn write_varint<T: Transport>(&mut self, transport: &mut T, value: u64) -> Result<()> {
+        let mut value = value;
+        let mut buf = [0u8; 10];
+        let mut len = 0;
+        loop {
+            if value & !0x7f == 0 {
+                buf[len] = value as u8;
+                len += 1;
+                break;
+            }
+            buf[len] = ((value & 0x7f) | 0x80) as u8;
+            len += 1;
+            value >>= 7;
+        }
+        Ok(try!(transport.write_all(&buf[..len])))
+    }
+
+    fn read_varint<T: Transport>(&mut self, transport: &mut T) -> Result<u64> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = try!(transport.read_u8());
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+            if shift >= 64 {
+                return Err(Error::from(protocol::Error::ProtocolViolation));
+            }
+        }
+    }
+
+    fn write_field_header<T: Transport>(&mut self, transport: &mut T, compact_type: u8, field_id: i16) -> Result<()> {
+        let delta = field_id as i32 - self.last_field_id as i32;
+        if delta > 0 && delta <= 15 {
+            try!(transport.write_u8(((delta as u8) << 4) | compact_type));
+        } else {
+            try!(transport.write_u8(compact_type));
+            try!(self.write_i16(transport, field_id));
+        }
+        self.last_field_id = field_id;
+        Ok(())
+    }
+
+    fn write_collection_begin<T: Transport>(&mut self, transport: &mut T, elem_type: Type, size: usize) -> Result<()> {
+        let compact_type = try!(to_compact_type(elem_type));
+        if size <= 14 {
+            Ok(try!(transport.write_u8(((size as u8) << 4) | compact_type)))
+        } else {
+            try!(transport.write_u8(0xf0 | compact_type));
+            self.write_varint(transport, size as u64)
+        }
+    }
+
+    fn read_collection_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        let header = try!(transport.read_u8());
+        let elem_type = try!(from_compact_type(header & 0x0f));
+        let size = match header >> 4 {
+            0x0f => try!(self.read_size(transport)),
+            size => size as u64,
+        };
+        let size = try!(self.limits.check_container_size(size as i64));
+        try!(check_element_type(elem_type, size));
+        Ok((elem_type, size))
+    }
+}
+
+impl Default for CompactProtocol {
+    fn default() -> CompactProtocol {
+        CompactProtocol::new()
+    }
+}
+
+fn to_compact_type(type_: Type) -> Result<u8> {
+    match type_ {
+        Type::Stop => Ok(0x00),
+        Type::Bool => Ok(COMPACT_BOOLEAN_TRUE),
+        Type::Byte => Ok(0x03),
+        Type::I16 => Ok(0x04),
+        Type::I32 => Ok(0x05),
+        Type::I64 => Ok(0x06),
+        Type::Double => Ok(0x07),
+        Type::String => Ok(0x08),
+        Type::List => Ok(0x09),
+        Type::Set => Ok(0x0a),
+        Type::Map => Ok(0x0b),
+        Type::Struct => Ok(0x0c),
+        Type::Void => Err(Error::from(protocol::Error::ProtocolViolation)),
+    }
+}
+
+fn from_compact_type(compact_type: u8) -> Result<Type> {
+    match compact_type {
+        0x00 => Ok(Type::Stop),
+        0x01 | 0x02 => Ok(Type::Bool),
+        0x03 => Ok(Type::Byte),
+        0x04 => Ok(Type::I16),
+        0x05 => Ok(Type::I32),
+        0x06 => Ok(Type::I64),
+        0x07 => Ok(Type::Double),
+        0x08 => Ok(Type::String),
+        0x09 => Ok(Type::List),
+        0x0a => Ok(Type::Set),
+        0x0b => Ok(Type::Map),
+        0x0c => Ok(Type::Struct),
+        _ => Err(Error::from(protocol::Error::ProtocolViolation)),
+    }
+}
+
+fn i32_to_zigzag(n: i32) -> u64 {
+    ((n << 1) ^ (n >> 31)) as u32 as u64
+}
+
+fn i64_to_zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn zigzag_to_i32(n: u64) -> i32 {
+    let n = n as u32;
+    ((n >> 1) as i32) ^ -((n & 1) as i32)
+}
+
+fn zigzag_to_i64(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+impl Protocol for CompactProtocol {
+    fn write_message_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        name: &str,
+        message_type: MessageType,
+        sequence_id: i32
+    ) -> Result<()> {
+        try!(transport.write_u8(COMPACT_PROTOCOL_ID));
+        try!(transport.write_u8((self.version & COMPACT_VERSION_MASK) |
+                                ((message_type as u8) << COMPACT_TYPE_SHIFT)));
+        try!(self.write_varint(transport, sequence_id as u32 as u64));
+        self.write_str(transport, name)
+    }
+
+    fn write_message_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        Ok(try!(transport.flush()))
+    }
+
+    fn write_struct_begin<T: Transport>(&mut self, _transport: &mut T, _name: &str) -> Result<()> {
+        self.last_field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+        Ok(())
+    }
+
+    fn write_struct_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.last_field_id = self.last_field_ids.pop().unwrap_or(0);
+        Ok(())
+    }
+
+    fn write_field_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        _name: &str,
+        field_type: Type,
+        field_id: i16
+    ) -> Result<()> {
+        match field_type {
+            // the value of a bool field is packed into the field header by write_bool
+            Type::Bool => {
+                self.pending_write_bool_field = Some(field_id);
+                Ok(())
+            }
+            _ => {
+                let compact_type = try!(to_compact_type(field_type));
+                self.write_field_header(transport, compact_type, field_id)
+            }
+        }
+    }
+
+    fn write_field_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_field_stop<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        Ok(try!(transport.write_u8(0x00)))
+    }
+
+    fn write_map_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        key_type: Type,
+        value_type: Type,
+        size: usize
+    ) -> Result<()> {
+        if size == 0 {
+            return Ok(try!(transport.write_u8(0x00)));
+        }
+        try!(self.write_varint(transport, size as u64));
+        let key_type = try!(to_compact_type(key_type));
+        let value_type = try!(to_compact_type(value_type));
+        Ok(try!(transport.write_u8((key_type << 4) | value_type)))
+    }
+
+    fn write_map_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_list_begin<T: Transport>(&mut self, transport: &mut T, elem_type: Type, size: usize) -> Result<()> {
+        self.write_collection_begin(transport, elem_type, size)
+    }
+
+    fn write_list_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_set_begin<T: Transport>(&mut self, transport: &mut T, elem_type: Type, size: usize) -> Result<()> {
+        self.write_collection_begin(transport, elem_type, size)
+    }
+
+    fn write_set_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_bool<T: Transport>(&mut self, transport: &mut T, value: bool) -> Result<()> {
+        let compact_type = if value { COMPACT_BOOLEAN_TRUE } else { COMPACT_BOOLEAN_FALSE };
+        match self.pending_write_bool_field.take() {
+            Some(field_id) => self.write_field_header(transport, compact_type, field_id),
+            None => Ok(try!(transport.write_u8(compact_type))),
+        }
+    }
+
+    fn write_byte<T: Transport>(&mut self, transport: &mut T, value: i8) -> Result<()> {
+        Ok(try!(transport.write_i8(value)))
+    }
+
+    fn write_i16<T: Transport>(&mut self, transport: &mut T, value: i16) -> Result<()> {
+        self.write_varint(transport, i32_to_zigzag(value as i32))
+    }
+
+    fn write_i32<T: Transport>(&mut self, transport: &mut T, value: i32) -> Result<()> {
+        self.write_varint(transport, i32_to_zigzag(value))
+    }
+
+    fn write_i64<T: Transport>(&mut self, transport: &mut T, value: i64) -> Result<()> {
+        self.write_varint(transport, i64_to_zigzag(value))
+    }
+
+    fn write_double<T: Transport>(&mut self, transport: &mut T, value: f64) -> Result<()> {
+        if self.version == COMPACT_PROTOCOL_VERSION_2 {
+            Ok(try!(transport.write_f64::<BigEndian>(value)))
+        } else {
+            Ok(try!(transport.write_f64::<LittleEndian>(value)))
+        }
+    }
+
+    fn write_str<T: Transport>(&mut self, transport: &mut T, value: &str) -> Result<()> {
+        self.write_binary(transport, value.as_bytes())
+    }
+
+    fn write_string<T: Transport>(&mut self, transport: &mut T, value: &String) -> Result<()> {
+        self.write_binary(transport, (&value[..]).as_bytes())
+    }
+
+    fn write_binary<T: Transport>(&mut self, transport: &mut T, value: &[u8]) -> Result<()> {
+        try!(self.write_varint(transport, value.len() as u64));
+        Ok(try!(transport.write_all(value)))
+    }
+
+    fn read_message_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(String, MessageType, i32)> {
+        // a new message starts at the top, even if the last one failed halfway
+        self.depth = 0;
+        self.last_field_ids.clear();
+        self.last_field_id = 0;
+        let protocol_id = try!(transport.read_u8());
+        if protocol_id != COMPACT_PROTOCOL_ID {
+            return Err(Error::from(protocol::Error::BadVersion));
+        }
+        let version_and_type = try!(transport.read_u8());
+        let version = version_and_type & COMPACT_VERSION_MASK;
+        if version != COMPACT_PROTOCOL_VERSION_1 && version != COMPACT_PROTOCOL_VERSION_2 {
+            return Err(Error::from(protocol::Error::BadVersion));
+        }
+        // answer in the flavour the peer is speaking
+        self.version = version;
+        let raw_type = version_and_type >> COMPACT_TYPE_SHIFT;
+        let message_type = match MessageType::from_num(raw_type as u64) {
+            Some(t) => t,
+            None => return Err(Error::from(protocol::Error::ProtocolViolation)),
+        };
+        let sequence_id = try!(self.read_varint(transport)) as u32 as i32;
+        let name = try!(self.read_string(transport));
+        Ok((name, message_type, sequence_id))
+    }
+
+    fn read_message_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_struct_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<String> {
+        try!(self.enter());
+        self.last_field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+        Ok(String::new())
+    }
+
+    fn read_struct_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
+        self.last_field_id = self.last_field_ids.pop().unwrap_or(0);
+        Ok(())
+    }
+
+    fn read_field_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(String, Type, i16)> {
+        let header = try!(transport.read_u8());
+        let compact_type = header & 0x0f;
+        let field_type = try!(from_compact_type(compact_type));
+        if field_type == Type::Stop {
+            return Ok((String::new(), Type::Stop, 0));
+        }
+
+        let field_id = match header >> 4 {
+            0 => try!(self.read_i16(transport)),
+            delta => self.last_field_id.wrapping_add(delta as i16),
+        };
+        self.last_field_id = field_id;
+
+        if field_type == Type::Bool {
+            self.pending_read_bool = Some(compact_type == COMPACT_BOOLEAN_TRUE);
+        }
+        Ok((String::new(), field_type, field_id))
+    }
+
+    fn read_field_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_map_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, Type, i32)> {
+        try!(self.enter());
+        let size = try!(self.read_size(transport));
+        let size = try!(self.limits.check_container_size(size as i64));
+        if size == 0 {
+            return Ok((Type::Stop, Type::Stop, 0));
+        }
+        let types = try!(transport.read_u8());
+        let key_type = try!(from_compact_type(types >> 4));
+        let value_type = try!(from_compact_type(types & 0x0f));
+        try!(check_element_type(key_type, size));
+        try!(check_element_type(value_type, size));
+        Ok((key_type, value_type, size))
+    }
+
+    fn read_map_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
+        Ok(())
+    }
+
+    fn read_list_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        try!(self.enter());
+        self.read_collection_begin(transport)
+    }
+
+    fn read_list_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
+        Ok(())
+    }
+
+    fn read_set_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        try!(self.enter());
+        self.read_collection_begin(transport)
+    }
+
+    fn read_set_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        self.leave();
+        Ok(())
+    }
+
+    fn read_bool<T: Transport>(&mut self, transport: &mut T) -> Result<bool> {
+        match self.pending_read_bool.take() {
+            Some(value) => Ok(value),
+            None => Ok(try!(transport.read_u8()) == COMPACT_BOOLEAN_TRUE),
+        }
+    }
+
+    fn read_byte<T: Transport>(&mut self, transport: &mut T) -> Result<i8> {
+        Ok(try!(transport.read_i8()))
+    }
+
+    fn read_i16<T: Transport>(&mut self, transport: &mut T) -> Result<i16> {
+        Ok(zigzag_to_i32(try!(self.read_varint(transport))) as i16)
+    }
+
+    fn read_i32<T: Transport>(&mut self, transport: &mut T) -> Result<i32> {
+        Ok(zigzag_to_i32(try!(self.read_varint(transport))))
+    }
+
+    fn read_i64<T: Transport>(&mut self, transport: &mut T) -> Result<i64> {
+        Ok(zigzag_to_i64(try!(self.read_varint(transport))))
+    }
+
+    fn read_double<T: Transport>(&mut self, transport: &mut T) -> Result<f64> {
+        if self.version == COMPACT_PROTOCOL_VERSION_2 {
+            Ok(try!(transport.read_f64::<BigEndian>()))
+        } else {
+            Ok(try!(transport.read_f64::<LittleEndian>()))
+        }
+    }
+
+    fn read_string<T: Transport>(&mut self, transport: &mut T) -> Result<String> {
+        let bytes = try!(self.read_binary(transport));
+        Ok(try!(String::from_utf8(bytes).map_err(|e| protocol::Error::from(e.utf8_error()))))
+    }
+
+    fn read_binary<T: Transport>(&mut self, transport: &mut T) -> Result<Vec<u8>> {
+        let len = try!(self.read_size(transport));
+        let len = try!(self.limits.check_string_length(len as i64));
+        read_bytes(transport, len)
+    }
+
+    fn skip<T: Transport>(&mut self, transport: &mut T, type_: Type) -> Result<()> {
+        if log_enabled!(Level::Debug) && type_ != Type::Stop && type_ != Type::Void {
+            let value = try!(read_value(self, transport, type_));
+            debug!("Skipped {:?} {}", type_, value);
+            return Ok(());
+        }
+
+        match type_ {
+            Type::Bool => { try!(self.read_bool(transport)); }
+            Type::Byte => { try!(self.read_byte(transport)); }
+            Type::I16 => { try!(self.read_i16(transport)); }
+            Type::I32 => { try!(self.read_i32(transport)); }
+            Type::I64 => { try!(self.read_i64(transport)); }
+            Type::Double => { try!(self.read_double(transport)); }
+            Type::String => { try!(self.read_binary(transport)); }
+            Type::Struct => {
+                try!(self.read_struct_begin(transport));
+                loop {
+                    let (_, field_type, _) = try!(self.read_field_begin(transport));
+                    if field_type == Type::Stop {
+                        break;
+                    }
+                    try!(self.skip(transport, field_type));
+                    try!(self.read_field_end(transport));
+                }
+                try!(self.read_struct_end(transport));
+            }
+            Type::Map => {
+                let (key_type, value_type, size) = try!(self.read_map_begin(transport));
+                for _ in 0..size {
+                    try!(self.skip(transport, key_type));
+                    try!(self.skip(transport, value_type));
+                }
+                try!(self.read_map_end(transport));
+            }
+            Type::Set => {
+                let (elem_type, size) = try!(self.read_set_begin(transport));
+                for _ in 0..size {
+                    try!(self.skip(transport, elem_type));
+                }
+                try!(self.read_set_end(transport));
+            }
+            Type::List => {
+                let (elem_type, size) = try!(self.read_list_begin(transport));
+                for _ in 0..size {
+                    try!(self.skip(transport, elem_type));
+                }
+                try!(self.read_list_end(transport));
+            }
+            Type::Void => { }
+            Type::Stop => { }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::CompactProtocol;
+    use protocol::{MessageType, Protocol, Type, Value, read_value, write_value};
+    use transport::memory_transport::MemoryTransport;
+
+    fn round_trip(protocol: CompactProtocol, value: &Value) -> (Vec<u8>, Value) {
+        let mut writer = protocol.clone();
+        let mut transport = MemoryTransport::new();
+        write_value(&mut writer, &mut transport, value).unwrap();
+        let written = transport.take_written();
+
+        let mut reader = protocol;
+        let mut transport = MemoryTransport::with_input(written.clone());
+        let read = read_value(&mut reader, &mut transport, value.typ()).unwrap();
+        assert!(transport.remaining().is_empty());
+        (written, read)
+    }
+
+    fn struct_of(fields: Vec<(i16, Value)>) -> Value {
+        Value::Struct(fields.into_iter().collect::<BTreeMap<_, _>>())
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        let value = struct_of(vec![(1, Value::Bool(true)),
+                                   (2, Value::Bool(false)),
+                                   (3, Value::Byte(-128)),
+                                   (4, Value::I16(i16::MIN)),
+                                   (5, Value::I32(i32::MIN)),
+                                   (6, Value::I64(i64::MIN)),
+                                   (7, Value::Double(-1.5)),
+                                   (8, Value::Binary(b"torc".to_vec())),
+                                   (9, struct_of(vec![(1, Value::I32(-1)), (40, Value::Bool(true))])),
+                                   (10, Value::List(Type::Bool, vec![Value::Bool(true), Value::Bool(false)])),
+                                   (11, Value::Set(Type::I64, vec![Value::I64(i64::MAX), Value::I64(-2)])),
+                                   (12, Value::Map(Type::String, Type::Double, vec![(Value::Binary(b"a".to_vec()),
+                                                                                    Value::Double(0.25))])),
+                                   (14, Value::List(Type::Struct, vec![])),
+                                   (300, Value::I32(i32::MAX))]);
+        for version in &[1, 2] {
+            let (_, read) = round_trip(CompactProtocol::with_version(*version), &value);
+            assert_eq!(read, value);
+        }
+
+        // an empty map is a single byte, without the key and value types
+        let (written, read) = round_trip(CompactProtocol::new(), &Value::Map(Type::I32, Type::I32, vec![]));
+        assert_eq!(written, vec![0x00]);
+        assert_eq!(read, Value::Map(Type::Stop, Type::Stop, vec![]));
+    }
+
+    #[test]
+    fn encodes_negative_values_as_zigzag_varints() {
+        let (written, _) = round_trip(CompactProtocol::new(), &Value::I32(-1));
+        assert_eq!(written, vec![0x01]);
+        let (written, _) = round_trip(CompactProtocol::new(), &Value::I64(-64));
+        assert_eq!(written, vec![0x7f]);
+        let (written, _) = round_trip(CompactProtocol::new(), &Value::I16(-65));
+        assert_eq!(written, vec![0x81, 0x01]);
+        for value in &[0, -1, 1, -300, i64::MIN, i64::MAX] {
+            let (_, read) = round_trip(CompactProtocol::new(), &Value::I64(*value));
+            assert_eq!(read, Value::I64(*value));
+        }
+    }
+
+    #[test]
+    fn round_trips_field_id_deltas() {
+        let mut protocol = CompactProtocol::new();
+        let mut transport = MemoryTransport::new();
+        let ids = [1i16, 16, 40, 3, -5, 32767, 20];
+        protocol.write_struct_begin(&mut transport, "deltas").unwrap();
+        for (index, id) in ids.iter().enumerate() {
+            if index % 2 == 0 {
+                protocol.write_field_begin(&mut transport, "", Type::Bool, *id).unwrap();
+                protocol.write_bool(&mut transport, index % 4 == 0).unwrap();
+            } else {
+                protocol.write_field_begin(&mut transport, "", Type::I32, *id).unwrap();
+                protocol.write_i32(&mut transport, -(*id as i32)).unwrap();
+            }
+            protocol.write_field_end(&mut transport).unwrap();
+        }
+        protocol.write_field_stop(&mut transport).unwrap();
+        protocol.write_struct_end(&mut transport).unwrap();
+
+        let written = transport.take_written();
+        // field 1 fits in the header, field 16 is 15 further on and fits too, field 40 does not
+        assert_eq!(&written[..3], &[0x11, 0xf5, 0x1f]);
+        assert_eq!(written[3], 0x02);
+
+        let mut protocol = CompactProtocol::new();
+        let mut transport = MemoryTransport::with_input(written);
+        protocol.read_struct_begin(&mut transport).unwrap();
+        for (index, id) in ids.iter().enumerate() {
+            let (_, field_type, field_id) = protocol.read_field_begin(&mut transport).unwrap();
+            assert_eq!(field_id, *id);
+            if index % 2 == 0 {
+                assert_eq!(field_type, Type::Bool);
+                assert_eq!(protocol.read_bool(&mut transport).unwrap(), index % 4 == 0);
+            } else {
+                assert_eq!(field_type, Type::I32);
+                assert_eq!(protocol.read_i32(&mut transport).unwrap(), -(*id as i32));
+            }
+            protocol.read_field_end(&mut transport).unwrap();
+        }
+        assert_eq!(protocol.read_field_begin(&mut transport).unwrap().1, Type::Stop);
+        protocol.read_struct_end(&mut transport).unwrap();
+    }
+
+    #[test]
+    fn round_trips_long_containers() {
+        let list = Value::List(Type::I32, (0..1000).map(|n| Value::I32(n - 500)).collect());
+        let (written, read) = round_trip(CompactProtocol::new(), &list);
+        // sizes above 14 move to a varint after the header
+        assert_eq!(&written[..3], &[0xf5, 0xe8, 0x07]);
+        assert_eq!(read, list);
+
+        let map = Value::Map(Type::I16,
+                             Type::String,
+                             (0..300).map(|n| (Value::I16(n), Value::Binary(vec![n as u8; 3]))).collect());
+        let (written, read) = round_trip(CompactProtocol::new(), &map);
+        assert_eq!(&written[..3], &[0xac, 0x02, 0x48]);
+        assert_eq!(read, map);
+
+        let set = Value::Set(Type::Bool, (0..15).map(|n| Value::Bool(n % 3 == 0)).collect());
+        let (written, read) = round_trip(CompactProtocol::new(), &set);
+        assert_eq!(&written[..2], &[0xf1, 0x0f]);
+        assert_eq!(read, set);
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let mut protocol = CompactProtocol::with_version(2);
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, "getRoutes", MessageType::Reply, -7).unwrap();
+        protocol.write_double(&mut transport, 1.0).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+
+        let written = transport.take_written();
+        assert_eq!(&written[..2], &[0x82, 0x42]);
+        // version 2 sends doubles big endian
+        assert_eq!(&written[written.len() - 8..], &[0x3f, 0xf0, 0, 0, 0, 0, 0, 0]);
+
+        let mut protocol = CompactProtocol::new();
+        let mut transport = MemoryTransport::with_input(written);
+        let (name, message_type, sequence_id) = protocol.read_message_begin(&mut transport).unwrap();
+        assert_eq!(name, "getRoutes");
+        assert_eq!(message_type, MessageType::Reply);
+        assert_eq!(sequence_id, -7);
+        assert_eq!(protocol.read_double(&mut transport).unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,426 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//! `Encode` and `Decode` for the rust types with a direct thrift representation
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+use protocol::{Protocol, Type, ThriftTyped, Encode, Decode, Error};
+use transport::Transport;
+use Result;
+
+macro_rules! primitive {
+    ($ty:ty, $typ:expr, $write:ident, $read:ident) => {
+        impl ThriftTyped for $ty {
+            fn typ() -> Type {
+                $typ
+            }
+        }
+
+        impl Encode for $ty {
+            fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+            where P: Protocol, T: Transport {
+                protocol.$write(transport, *self)
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+            where P: Protocol, T: Transport {
+                *self = try!(protocol.$read(transport));
+                Ok(())
+            }
+        }
+    }
+}
+
+primitive!(bool, Type::Bool, write_bool, read_bool);
+primitive!(i8, Type::Byte, write_byte, read_byte);
+primitive!(i16, Type::I16, write_i16, read_i16);
+primitive!(i32, Type::I32, write_i32, read_i32);
+primitive!(i64, Type::I64, write_i64, read_i64);
+primitive!(f64, Type::Double, write_double, read_double);
+
+impl ThriftTyped for String {
+    fn typ() -> Type {
+        Type::String
+    }
+}
+
+impl Encode for String {
+    fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        protocol.write_str(transport, self)
+    }
+}
+
+impl Decode for String {
+    fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        *self = try!(protocol.read_string(transport));
+        Ok(())
+    }
+}
+
+/// `Vec<u8>` is thrift's `binary`, not a list of bytes, which is `Vec<i8>`
+impl ThriftTyped for Vec<u8> {
+    fn typ() -> Type {
+        Type::String
+    }
+}
+
+impl Encode for Vec<u8> {
+    fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        protocol.write_binary(transport, self)
+    }
+}
+
+impl Decode for Vec<u8> {
+    fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        *self = try!(protocol.read_binary(transport));
+        Ok(())
+    }
+}
+
+/// Optional fields, `None` is not written at all, see `helpers::write_field`
+impl<V: ThriftTyped> ThriftTyped for Option<V> {
+    fn typ() -> Type {
+        V::typ()
+    }
+}
+
+impl<V: Encode> Encode for Option<V> {
+    fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        match *self {
+            Some(ref value) => value.encode(protocol, transport),
+            None => Ok(()),
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.is_some()
+    }
+}
+
+impl<V: Decode> Decode for Option<V> {
+    fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        *self = Some(try!(read_element(protocol, transport)));
+        Ok(())
+    }
+}
+
+fn read_element<V, P, T>(protocol: &mut P, transport: &mut T) -> Result<V>
+where V: Decode, P: Protocol, T: Transport {
+    let mut value = V::default();
+    try!(value.decode(protocol, transport));
+    Ok(value)
+}
+
+/// Elements of another type than expected can't be decoded, and skipping them would hide the mismatch
+pub fn check_element_type(expected: Type, actual: Type, size: i32) -> Result<()> {
+    if size > 0 && expected != actual {
+        return Err(::Error::from(Error::ProtocolViolation));
+    }
+    Ok(())
+}
+
+impl<V: ThriftTyped> ThriftTyped for Vec<V> {
+    fn typ() -> Type {
+        Type::List
+    }
+}
+
+impl<V: Encode> Encode for Vec<V> {
+    fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        try!(protocol.write_list_begin(transport, V::typ(), self.len()));
+        for value in self {
+            try!(value.encode(protocol, transport));
+        }
+        protocol.write_list_end(transport)
+    }
+}
+
+impl<V: Decode> Decode for Vec<V> {
+    fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+    where P: Protocol, T: Transport {
+        let (element_type, size) = try!(protocol.read_list_begin(transport));
+        try!(check_element_type(V::typ(), element_type, size));
+        self.clear();
+        for _ in 0..size {
+            self.push(try!(read_element(protocol, transport)));
+        }
+        protocol.read_list_end(transport)
+    }
+}
+
+macro_rules! set {
+    ($set:ident, $($bound:ident),+) => {
+        impl<V: ThriftTyped> ThriftTyped for $set<V> {
+            fn typ() -> Type {
+                Type::Set
+            }
+        }
+
+        impl<V: Encode> Encode for $set<V> {
+            fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+            where P: Protocol, T: Transport {
+                try!(protocol.write_set_begin(transport, V::typ(), self.len()));
+                for value in self {
+                    try!(value.encode(protocol, transport));
+                }
+                protocol.write_set_end(transport)
+            }
+        }
+
+        impl<V: Decode $(+ $bound)+> Decode for $set<V> {
+            fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+            where P: Protocol, T: Transport {
+                let (element_type, size) = try!(protocol.read_set_begin(transport));
+                try!(check_element_type(V::typ(), element_type, size));
+                self.clear();
+                for _ in 0..size {
+                    self.insert(try!(read_element(protocol, transport)));
+                }
+                protocol.read_set_end(transport)
+            }
+        }
+    }
+}
+
+set!(HashSet, Eq, Hash);
+set!(BTreeSet, Ord);
+
+macro_rules! map {
+    ($map:ident, $($bound:ident),+) => {
+        impl<K: ThriftTyped, V: ThriftTyped> ThriftTyped for $map<K, V> {
+            fn typ() -> Type {
+                Type::Map
+            }
+        }
+
+        impl<K: Encode, V: Encode> Encode for $map<K, V> {
+            fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+            where P: Protocol, T: Transport {
+                try!(protocol.write_map_begin(transport, K::typ(), V::typ(), self.len()));
+                for (key, value) in self {
+                    try!(key.encode(protocol, transport));
+                    try!(value.encode(protocol, transport));
+                }
+                protocol.write_map_end(transport)
+            }
+        }
+
+        impl<K: Decode $(+ $bound)+, V: Decode> Decode for $map<K, V> {
+            fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+            where P: Protocol, T: Transport {
+                let (key_type, value_type, size) = try!(protocol.read_map_begin(transport));
+                try!(check_element_type(K::typ(), key_type, size));
+                try!(check_element_type(V::typ(), value_type, size));
+                self.clear();
+                for _ in 0..size {
+                    let key = try!(read_element(protocol, transport));
+                    let value = try!(read_element(protocol, transport));
+                    self.insert(key, value);
+                }
+                protocol.read_map_end(transport)
+            }
+        }
+    }
+}
+
+map!(HashMap, Eq, Hash);
+map!(BTreeMap, Ord);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::fmt::Debug;
+
+    use super::check_element_type;
+    use protocol::{Decode, Encode, Error, Protocol, ThriftTyped, Type, Value, read_value, write_value};
+    use protocol::binary_protocol::BinaryProtocol;
+    use protocol::compact_protocol::CompactProtocol;
+    use transport::memory_transport::MemoryTransport;
+
+    fn encode<V: Encode>(value: &V) -> Vec<u8> {
+        let mut transport = MemoryTransport::new();
+        value.encode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+        transport.take_written()
+    }
+
+    fn decode<V: Decode>(bytes: Vec<u8>) -> ::Result<V> {
+        let mut transport = MemoryTransport::with_input(bytes);
+        let mut value = V::default();
+        try!(value.decode(&mut BinaryProtocol::new(), &mut transport));
+        assert!(transport.remaining().is_empty());
+        Ok(value)
+    }
+
+    fn round_trip_with<P, V>(mut protocol: P, value: &V)
+        where P: Protocol,
+              V: Encode + Decode + PartialEq + Debug
+    {
+        let mut transport = MemoryTransport::new();
+        value.encode(&mut protocol, &mut transport).unwrap();
+        let mut transport = MemoryTransport::with_input(transport.take_written());
+        let mut decoded = V::default();
+        decoded.decode(&mut protocol, &mut transport).unwrap();
+        assert_eq!(&decoded, value);
+        assert!(transport.remaining().is_empty());
+    }
+
+    fn round_trip<V: Encode + Decode + PartialEq + Debug>(values: &[V]) {
+        for value in values {
+            round_trip_with(BinaryProtocol::new(), value);
+            round_trip_with(CompactProtocol::new(), value);
+        }
+    }
+
+    /// The generic value written by `Encode`, read back with the type the impl declares
+    fn as_value<V: Encode>(value: &V) -> Value {
+        let mut transport = MemoryTransport::with_input(encode(value));
+        read_value(&mut BinaryProtocol::new(), &mut transport, V::typ()).unwrap()
+    }
+
+    fn encode_value(value: &Value) -> Vec<u8> {
+        let mut transport = MemoryTransport::new();
+        write_value(&mut BinaryProtocol::new(), &mut transport, value).unwrap();
+        transport.take_written()
+    }
+
+    fn assert_violation<V: Decode + Debug>(value: &Value) {
+        match decode::<V>(encode_value(value)) {
+            Err(::Error::ProtocolError(Error::ProtocolViolation)) => {}
+            other => panic!("{}: unexpected result {:?}", value, other),
+        }
+    }
+
+    #[test]
+    fn round_trips_primitives() {
+        round_trip(&[false, true]);
+        round_trip(&[i8::min_value(), -1, 0, i8::max_value()]);
+        round_trip(&[i16::min_value(), -1, 0, i16::max_value()]);
+        round_trip(&[i32::min_value(), -1, 0, i32::max_value()]);
+        round_trip(&[i64::min_value(), -1, 0, i64::max_value()]);
+        round_trip(&[0.0, -0.5, 1e300, ::std::f64::INFINITY, ::std::f64::NEG_INFINITY]);
+        round_trip(&["".to_string(), "eth1/3/1".to_string(), "Schalter ✓".to_string()]);
+
+        assert_eq!(bool::typ(), Type::Bool);
+        assert_eq!(i8::typ(), Type::Byte);
+        assert_eq!(i16::typ(), Type::I16);
+        assert_eq!(i32::typ(), Type::I32);
+        assert_eq!(i64::typ(), Type::I64);
+        assert_eq!(f64::typ(), Type::Double);
+        assert_eq!(String::typ(), Type::String);
+    }
+
+    #[test]
+    fn writes_primitives_in_binary_encoding() {
+        assert_eq!(encode(&true), vec![1]);
+        assert_eq!(encode(&-2i8), vec![0xfe]);
+        assert_eq!(encode(&0x0102i16), vec![1, 2]);
+        assert_eq!(encode(&0x01020304i32), vec![1, 2, 3, 4]);
+        assert_eq!(encode(&-1i64), vec![0xff; 8]);
+        assert_eq!(encode(&1.0f64), vec![0x3f, 0xf0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encode(&"ab".to_string()), vec![0, 0, 0, 2, b'a', b'b']);
+    }
+
+    #[test]
+    fn writes_bytes_as_binary_and_signed_bytes_as_list() {
+        let binary: Vec<u8> = vec![0, 0x80, 0xff];
+        assert_eq!(Vec::<u8>::typ(), Type::String);
+        assert_eq!(encode(&binary), vec![0, 0, 0, 3, 0, 0x80, 0xff]);
+        assert_eq!(as_value(&binary), Value::Binary(binary.clone()));
+        round_trip(&[binary, vec![]]);
+
+        let bytes: Vec<i8> = vec![0, -128, -1];
+        assert_eq!(Vec::<i8>::typ(), Type::List);
+        assert_eq!(as_value(&bytes),
+                   Value::List(Type::Byte, vec![Value::Byte(0), Value::Byte(-128), Value::Byte(-1)]));
+        round_trip(&[bytes, vec![]]);
+
+        // binary holding invalid UTF-8 is no string
+        assert!(decode::<String>(encode(&vec![0xffu8])).is_err());
+    }
+
+    #[test]
+    fn round_trips_containers() {
+        round_trip(&[vec![1i32, 2, 3], vec![]]);
+        round_trip(&[vec![vec!["a".to_string()], vec![]]]);
+        round_trip(&[vec![1i64, 2].into_iter().collect::<BTreeSet<_>>(), BTreeSet::new()]);
+        round_trip(&[vec!["a".to_string(), "b".to_string()].into_iter().collect::<HashSet<_>>()]);
+        round_trip(&[vec![(1i16, "one".to_string())].into_iter().collect::<BTreeMap<_, _>>(), BTreeMap::new()]);
+        round_trip(&[vec![("lanes".to_string(), vec![1i32, 2])].into_iter().collect::<HashMap<_, _>>()]);
+        let labels: BTreeSet<i64> = vec![2].into_iter().collect();
+        round_trip(&[vec![(1i32, labels)].into_iter().collect::<BTreeMap<_, _>>()]);
+
+        assert_eq!(as_value(&vec![2i64, 1].into_iter().collect::<BTreeSet<_>>()),
+                   Value::Set(Type::I64, vec![Value::I64(1), Value::I64(2)]));
+        assert_eq!(as_value(&vec![(1i16, true)].into_iter().collect::<BTreeMap<_, _>>()),
+                   Value::Map(Type::I16, Type::Bool, vec![(Value::I16(1), Value::Bool(true))]));
+    }
+
+    #[test]
+    fn decoding_replaces_previous_contents() {
+        let mut transport = MemoryTransport::with_input(encode(&vec![3i32]));
+        let mut value = vec![1i32, 2];
+        value.decode(&mut BinaryProtocol::new(), &mut transport).unwrap();
+        assert_eq!(value, vec![3]);
+    }
+
+    #[test]
+    fn writes_options_only_when_set() {
+        assert!(encode(&None::<i32>).is_empty());
+        assert!(!None::<i32>.is_set());
+        assert_eq!(encode(&Some(7i32)), encode(&7i32));
+        assert!(Some(7i32).is_set());
+        assert_eq!(Option::<String>::typ(), Type::String);
+        assert_eq!(decode::<Option<i32>>(encode(&7i32)).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn rejects_mismatched_element_types() {
+        let strings = Value::List(Type::String, vec![Value::from("1")]);
+        assert_violation::<Vec<i32>>(&strings);
+        assert_violation::<Vec<i8>>(&strings);
+        assert_violation::<Vec<Vec<i32>>>(&Value::List(Type::List, vec![strings.clone()]));
+        assert_violation::<BTreeSet<i64>>(&Value::Set(Type::I32, vec![Value::I32(1)]));
+        assert_violation::<HashSet<String>>(&Value::Set(Type::I32, vec![Value::I32(1)]));
+        assert_violation::<BTreeMap<i32, String>>(&Value::Map(Type::I64, Type::String,
+                                                               vec![(Value::I64(1), Value::from("a"))]));
+        assert_violation::<HashMap<i32, String>>(&Value::Map(Type::I32, Type::I32,
+                                                              vec![(Value::I32(1), Value::I32(2))]));
+
+        // empty containers carry no elements that could be misread
+        assert_eq!(decode::<Vec<i32>>(encode_value(&Value::List(Type::String, vec![]))).unwrap(), vec![]);
+        assert!(decode::<BTreeMap<i32, String>>(encode_value(&Value::Map(Type::Bool, Type::Bool, vec![])))
+            .unwrap()
+            .is_empty());
+
+        assert!(check_element_type(Type::I32, Type::I32, 3).is_ok());
+        assert!(check_element_type(Type::I32, Type::String, 0).is_ok());
+        assert!(check_element_type(Type::I32, Type::String, 1).is_err());
+    }
+}
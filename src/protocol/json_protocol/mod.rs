@@ -0,0 +1,1281 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//! Thrift's JSON protocol, `JsonProtocol`, and the write-only `SimpleJsonProtocol`
+//!
+//! `JsonProtocol` writes fields by id with their type, like
+//! `{"1":{"i32":5},"2":{"lst":["str",2,"eth1","eth2"]}}`, and reads back everything it wrote.
+//! `SimpleJsonProtocol` writes fields by name, like `{"port":5,"names":["eth1","eth2"]}`,
+//! which is easier to read but can't be decoded without knowing the field ids.
+
+use protocol::{self, Limits, MessageType, Protocol, Type};
+use transport::Transport;
+use {Result, Error};
+
+use podio::ReadPodExt;
+
+static JSON_PROTOCOL_VERSION: i64 = 1;
+
+// longer numbers are no numbers thrift writes
+static MAX_NUMBER_LENGTH: usize = 128;
+
+static BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Copy, Clone, Debug)]
+enum Context {
+    /// Values separated by commas
+    List { first: bool },
+    /// Keys and values separated by colons, pairs by commas
+    Pair { first: bool, colon: bool },
+}
+
+/// Separator to put before the next value of the innermost context
+fn next_separator(contexts: &mut [Context]) -> Option<u8> {
+    match contexts.last_mut() {
+        None => None,
+        Some(&mut Context::List { ref mut first }) => {
+            if *first {
+                *first = false;
+                None
+            } else {
+                Some(b',')
+            }
+        }
+        Some(&mut Context::Pair { ref mut first, ref mut colon }) => {
+            if *first {
+                *first = false;
+                *colon = true;
+                None
+            } else {
+                let separator = if *colon { b':' } else { b',' };
+                *colon = !*colon;
+                Some(separator)
+            }
+        }
+    }
+}
+
+/// Whether the value after the last separator is the key of a pair, keys are always strings
+fn in_key(contexts: &[Context]) -> bool {
+    match contexts.last() {
+        Some(&Context::Pair { colon, .. }) => colon,
+        _ => false,
+    }
+}
+
+fn violation() -> Error {
+    Error::from(protocol::Error::ProtocolViolation)
+}
+
+fn narrow(value: i64, min: i64, max: i64) -> Result<i64> {
+    if value < min || value > max {
+        return Err(violation());
+    }
+    Ok(value)
+}
+
+fn type_name(type_: Type) -> Result<&'static str> {
+    match type_ {
+        Type::Bool => Ok("tf"),
+        Type::Byte => Ok("i8"),
+        Type::I16 => Ok("i16"),
+        Type::I32 => Ok("i32"),
+        Type::I64 => Ok("i64"),
+        Type::Double => Ok("dbl"),
+        Type::String => Ok("str"),
+        Type::Struct => Ok("rec"),
+        Type::Map => Ok("map"),
+        Type::Set => Ok("set"),
+        Type::List => Ok("lst"),
+        Type::Stop | Type::Void => Err(violation()),
+    }
+}
+
+fn type_from_name(name: &[u8]) -> Result<Type> {
+    match name {
+        b"tf" => Ok(Type::Bool),
+        b"i8" => Ok(Type::Byte),
+        b"i16" => Ok(Type::I16),
+        b"i32" => Ok(Type::I32),
+        b"i64" => Ok(Type::I64),
+        b"dbl" => Ok(Type::Double),
+        b"str" => Ok(Type::String),
+        b"rec" => Ok(Type::Struct),
+        b"map" => Ok(Type::Map),
+        b"set" => Ok(Type::Set),
+        b"lst" => Ok(Type::List),
+        _ => Err(violation()),
+    }
+}
+
+/// Base64 without padding, like the other thrift implementations write it
+fn encode_base64(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = chunk.get(1).map_or(0, |&b| b as usize);
+        let b2 = chunk.get(2).map_or(0, |&b| b as usize);
+        encoded.push(BASE64_ALPHABET[b0 >> 2]);
+        encoded.push(BASE64_ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)]);
+        if chunk.len() > 1 {
+            encoded.push(BASE64_ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)]);
+        }
+        if chunk.len() > 2 {
+            encoded.push(BASE64_ALPHABET[b2 & 0x3f]);
+        }
+    }
+    encoded
+}
+
+/// Base64 with or without padding
+fn decode_base64(encoded: &[u8]) -> Result<Vec<u8>> {
+    let mut end = encoded.len();
+    while end > 0 && encoded[end - 1] == b'=' {
+        end -= 1;
+    }
+    let mut decoded = Vec::with_capacity(end / 4 * 3 + 2);
+    for chunk in encoded[..end].chunks(4) {
+        if chunk.len() == 1 {
+            return Err(violation());
+        }
+        let mut bits = 0u32;
+        for &c in chunk {
+            let sextet = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return Err(violation()),
+            };
+            bits = (bits << 6) | sextet as u32;
+        }
+        bits <<= 6 * (4 - chunk.len()) as u32;
+        decoded.push((bits >> 16) as u8);
+        if chunk.len() > 2 {
+            decoded.push((bits >> 8) as u8);
+        }
+        if chunk.len() > 3 {
+            decoded.push(bits as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+/// Writes JSON values, with the separators the nesting calls for
+#[derive(Clone, Debug, Default)]
+struct JsonWriter {
+    contexts: Vec<Context>,
+}
+
+impl JsonWriter {
+    /// Whether the next value is the key of a pair
+    fn at_key(&self) -> bool {
+        match self.contexts.last() {
+            Some(&Context::Pair { first, colon }) => first || !colon,
+            _ => false,
+        }
+    }
+
+    fn write_separator<T: Transport>(&mut self, transport: &mut T) -> Result<bool> {
+        if let Some(separator) = next_separator(&mut self.contexts) {
+            try!(transport.write_all(&[separator]));
+        }
+        Ok(in_key(&self.contexts))
+    }
+
+    fn write_string<T: Transport>(&mut self, transport: &mut T, value: &[u8]) -> Result<()> {
+        try!(self.write_separator(transport));
+        let mut escaped = Vec::with_capacity(value.len() + 2);
+        escaped.push(b'"');
+        for &b in value {
+            match b {
+                b'"' => escaped.extend_from_slice(b"\\\""),
+                b'\\' => escaped.extend_from_slice(b"\\\\"),
+                b'\n' => escaped.extend_from_slice(b"\\n"),
+                b'\r' => escaped.extend_from_slice(b"\\r"),
+                b'\t' => escaped.extend_from_slice(b"\\t"),
+                0x08 => escaped.extend_from_slice(b"\\b"),
+                0x0c => escaped.extend_from_slice(b"\\f"),
+                0x00..=0x1f => escaped.extend_from_slice(format!("\\u{:04x}", b).as_bytes()),
+                _ => escaped.push(b),
+            }
+        }
+        escaped.push(b'"');
+        Ok(try!(transport.write_all(&escaped)))
+    }
+
+    /// Numbers and literals, quoted when they are keys
+    fn write_token<T: Transport>(&mut self, transport: &mut T, token: &str) -> Result<()> {
+        if try!(self.write_separator(transport)) {
+            Ok(try!(write!(transport, "\"{}\"", token)))
+        } else {
+            Ok(try!(transport.write_all(token.as_bytes())))
+        }
+    }
+
+    fn write_integer<T: Transport>(&mut self, transport: &mut T, value: i64) -> Result<()> {
+        self.write_token(transport, &value.to_string())
+    }
+
+    fn write_double<T: Transport>(&mut self, transport: &mut T, value: f64) -> Result<()> {
+        if value.is_nan() {
+            self.write_string(transport, b"NaN")
+        } else if value.is_infinite() {
+            self.write_string(transport, if value > 0.0 { b"Infinity" } else { b"-Infinity" })
+        } else {
+            self.write_token(transport, &format!("{:?}", value))
+        }
+    }
+
+    fn write_base64<T: Transport>(&mut self, transport: &mut T, value: &[u8]) -> Result<()> {
+        try!(self.write_separator(transport));
+        try!(transport.write_all(b"\""));
+        try!(transport.write_all(&encode_base64(value)));
+        Ok(try!(transport.write_all(b"\"")))
+    }
+
+    fn write_object_begin<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.write_separator(transport));
+        try!(transport.write_all(b"{"));
+        self.contexts.push(Context::Pair { first: true, colon: false });
+        Ok(())
+    }
+
+    fn write_object_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.contexts.pop();
+        Ok(try!(transport.write_all(b"}")))
+    }
+
+    fn write_array_begin<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.write_separator(transport));
+        try!(transport.write_all(b"["));
+        self.contexts.push(Context::List { first: true });
+        Ok(())
+    }
+
+    fn write_array_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.contexts.pop();
+        Ok(try!(transport.write_all(b"]")))
+    }
+}
+
+/// Reads JSON values written by `JsonWriter`, whitespace between tokens is ignored
+#[derive(Clone, Debug, Default)]
+struct JsonReader {
+    contexts: Vec<Context>,
+    peeked: Option<u8>,
+    limits: Limits,
+}
+
+impl JsonReader {
+    fn next<T: Transport>(&mut self, transport: &mut T) -> Result<u8> {
+        match self.peeked.take() {
+            Some(b) => Ok(b),
+            None => Ok(try!(transport.read_u8())),
+        }
+    }
+
+    /// The next byte which isn't whitespace, without consuming it
+    fn peek<T: Transport>(&mut self, transport: &mut T) -> Result<u8> {
+        loop {
+            let b = try!(self.next(transport));
+            match b {
+                b' ' | b'\t' | b'\r' | b'\n' => continue,
+                _ => {
+                    self.peeked = Some(b);
+                    return Ok(b);
+                }
+            }
+        }
+    }
+
+    fn expect<T: Transport>(&mut self, transport: &mut T, expected: u8) -> Result<()> {
+        try!(self.peek(transport));
+        if try!(self.next(transport)) != expected {
+            return Err(violation());
+        }
+        Ok(())
+    }
+
+    fn read_separator<T: Transport>(&mut self, transport: &mut T) -> Result<bool> {
+        if let Some(separator) = next_separator(&mut self.contexts) {
+            try!(self.expect(transport, separator));
+        }
+        Ok(in_key(&self.contexts))
+    }
+
+    fn read_string<T: Transport>(&mut self, transport: &mut T) -> Result<Vec<u8>> {
+        try!(self.read_separator(transport));
+        let max_length = self.limits.max_string_length;
+        self.read_quoted(transport, max_length)
+    }
+
+    fn read_quoted<T: Transport>(&mut self, transport: &mut T, max_length: usize) -> Result<Vec<u8>> {
+        try!(self.expect(transport, b'"'));
+        let mut value = Vec::new();
+        loop {
+            if value.len() > max_length {
+                return Err(Error::from(protocol::Error::SizeLimit));
+            }
+            match try!(self.next(transport)) {
+                b'"' if value.len() > max_length => return Err(Error::from(protocol::Error::SizeLimit)),
+                b'"' => return Ok(value),
+                b'\\' => {
+                    match try!(self.next(transport)) {
+                        b'"' => value.push(b'"'),
+                        b'\\' => value.push(b'\\'),
+                        b'/' => value.push(b'/'),
+                        b'b' => value.push(0x08),
+                        b'f' => value.push(0x0c),
+                        b'n' => value.push(b'\n'),
+                        b'r' => value.push(b'\r'),
+                        b't' => value.push(b'\t'),
+                        b'u' => {
+                            let c = try!(self.read_escaped_char(transport));
+                            let mut buf = [0u8; 4];
+                            value.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        }
+                        _ => return Err(violation()),
+                    }
+                }
+                b => value.push(b),
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, surrogate pairs take two escapes
+    fn read_escaped_char<T: Transport>(&mut self, transport: &mut T) -> Result<char> {
+        let high = try!(self.read_hex4(transport));
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if try!(self.next(transport)) != b'\\' || try!(self.next(transport)) != b'u' {
+                return Err(violation());
+            }
+            let low = try!(self.read_hex4(transport));
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(violation());
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        ::std::char::from_u32(code).ok_or_else(violation)
+    }
+
+    fn read_hex4<T: Transport>(&mut self, transport: &mut T) -> Result<u32> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = match try!(self.next(transport)) {
+                b @ b'0'..=b'9' => b - b'0',
+                b @ b'a'..=b'f' => b - b'a' + 10,
+                b @ b'A'..=b'F' => b - b'A' + 10,
+                _ => return Err(violation()),
+            };
+            value = (value << 4) | digit as u32;
+        }
+        Ok(value)
+    }
+
+    /// A number after its separator, quoted when it is a key
+    fn read_token<T: Transport>(&mut self, transport: &mut T) -> Result<String> {
+        if in_key(&self.contexts) {
+            let token = try!(self.read_quoted(transport, MAX_NUMBER_LENGTH));
+            return String::from_utf8(token).map_err(|_| violation());
+        }
+        let mut token = String::new();
+        loop {
+            let b = try!(self.peek(transport));
+            match b {
+                b'+' | b'-' | b'.' | b'0'..=b'9' | b'e' | b'E' if token.len() < MAX_NUMBER_LENGTH => {
+                    self.peeked = None;
+                    token.push(b as char);
+                }
+                _ => return Ok(token),
+            }
+        }
+    }
+
+    fn read_integer<T: Transport>(&mut self, transport: &mut T) -> Result<i64> {
+        try!(self.read_separator(transport));
+        let token = try!(self.read_token(transport));
+        token.parse().map_err(|_| violation())
+    }
+
+    fn read_double<T: Transport>(&mut self, transport: &mut T) -> Result<f64> {
+        let key = try!(self.read_separator(transport));
+        let token = if !key && try!(self.peek(transport)) == b'"' {
+            try!(String::from_utf8(try!(self.read_quoted(transport, MAX_NUMBER_LENGTH))).map_err(|_| violation()))
+        } else {
+            try!(self.read_token(transport))
+        };
+        match &token[..] {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            // rust would also parse quoted spellings like "inf" or "nan"
+            _ if token.bytes().all(|b| matches!(b, b'+' | b'-' | b'.' | b'0'..=b'9' | b'e' | b'E')) => {
+                token.parse().map_err(|_| violation())
+            }
+            _ => Err(violation()),
+        }
+    }
+
+    fn read_base64<T: Transport>(&mut self, transport: &mut T) -> Result<Vec<u8>> {
+        try!(self.read_separator(transport));
+        let max_length = self.limits.max_string_length.saturating_add(2) / 3 * 4;
+        let encoded = try!(self.read_quoted(transport, max_length));
+        let decoded = try!(decode_base64(&encoded));
+        try!(self.limits.check_string_length(decoded.len() as i64));
+        Ok(decoded)
+    }
+
+    fn read_object_begin<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.read_separator(transport));
+        try!(self.expect(transport, b'{'));
+        self.contexts.push(Context::Pair { first: true, colon: false });
+        Ok(())
+    }
+
+    fn read_object_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.expect(transport, b'}'));
+        self.contexts.pop();
+        Ok(())
+    }
+
+    fn read_array_begin<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.read_separator(transport));
+        try!(self.expect(transport, b'['));
+        self.contexts.push(Context::List { first: true });
+        Ok(())
+    }
+
+    fn read_array_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.expect(transport, b']'));
+        self.contexts.pop();
+        Ok(())
+    }
+}
+
+/// Thrift's JSON protocol, binaries are written as base64 strings
+///
+/// Strings and binaries can only be told apart with a schema, `read_value` takes every
+/// string for a base64 binary.
+#[derive(Clone, Debug, Default)]
+pub struct JsonProtocol {
+    writer: JsonWriter,
+    reader: JsonReader,
+    depth: usize,
+}
+
+impl JsonProtocol {
+    pub fn new() -> JsonProtocol {
+        JsonProtocol::default()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.reader.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.reader.limits
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        self.reader.limits.check_depth(self.depth)
+    }
+
+    fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    fn read_type<T: Transport>(&mut self, transport: &mut T) -> Result<Type> {
+        let name = try!(self.reader.read_string(transport));
+        type_from_name(&name)
+    }
+
+    fn read_size<T: Transport>(&mut self, transport: &mut T) -> Result<i32> {
+        let size = try!(self.reader.read_integer(transport));
+        self.reader.limits.check_container_size(size)
+    }
+}
+
+impl Protocol for JsonProtocol {
+    fn write_message_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        name: &str,
+        message_type: MessageType,
+        sequence_id: i32
+    ) -> Result<()> {
+        try!(self.writer.write_array_begin(transport));
+        try!(self.writer.write_integer(transport, JSON_PROTOCOL_VERSION));
+        try!(self.writer.write_string(transport, name.as_bytes()));
+        try!(self.writer.write_integer(transport, message_type as i64));
+        self.writer.write_integer(transport, sequence_id as i64)
+    }
+
+    fn write_message_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.writer.write_array_end(transport));
+        Ok(try!(transport.flush()))
+    }
+
+    fn write_struct_begin<T: Transport>(&mut self, transport: &mut T, _name: &str) -> Result<()> {
+        self.writer.write_object_begin(transport)
+    }
+
+    fn write_struct_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_object_end(transport)
+    }
+
+    fn write_field_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        _name: &str,
+        field_type: Type,
+        field_id: i16
+    ) -> Result<()> {
+        try!(self.writer.write_integer(transport, field_id as i64));
+        try!(self.writer.write_object_begin(transport));
+        self.writer.write_string(transport, try!(type_name(field_type)).as_bytes())
+    }
+
+    fn write_field_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_object_end(transport)
+    }
+
+    fn write_field_stop<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_map_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        key_type: Type,
+        value_type: Type,
+        size: usize
+    ) -> Result<()> {
+        try!(self.writer.write_array_begin(transport));
+        try!(self.writer.write_string(transport, try!(type_name(key_type)).as_bytes()));
+        try!(self.writer.write_string(transport, try!(type_name(value_type)).as_bytes()));
+        try!(self.writer.write_integer(transport, size as i64));
+        self.writer.write_object_begin(transport)
+    }
+
+    fn write_map_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.writer.write_object_end(transport));
+        self.writer.write_array_end(transport)
+    }
+
+    fn write_list_begin<T: Transport>(&mut self, transport: &mut T, elem_type: Type, size: usize) -> Result<()> {
+        try!(self.writer.write_array_begin(transport));
+        try!(self.writer.write_string(transport, try!(type_name(elem_type)).as_bytes()));
+        self.writer.write_integer(transport, size as i64)
+    }
+
+    fn write_list_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_array_end(transport)
+    }
+
+    fn write_set_begin<T: Transport>(&mut self, transport: &mut T, elem_type: Type, size: usize) -> Result<()> {
+        self.write_list_begin(transport, elem_type, size)
+    }
+
+    fn write_set_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_array_end(transport)
+    }
+
+    fn write_bool<T: Transport>(&mut self, transport: &mut T, value: bool) -> Result<()> {
+        self.writer.write_integer(transport, if value { 1 } else { 0 })
+    }
+
+    fn write_byte<T: Transport>(&mut self, transport: &mut T, value: i8) -> Result<()> {
+        self.writer.write_integer(transport, value as i64)
+    }
+
+    fn write_i16<T: Transport>(&mut self, transport: &mut T, value: i16) -> Result<()> {
+        self.writer.write_integer(transport, value as i64)
+    }
+
+    fn write_i32<T: Transport>(&mut self, transport: &mut T, value: i32) -> Result<()> {
+        self.writer.write_integer(transport, value as i64)
+    }
+
+    fn write_i64<T: Transport>(&mut self, transport: &mut T, value: i64) -> Result<()> {
+        self.writer.write_integer(transport, value)
+    }
+
+    fn write_double<T: Transport>(&mut self, transport: &mut T, value: f64) -> Result<()> {
+        self.writer.write_double(transport, value)
+    }
+
+    fn write_str<T: Transport>(&mut self, transport: &mut T, value: &str) -> Result<()> {
+        self.writer.write_string(transport, value.as_bytes())
+    }
+
+    fn write_string<T: Transport>(&mut self, transport: &mut T, value: &String) -> Result<()> {
+        self.writer.write_string(transport, value.as_bytes())
+    }
+
+    fn write_binary<T: Transport>(&mut self, transport: &mut T, value: &[u8]) -> Result<()> {
+        self.writer.write_base64(transport, value)
+    }
+
+    fn read_message_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(String, MessageType, i32)> {
+        // a new message starts at the top, even if the last one failed halfway
+        self.depth = 0;
+        self.reader.contexts.clear();
+        try!(self.reader.read_array_begin(transport));
+        if try!(self.reader.read_integer(transport)) != JSON_PROTOCOL_VERSION {
+            return Err(Error::from(protocol::Error::BadVersion));
+        }
+        let name = try!(self.read_string(transport));
+        let raw_type = try!(self.reader.read_integer(transport));
+        let message_type = match MessageType::from_num(raw_type as u64) {
+            Some(t) => t,
+            None => return Err(violation()),
+        };
+        let sequence_id = try!(self.reader.read_integer(transport));
+        Ok((name, message_type, try!(narrow(sequence_id, i32::MIN as i64, i32::MAX as i64)) as i32))
+    }
+
+    fn read_message_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.reader.read_array_end(transport)
+    }
+
+    fn read_struct_begin<T: Transport>(&mut self, transport: &mut T) -> Result<String> {
+        try!(self.enter());
+        try!(self.reader.read_object_begin(transport));
+        Ok(String::new())
+    }
+
+    fn read_struct_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.leave();
+        self.reader.read_object_end(transport)
+    }
+
+    fn read_field_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(String, Type, i16)> {
+        if try!(self.reader.peek(transport)) == b'}' {
+            return Ok((String::new(), Type::Stop, 0));
+        }
+        let field_id = try!(self.reader.read_integer(transport));
+        let field_id = try!(narrow(field_id, i16::MIN as i64, i16::MAX as i64)) as i16;
+        try!(self.reader.read_object_begin(transport));
+        let field_type = try!(self.read_type(transport));
+        Ok((String::new(), field_type, field_id))
+    }
+
+    fn read_field_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.reader.read_object_end(transport)
+    }
+
+    fn read_map_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, Type, i32)> {
+        try!(self.enter());
+        try!(self.reader.read_array_begin(transport));
+        let key_type = try!(self.read_type(transport));
+        let value_type = try!(self.read_type(transport));
+        let size = try!(self.read_size(transport));
+        try!(self.reader.read_object_begin(transport));
+        Ok((key_type, value_type, size))
+    }
+
+    fn read_map_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.leave();
+        try!(self.reader.read_object_end(transport));
+        self.reader.read_array_end(transport)
+    }
+
+    fn read_list_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        try!(self.enter());
+        try!(self.reader.read_array_begin(transport));
+        let elem_type = try!(self.read_type(transport));
+        let size = try!(self.read_size(transport));
+        Ok((elem_type, size))
+    }
+
+    fn read_list_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.leave();
+        self.reader.read_array_end(transport)
+    }
+
+    fn read_set_begin<T: Transport>(&mut self, transport: &mut T) -> Result<(Type, i32)> {
+        self.read_list_begin(transport)
+    }
+
+    fn read_set_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.read_list_end(transport)
+    }
+
+    fn read_bool<T: Transport>(&mut self, transport: &mut T) -> Result<bool> {
+        Ok(try!(self.reader.read_integer(transport)) != 0)
+    }
+
+    fn read_byte<T: Transport>(&mut self, transport: &mut T) -> Result<i8> {
+        let value = try!(self.reader.read_integer(transport));
+        Ok(try!(narrow(value, i8::MIN as i64, i8::MAX as i64)) as i8)
+    }
+
+    fn read_i16<T: Transport>(&mut self, transport: &mut T) -> Result<i16> {
+        let value = try!(self.reader.read_integer(transport));
+        Ok(try!(narrow(value, i16::MIN as i64, i16::MAX as i64)) as i16)
+    }
+
+    fn read_i32<T: Transport>(&mut self, transport: &mut T) -> Result<i32> {
+        let value = try!(self.reader.read_integer(transport));
+        Ok(try!(narrow(value, i32::MIN as i64, i32::MAX as i64)) as i32)
+    }
+
+    fn read_i64<T: Transport>(&mut self, transport: &mut T) -> Result<i64> {
+        self.reader.read_integer(transport)
+    }
+
+    fn read_double<T: Transport>(&mut self, transport: &mut T) -> Result<f64> {
+        self.reader.read_double(transport)
+    }
+
+    fn read_string<T: Transport>(&mut self, transport: &mut T) -> Result<String> {
+        let bytes = try!(self.reader.read_string(transport));
+        Ok(try!(String::from_utf8(bytes).map_err(|e| protocol::Error::from(e.utf8_error()))))
+    }
+
+    fn read_binary<T: Transport>(&mut self, transport: &mut T) -> Result<Vec<u8>> {
+        self.reader.read_base64(transport)
+    }
+
+    // unlike the binary protocols this doesn't log skipped values, `read_value` would take
+    // strings for base64 binaries
+    fn skip<T: Transport>(&mut self, transport: &mut T, type_: Type) -> Result<()> {
+        match type_ {
+            Type::Bool => { try!(self.read_bool(transport)); }
+            Type::Byte => { try!(self.read_byte(transport)); }
+            Type::I16 => { try!(self.read_i16(transport)); }
+            Type::I32 => { try!(self.read_i32(transport)); }
+            Type::I64 => { try!(self.read_i64(transport)); }
+            Type::Double => { try!(self.read_double(transport)); }
+            Type::String => { try!(self.reader.read_string(transport)); }
+            Type::Struct => {
+                try!(self.read_struct_begin(transport));
+                loop {
+                    let (_, field_type, _) = try!(self.read_field_begin(transport));
+                    if field_type == Type::Stop {
+                        break;
+                    }
+                    try!(self.skip(transport, field_type));
+                    try!(self.read_field_end(transport));
+                }
+                try!(self.read_struct_end(transport));
+            }
+            Type::Map => {
+                let (key_type, value_type, size) = try!(self.read_map_begin(transport));
+                for _ in 0..size {
+                    try!(self.skip(transport, key_type));
+                    try!(self.skip(transport, value_type));
+                }
+                try!(self.read_map_end(transport));
+            }
+            Type::Set => {
+                let (elem_type, size) = try!(self.read_set_begin(transport));
+                for _ in 0..size {
+                    try!(self.skip(transport, elem_type));
+                }
+                try!(self.read_set_end(transport));
+            }
+            Type::List => {
+                let (elem_type, size) = try!(self.read_list_begin(transport));
+                for _ in 0..size {
+                    try!(self.skip(transport, elem_type));
+                }
+                try!(self.read_list_end(transport));
+            }
+            Type::Void => { }
+            Type::Stop => { }
+        };
+
+        Ok(())
+    }
+}
+
+/// JSON for humans and tools, fields are written by name and can't be read back
+#[derive(Clone, Debug, Default)]
+pub struct SimpleJsonProtocol {
+    writer: JsonWriter,
+}
+
+impl SimpleJsonProtocol {
+    pub fn new() -> SimpleJsonProtocol {
+        SimpleJsonProtocol::default()
+    }
+
+    /// Containers are written as JSON objects and arrays, which can't be keys
+    fn check_not_key(&self) -> Result<()> {
+        if self.writer.at_key() {
+            return Err(Error::InvalidArgument("SimpleJSON map keys must be strings or numbers".to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn write_only<R>() -> Result<R> {
+    Err(Error::from(protocol::Error::Unsupported))
+}
+
+impl Protocol for SimpleJsonProtocol {
+    fn write_message_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        name: &str,
+        message_type: MessageType,
+        sequence_id: i32
+    ) -> Result<()> {
+        try!(self.writer.write_array_begin(transport));
+        try!(self.writer.write_string(transport, name.as_bytes()));
+        try!(self.writer.write_integer(transport, message_type as i64));
+        self.writer.write_integer(transport, sequence_id as i64)
+    }
+
+    fn write_message_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        try!(self.writer.write_array_end(transport));
+        Ok(try!(transport.flush()))
+    }
+
+    fn write_struct_begin<T: Transport>(&mut self, transport: &mut T, _name: &str) -> Result<()> {
+        try!(self.check_not_key());
+        self.writer.write_object_begin(transport)
+    }
+
+    fn write_struct_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_object_end(transport)
+    }
+
+    /// Fields without a name, e.g. of a `Value`, are written by id
+    fn write_field_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        name: &str,
+        _field_type: Type,
+        field_id: i16
+    ) -> Result<()> {
+        if name.is_empty() {
+            self.writer.write_string(transport, field_id.to_string().as_bytes())
+        } else {
+            self.writer.write_string(transport, name.as_bytes())
+        }
+    }
+
+    fn write_field_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_field_stop<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_map_begin<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        _key_type: Type,
+        _value_type: Type,
+        _size: usize
+    ) -> Result<()> {
+        try!(self.check_not_key());
+        self.writer.write_object_begin(transport)
+    }
+
+    fn write_map_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_object_end(transport)
+    }
+
+    fn write_list_begin<T: Transport>(&mut self, transport: &mut T, _elem_type: Type, _size: usize) -> Result<()> {
+        try!(self.check_not_key());
+        self.writer.write_array_begin(transport)
+    }
+
+    fn write_list_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_array_end(transport)
+    }
+
+    fn write_set_begin<T: Transport>(&mut self, transport: &mut T, elem_type: Type, size: usize) -> Result<()> {
+        self.write_list_begin(transport, elem_type, size)
+    }
+
+    fn write_set_end<T: Transport>(&mut self, transport: &mut T) -> Result<()> {
+        self.writer.write_array_end(transport)
+    }
+
+    fn write_bool<T: Transport>(&mut self, transport: &mut T, value: bool) -> Result<()> {
+        self.writer.write_token(transport, if value { "true" } else { "false" })
+    }
+
+    fn write_byte<T: Transport>(&mut self, transport: &mut T, value: i8) -> Result<()> {
+        self.writer.write_integer(transport, value as i64)
+    }
+
+    fn write_i16<T: Transport>(&mut self, transport: &mut T, value: i16) -> Result<()> {
+        self.writer.write_integer(transport, value as i64)
+    }
+
+    fn write_i32<T: Transport>(&mut self, transport: &mut T, value: i32) -> Result<()> {
+        self.writer.write_integer(transport, value as i64)
+    }
+
+    fn write_i64<T: Transport>(&mut self, transport: &mut T, value: i64) -> Result<()> {
+        self.writer.write_integer(transport, value)
+    }
+
+    fn write_double<T: Transport>(&mut self, transport: &mut T, value: f64) -> Result<()> {
+        self.writer.write_double(transport, value)
+    }
+
+    fn write_str<T: Transport>(&mut self, transport: &mut T, value: &str) -> Result<()> {
+        self.writer.write_string(transport, value.as_bytes())
+    }
+
+    fn write_string<T: Transport>(&mut self, transport: &mut T, value: &String) -> Result<()> {
+        self.writer.write_string(transport, value.as_bytes())
+    }
+
+    fn write_binary<T: Transport>(&mut self, transport: &mut T, value: &[u8]) -> Result<()> {
+        self.writer.write_base64(transport, value)
+    }
+
+    fn read_message_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<(String, MessageType, i32)> {
+        write_only()
+    }
+
+    fn read_message_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        write_only()
+    }
+
+    fn read_struct_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<String> {
+        write_only()
+    }
+
+    fn read_struct_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        write_only()
+    }
+
+    fn read_field_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<(String, Type, i16)> {
+        write_only()
+    }
+
+    fn read_field_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        write_only()
+    }
+
+    fn read_map_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<(Type, Type, i32)> {
+        write_only()
+    }
+
+    fn read_map_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        write_only()
+    }
+
+    fn read_list_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<(Type, i32)> {
+        write_only()
+    }
+
+    fn read_list_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        write_only()
+    }
+
+    fn read_set_begin<T: Transport>(&mut self, _transport: &mut T) -> Result<(Type, i32)> {
+        write_only()
+    }
+
+    fn read_set_end<T: Transport>(&mut self, _transport: &mut T) -> Result<()> {
+        write_only()
+    }
+
+    fn read_bool<T: Transport>(&mut self, _transport: &mut T) -> Result<bool> {
+        write_only()
+    }
+
+    fn read_byte<T: Transport>(&mut self, _transport: &mut T) -> Result<i8> {
+        write_only()
+    }
+
+    fn read_i16<T: Transport>(&mut self, _transport: &mut T) -> Result<i16> {
+        write_only()
+    }
+
+    fn read_i32<T: Transport>(&mut self, _transport: &mut T) -> Result<i32> {
+        write_only()
+    }
+
+    fn read_i64<T: Transport>(&mut self, _transport: &mut T) -> Result<i64> {
+        write_only()
+    }
+
+    fn read_double<T: Transport>(&mut self, _transport: &mut T) -> Result<f64> {
+        write_only()
+    }
+
+    fn read_string<T: Transport>(&mut self, _transport: &mut T) -> Result<String> {
+        write_only()
+    }
+
+    fn read_binary<T: Transport>(&mut self, _transport: &mut T) -> Result<Vec<u8>> {
+        write_only()
+    }
+
+    fn skip<T: Transport>(&mut self, _transport: &mut T, _type_: Type) -> Result<()> {
+        write_only()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use super::{JsonProtocol, SimpleJsonProtocol};
+    use protocol::{self, MessageType, Protocol, Type, Value, read_value, write_value};
+    use transport::memory_transport::MemoryTransport;
+    use {Error, Result};
+
+    fn write<P: Protocol>(mut protocol: P, value: &Value) -> Result<String> {
+        let mut transport = MemoryTransport::new();
+        try!(write_value(&mut protocol, &mut transport, value));
+        Ok(String::from_utf8(transport.take_written()).unwrap())
+    }
+
+    fn read(json: &str, type_: Type) -> Result<Value> {
+        let mut transport = MemoryTransport::with_input(json.as_bytes().to_vec());
+        let value = try!(read_value(&mut JsonProtocol::new(), &mut transport, type_));
+        assert!(transport.remaining().is_empty());
+        Ok(value)
+    }
+
+    fn struct_of(fields: Vec<(i16, Value)>) -> Value {
+        Value::Struct(fields.into_iter().collect())
+    }
+
+    fn binary(value: &str) -> Value {
+        Value::Binary(value.as_bytes().to_vec())
+    }
+
+    fn assert_violation(result: Result<Value>) {
+        match result {
+            Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+            other => panic!("expected ProtocolViolation, got {:?}", other),
+        }
+    }
+
+    // The expected strings are what Apache Thrift's TJSONProtocol writes for the same values
+
+    #[test]
+    fn writes_every_type_like_apache_thrift() {
+        let value = struct_of(vec![(1, Value::Bool(true)),
+                                   (2, Value::Byte(-1)),
+                                   (3, Value::I16(300)),
+                                   (4, Value::I32(-5)),
+                                   (5, Value::I64(1 << 40)),
+                                   (6, Value::Double(1.5)),
+                                   (7, binary("abcd")),
+                                   (8, Value::List(Type::I32, vec![Value::I32(1), Value::I32(2)])),
+                                   (9, Value::Set(Type::String, vec![binary("a")])),
+                                   (10, struct_of(vec![])),
+                                   (11, Value::List(Type::I64, vec![]))]);
+        let json = "{\"1\":{\"tf\":1},\"2\":{\"i8\":-1},\"3\":{\"i16\":300},\"4\":{\"i32\":-5},\
+                    \"5\":{\"i64\":1099511627776},\"6\":{\"dbl\":1.5},\"7\":{\"str\":\"YWJjZA\"},\
+                    \"8\":{\"lst\":[\"i32\",2,1,2]},\"9\":{\"set\":[\"str\",1,\"YQ\"]},\"10\":{\"rec\":{}},\
+                    \"11\":{\"lst\":[\"i64\",0]}}";
+        assert_eq!(write(JsonProtocol::new(), &value).unwrap(), json);
+        assert_eq!(read(json, Type::Struct).unwrap(), value);
+    }
+
+    #[test]
+    fn writes_messages_like_apache_thrift() {
+        let mut protocol = JsonProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_message_begin(&mut transport, "getName", MessageType::Call, 7).unwrap();
+        write_value(&mut protocol, &mut transport, &struct_of(vec![])).unwrap();
+        protocol.write_message_end(&mut transport).unwrap();
+        assert_eq!(transport.written(), b"[1,\"getName\",1,7,{}]");
+
+        let reply = b"[1,\"getName\",2,7,{\"0\":{\"str\":\"YWdlbnQ\"}}]";
+        let mut transport = MemoryTransport::with_input(reply.to_vec());
+        let (name, message_type, sequence_id) = protocol.read_message_begin(&mut transport).unwrap();
+        assert_eq!((&name[..], message_type, sequence_id), ("getName", MessageType::Reply, 7));
+        assert_eq!(read_value(&mut protocol, &mut transport, Type::Struct).unwrap(),
+                   struct_of(vec![(0, binary("agent"))]));
+        protocol.read_message_end(&mut transport).unwrap();
+
+        let mut transport = MemoryTransport::with_input(b"[2,\"getName\",2,7,{}]".to_vec());
+        match protocol.read_message_begin(&mut transport) {
+            Err(Error::ProtocolError(protocol::Error::BadVersion)) => {}
+            other => panic!("expected BadVersion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn writes_base64_without_padding() {
+        for &(bytes, encoded) in &[("", "\"\""),
+                                   ("a", "\"YQ\""),
+                                   ("ab", "\"YWI\""),
+                                   ("abc", "\"YWJj\""),
+                                   ("\u{0}\u{ff}", "\"AMO/\"")] {
+            assert_eq!(write(JsonProtocol::new(), &binary(bytes)).unwrap(), encoded);
+            assert_eq!(read(encoded, Type::String).unwrap(), binary(bytes));
+        }
+        let all: Vec<u8> = (0..=255).collect();
+        let json = write(JsonProtocol::new(), &Value::Binary(all.clone())).unwrap();
+        assert!(!json.contains('='));
+        assert_eq!(read(&json, Type::String).unwrap(), Value::Binary(all));
+    }
+
+    #[test]
+    fn reads_base64_with_padding() {
+        assert_eq!(read("\"YQ==\"", Type::String).unwrap(), binary("a"));
+        assert_eq!(read("\"YWI=\"", Type::String).unwrap(), binary("ab"));
+        assert_violation(read("\"Y\"", Type::String));
+        assert_violation(read("\"YQ*=\"", Type::String));
+    }
+
+    #[test]
+    fn escapes_strings_like_apache_thrift() {
+        let mut protocol = JsonProtocol::new();
+        let mut transport = MemoryTransport::new();
+        protocol.write_str(&mut transport, "a\"b\\c/\n\r\t\u{8}\u{c}\u{1}\u{1f}é😀").unwrap();
+        assert_eq!(String::from_utf8(transport.take_written()).unwrap(),
+                   "\"a\\\"b\\\\c/\\n\\r\\t\\b\\f\\u0001\\u001fé😀\"");
+    }
+
+    #[test]
+    fn reads_escapes_and_surrogate_pairs() {
+        let mut protocol = JsonProtocol::new();
+        let json = "\"\\u0041\\u00e9\\ud83d\\ude00\\/\\\"\"";
+        let mut transport = MemoryTransport::with_input(json.as_bytes().to_vec());
+        assert_eq!(protocol.read_string(&mut transport).unwrap(), "Aé😀/\"");
+
+        // lone and reversed surrogates, unknown escapes, bad hex digits
+        for json in &["\"\\ud83d\"", "\"\\ud83dx\"", "\"\\ud83d\\u0041\"", "\"\\ude00\"", "\"\\ude00\\ud83d\"",
+                      "\"\\x41\"", "\"\\u00g1\""] {
+            let mut transport = MemoryTransport::with_input(json.as_bytes().to_vec());
+            match protocol.read_string(&mut transport) {
+                Err(Error::ProtocolError(protocol::Error::ProtocolViolation)) => {}
+                other => panic!("expected ProtocolViolation for {}, got {:?}", json, other),
+            }
+        }
+    }
+
+    #[test]
+    fn writes_special_doubles_as_strings() {
+        let value = Value::List(Type::Double,
+                                vec![Value::Double(f64::NAN),
+                                     Value::Double(f64::INFINITY),
+                                     Value::Double(f64::NEG_INFINITY),
+                                     Value::Double(-0.25)]);
+        let json = "[\"dbl\",4,\"NaN\",\"Infinity\",\"-Infinity\",-0.25]";
+        assert_eq!(write(JsonProtocol::new(), &value).unwrap(), json);
+
+        match read(json, Type::List).unwrap() {
+            Value::List(Type::Double, ref items) => {
+                match (&items[0], &items[1], &items[2], &items[3]) {
+                    (&Value::Double(nan), &Value::Double(infinity), &Value::Double(negative), &Value::Double(x)) => {
+                        assert!(nan.is_nan());
+                        assert_eq!((infinity, negative, x), (f64::INFINITY, f64::NEG_INFINITY, -0.25));
+                    }
+                    other => panic!("unexpected items {:?}", other),
+                }
+            }
+            other => panic!("unexpected value {:?}", other),
+        }
+        assert_eq!(read("[\"dbl\",2,1e3,\"1.5\"]", Type::List).unwrap(),
+                   Value::List(Type::Double, vec![Value::Double(1000.0), Value::Double(1.5)]));
+        for json in &["[\"dbl\",1,\"nan\"]", "[\"dbl\",1,\"inf\"]", "[\"dbl\",1,\"infinity\"]", "[\"dbl\",1,\"\"]"] {
+            assert_violation(read(json, Type::List));
+        }
+    }
+
+    #[test]
+    fn quotes_map_keys() {
+        let value = Value::Map(Type::I32,
+                               Type::String,
+                               vec![(Value::I32(5), binary("eth1")), (Value::I32(-1), binary(""))]);
+        let json = "[\"i32\",\"str\",2,{\"5\":\"ZXRoMQ\",\"-1\":\"\"}]";
+        assert_eq!(write(JsonProtocol::new(), &value).unwrap(), json);
+        assert_eq!(read(json, Type::Map).unwrap(), value);
+
+        let value = Value::Map(Type::Double,
+                               Type::Bool,
+                               vec![(Value::Double(1.5), Value::Bool(true)),
+                                    (Value::Double(f64::INFINITY), Value::Bool(false))]);
+        assert_eq!(write(JsonProtocol::new(), &value).unwrap(),
+                   "[\"dbl\",\"tf\",2,{\"1.5\":1,\"Infinity\":0}]");
+
+        let value = Value::Map(Type::String, Type::I64, vec![(binary("a"), Value::I64(1))]);
+        assert_eq!(write(JsonProtocol::new(), &value).unwrap(), "[\"str\",\"i64\",1,{\"YQ\":1}]");
+
+        // thrift always quotes keys
+        assert_violation(read("[\"i32\",\"str\",1,{5:\"ZXRoMQ\"}]", Type::Map));
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        for &(json, type_) in &[("{\"1\":{\"xyz\":1}}", Type::Struct),
+                                 ("{\"1\"{\"i32\":1}}", Type::Struct),
+                                 ("{\"99999\":{\"i32\":1}}", Type::Struct),
+                                 ("[\"i8\",1,300]", Type::List),
+                                 ("[\"i32\",1,1.5]", Type::List),
+                                 ("[\"i32\",2,1 2]", Type::List)] {
+            assert_violation(read(json, type_));
+        }
+        match read("[\"i32\",-1]", Type::List) {
+            Err(Error::ProtocolError(protocol::Error::NegativeSize)) => {}
+            other => panic!("expected NegativeSize, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn writes_simple_json_by_field_id() {
+        let value = struct_of(vec![(1, Value::I32(5)),
+                                   (2, Value::List(Type::String, vec![binary("a")])),
+                                   (3, Value::Map(Type::I16, Type::Bool, vec![(Value::I16(7), Value::Bool(true))])),
+                                   (4, Value::Double(f64::NAN))]);
+        assert_eq!(write(SimpleJsonProtocol::new(), &value).unwrap(),
+                   "{\"1\":5,\"2\":[\"YQ\"],\"3\":{\"7\":true},\"4\":\"NaN\"}");
+    }
+
+    #[test]
+    fn simple_json_rejects_container_map_keys() {
+        let keys = vec![Value::List(Type::I32, vec![Value::I32(1)]),
+                        Value::Set(Type::I32, vec![]),
+                        Value::Map(Type::I32, Type::I32, vec![]),
+                        struct_of(vec![(1, Value::I32(1))])];
+        for key in keys {
+            let value = Value::Map(key.typ(), Type::I32, vec![(key.clone(), Value::I32(1))]);
+            match write(SimpleJsonProtocol::new(), &value) {
+                Err(Error::InvalidArgument(_)) => {}
+                other => panic!("expected InvalidArgument for key {:?}, got {:?}", key, other),
+            }
+            // container values are fine
+            let value = Value::Map(Type::I32, key.typ(), vec![(Value::I32(1), key)]);
+            write(SimpleJsonProtocol::new(), &value).unwrap();
+        }
+    }
+
+    #[test]
+    fn simple_json_is_write_only() {
+        let mut transport = MemoryTransport::with_input(b"{}".to_vec());
+        match read_value(&mut SimpleJsonProtocol::new(), &mut transport, Type::Struct) {
+            Err(Error::ProtocolError(protocol::Error::Unsupported)) => {}
+            other => panic!("expected Unsupported, got {:?}", other),
+        }
+    }
+}
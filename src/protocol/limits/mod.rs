@@ -0,0 +1,246 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//! Bounds on what the protocols read, against corrupt or malicious input
+
+use std::io::{self, Read};
+
+use protocol::{Error, Type};
+use transport::Transport;
+use Result;
+
+/// Bounds on what a protocol reads, data beyond them fails with a `protocol::Error`
+///
+/// Strings and binaries are read as their bytes arrive, so memory is bounded by what was
+/// actually received and not by the announced length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Longest string or binary in bytes
+    pub max_string_length: usize,
+    /// Most elements of a list or set, or entries of a map
+    pub max_container_size: usize,
+    /// Deepest nesting of structs and containers
+    pub max_depth: usize,
+}
+
+impl Limits {
+    /// Strings of up to 64 MiB, containers of up to 16M elements, 64 levels of nesting
+    pub fn new() -> Limits {
+        Limits {
+            max_string_length: 64 * 1024 * 1024,
+            max_container_size: 16 * 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+
+    pub fn with_max_string_length(mut self, max_string_length: usize) -> Limits {
+        self.max_string_length = max_string_length;
+        self
+    }
+
+    pub fn with_max_container_size(mut self, max_container_size: usize) -> Limits {
+        self.max_container_size = max_container_size;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Limits {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Checks the length of a string or binary as it was received
+    pub fn check_string_length(&self, length: i64) -> Result<usize> {
+        if length < 0 {
+            return Err(::Error::from(Error::NegativeSize));
+        }
+        if length as u64 > self.max_string_length as u64 {
+            return Err(::Error::from(Error::SizeLimit));
+        }
+        Ok(length as usize)
+    }
+
+    /// Checks the size of a container as it was received
+    pub fn check_container_size(&self, size: i64) -> Result<i32> {
+        if size < 0 {
+            return Err(::Error::from(Error::NegativeSize));
+        }
+        if size as u64 > self.max_container_size as u64 || size > i32::MAX as i64 {
+            return Err(::Error::from(Error::SizeLimit));
+        }
+        Ok(size as i32)
+    }
+
+    /// Checks the depth of a struct or container that is about to be read, counting from 1
+    pub fn check_depth(&self, depth: usize) -> Result<()> {
+        if depth > self.max_depth {
+            return Err(::Error::from(Error::DepthLimit));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::new()
+    }
+}
+
+/// Checks the element, key or value type of a container with `size` elements, values of `Stop`
+/// and `Void` take no bytes, so skipping them would spin without consuming input
+pub fn check_element_type(type_: Type, size: i32) -> Result<()> {
+    match type_ {
+        Type::Stop | Type::Void if size > 0 => Err(::Error::from(Error::ProtocolViolation)),
+        _ => Ok(()),
+    }
+}
+
+/// Reads `len` bytes without allocating them up front
+pub fn read_bytes<T: Transport>(transport: &mut T, len: usize) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    try!(Read::by_ref(transport).take(len as u64).read_to_end(&mut bytes));
+    if bytes.len() < len {
+        return Err(::Error::from(io::Error::new(io::ErrorKind::UnexpectedEof, "Could not read enough bytes")));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Limits;
+    use protocol::{self, Protocol, Type, Value, read_value};
+    use protocol::binary_protocol::BinaryProtocol;
+    use protocol::compact_protocol::CompactProtocol;
+    use protocol::json_protocol::JsonProtocol;
+    use transport::memory_transport::MemoryTransport;
+    use {Error, Result};
+
+    fn small_limits() -> Limits {
+        Limits::new().with_max_string_length(4).with_max_container_size(2).with_max_depth(3)
+    }
+
+    fn binary() -> BinaryProtocol {
+        BinaryProtocol::with_limits(small_limits())
+    }
+
+    fn compact() -> CompactProtocol {
+        let mut protocol = CompactProtocol::new();
+        protocol.set_limits(small_limits());
+        protocol
+    }
+
+    fn json() -> JsonProtocol {
+        let mut protocol = JsonProtocol::new();
+        protocol.set_limits(small_limits());
+        protocol
+    }
+
+    fn read<P: Protocol>(mut protocol: P, bytes: &[u8], type_: Type) -> Result<Value> {
+        read_value(&mut protocol, &mut MemoryTransport::with_input(bytes.to_vec()), type_)
+    }
+
+    fn skip<P: Protocol>(mut protocol: P, bytes: &[u8], type_: Type) -> Result<()> {
+        protocol.skip(&mut MemoryTransport::with_input(bytes.to_vec()), type_)
+    }
+
+    fn assert_error<R: ::std::fmt::Debug>(result: Result<R>, expected: protocol::Error) {
+        match result {
+            Err(Error::ProtocolError(ref err)) if format!("{:?}", err) == format!("{:?}", expected) => {}
+            other => panic!("expected {:?}, got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn rejects_negative_sizes() {
+        assert_error(read(binary(), b"\xff\xff\xff\xff", Type::String), protocol::Error::NegativeSize);
+        assert_error(read(binary(), b"\x08\x80\x00\x00\x00", Type::List), protocol::Error::NegativeSize);
+        assert_error(read(binary(), b"\x08\x08\xff\xff\xff\xfe", Type::Map), protocol::Error::NegativeSize);
+        assert_error(read(json(), b"[\"i32\",-1]", Type::Set), protocol::Error::NegativeSize);
+    }
+
+    #[test]
+    fn rejects_over_long_strings() {
+        assert_eq!(read(binary(), b"\x00\x00\x00\x04eth1", Type::String).unwrap(), Value::Binary(b"eth1".to_vec()));
+        assert_error(read(binary(), b"\x00\x00\x00\x05eth10", Type::String), protocol::Error::SizeLimit);
+        assert_error(read(compact(), b"\x05eth10", Type::String), protocol::Error::SizeLimit);
+        assert_error(read(json(), b"\"ZXRoMTA\"", Type::String), protocol::Error::SizeLimit);
+
+        // the announced length is checked before anything is read, with the default limits too
+        assert_error(read(BinaryProtocol::new(), b"\x7f\xff\xff\xff", Type::String), protocol::Error::SizeLimit);
+        assert_error(read(CompactProtocol::new(), b"\xff\xff\xff\xff\x0f", Type::String),
+                     protocol::Error::SizeLimit);
+    }
+
+    #[test]
+    fn reads_announced_length_only_as_it_arrives() {
+        let limits = Limits::new().with_max_string_length(usize::MAX);
+        match read(BinaryProtocol::with_limits(limits), b"\x7f\xff\xff\xffabc", Type::String) {
+            Err(Error::TransportError(_)) => {}
+            other => panic!("expected a transport error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_containers() {
+        assert_error(read(binary(), b"\x08\x00\x00\x00\x03", Type::List), protocol::Error::SizeLimit);
+        assert_error(read(compact(), b"\x35", Type::List), protocol::Error::SizeLimit);
+        assert_error(read(json(), b"[\"i32\",3,1,2,3]", Type::List), protocol::Error::SizeLimit);
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        // lists of one list each, the innermost is empty
+        let nested = |depth: usize| {
+            let mut bytes = b"\x0f\x00\x00\x00\x01".repeat(depth - 1);
+            bytes.extend_from_slice(b"\x08\x00\x00\x00\x00");
+            bytes
+        };
+        assert!(read(binary(), &nested(3), Type::List).is_ok());
+        assert_error(read(binary(), &nested(4), Type::List), protocol::Error::DepthLimit);
+        assert_error(skip(binary(), &nested(4), Type::List), protocol::Error::DepthLimit);
+        assert_error(read(BinaryProtocol::new(), &nested(100_000), Type::List), protocol::Error::DepthLimit);
+
+        // structs holding a struct in field 1
+        assert_error(read(compact(), b"\x1c\x1c\x1c\x1c\x00\x00\x00\x00", Type::Struct), protocol::Error::DepthLimit);
+        assert_error(read(json(), b"{\"1\":{\"rec\":{\"1\":{\"rec\":{\"1\":{\"rec\":{\"1\":{\"rec\":{}}}}}}}}}",
+                          Type::Struct),
+                     protocol::Error::DepthLimit);
+    }
+
+    #[test]
+    fn rejects_stop_and_void_elements() {
+        // Stop and Void elements, keys and values in containers of up to 16M elements, within the limits
+        for bytes in &[&b"\x00\x00\xff\xff\xff"[..], b"\x01\x00\x00\x00\x02"] {
+            assert_error(skip(BinaryProtocol::new(), bytes, Type::List), protocol::Error::ProtocolViolation);
+            assert_error(skip(BinaryProtocol::new(), bytes, Type::Set), protocol::Error::ProtocolViolation);
+        }
+        assert_error(skip(BinaryProtocol::new(), b"\x00\x08\x00\x00\x00\x01", Type::Map),
+                     protocol::Error::ProtocolViolation);
+        assert_error(skip(BinaryProtocol::new(), b"\x08\x01\x00\x00\x00\x01", Type::Map),
+                     protocol::Error::ProtocolViolation);
+        assert_error(skip(CompactProtocol::new(), b"\xf0\xff\xff\xff\x07", Type::List),
+                     protocol::Error::ProtocolViolation);
+        assert_error(skip(CompactProtocol::new(), b"\x10", Type::Set), protocol::Error::ProtocolViolation);
+        assert_error(skip(CompactProtocol::new(), b"\x05\x80", Type::Map), protocol::Error::ProtocolViolation);
+
+        // empty containers say nothing about their elements
+        assert_eq!(read(BinaryProtocol::new(), b"\x00\x00\x00\x00\x00", Type::List).unwrap(),
+                   Value::List(Type::Stop, vec![]));
+        assert!(skip(CompactProtocol::new(), b"\x00", Type::Map).is_ok());
+    }
+}
@@ -23,6 +23,15 @@ use transport::Transport;
 use Result;
 
 pub mod binary_protocol;
+pub mod compact_protocol;
+pub mod json_protocol;
+mod impls;
+mod limits;
+pub mod value;
+
+pub use self::impls::check_element_type;
+pub use self::limits::Limits;
+pub use self::value::{Value, read_value, write_value};
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -32,6 +41,16 @@ pub enum Error {
     ProtocolViolation,
     /// Received string cannot be converted to a UTF8 string
     InvalidUtf8(str::Utf8Error),
+    /// Sequence id of a reply doesn't match the request it was read for
+    BadSequenceId,
+    /// The protocol can't do this, e.g. reading with the write-only SimpleJSON protocol
+    Unsupported,
+    /// Received a negative length or container size
+    NegativeSize,
+    /// Received a string, binary or container beyond the protocol's `Limits`
+    SizeLimit,
+    /// Received structs or containers nested deeper than the protocol's `Limits` allow
+    DepthLimit,
 }
 
 impl StdError for Error {
@@ -116,6 +135,7 @@ pub enum MessageType {
     Call = 0x01,
     Reply = 0x02,
     Exception = 0x03,
+    Oneway = 0x04,
 }
 
 impl MessageType {
@@ -124,6 +144,7 @@ impl MessageType {
             0x01 => Some(MessageType::Call),
             0x02 => Some(MessageType::Reply),
             0x03 => Some(MessageType::Exception),
+            0x04 => Some(MessageType::Oneway),
             _ => None,
         }
     }
@@ -136,6 +157,11 @@ pub trait ThriftTyped {
 pub trait Encode: ThriftTyped {
     fn encode<P, T>(&self, &mut P, &mut T) -> Result<()>
     where P: Protocol, T: Transport;
+
+    /// Whether the value is written as a struct field, false for optional fields without value
+    fn is_set(&self) -> bool {
+        true
+    }
 }
 
 pub trait Decode: ThriftTyped + Default {
@@ -225,8 +251,73 @@ pub trait FromNum: Sized{
     fn from_num(num: i32) -> Option<Self>;
 }
 
+/// What a server replies with an `Exception` message when it fails to handle a call, thrift's
+/// `TApplicationException`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApplicationException {
+    pub message: String,
+    /// Why the call failed, one of the `ApplicationException::*` kinds, e.g. `UNKNOWN_METHOD`
+    pub kind: i32,
+}
+
+impl ApplicationException {
+    pub const UNKNOWN: i32 = 0;
+    pub const UNKNOWN_METHOD: i32 = 1;
+    pub const INVALID_MESSAGE_TYPE: i32 = 2;
+    pub const WRONG_METHOD_NAME: i32 = 3;
+    pub const BAD_SEQUENCE_ID: i32 = 4;
+    pub const MISSING_RESULT: i32 = 5;
+    pub const INTERNAL_ERROR: i32 = 6;
+    pub const PROTOCOL_ERROR: i32 = 7;
+    pub const INVALID_TRANSFORM: i32 = 8;
+    pub const INVALID_PROTOCOL: i32 = 9;
+    pub const UNSUPPORTED_CLIENT_TYPE: i32 = 10;
+}
+
+impl ThriftTyped for ApplicationException {
+    fn typ() -> Type {
+        Type::Struct
+    }
+}
+
+impl Encode for ApplicationException {
+    fn encode<P, T>(&self, protocol: &mut P, transport: &mut T) -> Result<()>
+        where P: Protocol,
+              T: Transport
+    {
+        try!(protocol.write_struct_begin(transport, "TApplicationException"));
+        try!(protocol.write_field_begin(transport, "message", Type::String, 1));
+        try!(protocol.write_str(transport, &self.message));
+        try!(protocol.write_field_end(transport));
+        try!(protocol.write_field_begin(transport, "type", Type::I32, 2));
+        try!(protocol.write_i32(transport, self.kind));
+        try!(protocol.write_field_end(transport));
+        try!(protocol.write_field_stop(transport));
+        protocol.write_struct_end(transport)
+    }
+}
+
+impl Decode for ApplicationException {
+    fn decode<P, T>(&mut self, protocol: &mut P, transport: &mut T) -> Result<()>
+        where P: Protocol,
+              T: Transport
+    {
+        try!(protocol.read_struct_begin(transport));
+        loop {
+            match try!(protocol.read_field_begin(transport)) {
+                (_, Type::Stop, _) => break,
+                (_, Type::String, 1) => self.message = try!(protocol.read_string(transport)),
+                (_, Type::I32, 2) => self.kind = try!(protocol.read_i32(transport)),
+                (_, field_type, _) => try!(protocol.skip(transport, field_type)),
+            }
+            try!(protocol.read_field_end(transport));
+        }
+        protocol.read_struct_end(transport)
+    }
+}
+
 pub mod helpers {
-    use protocol::{Protocol, Type, MessageType, FromNum, Decode, Encode, Error};
+    use protocol::{Protocol, Type, MessageType, FromNum, Decode, Encode, Error, ApplicationException};
     use transport::Transport;
     use Result;
 
@@ -239,18 +330,73 @@ pub mod helpers {
         }
     }
 
+    /// Reads a value of any `Decode` type, like `let ids: Vec<i32> = try!(read(protocol, transport))`
+    pub fn read<V, T, P>(protocol: &mut P, transport: &mut T) -> Result<V>
+    where V: Decode, T: Transport, P: Protocol {
+        let mut value = V::default();
+        try!(value.decode(protocol, transport));
+        Ok(value)
+    }
+
+    /// Reads a struct, `read_field` is called with the id and type of every field and returns
+    /// false for the fields it doesn't read, which are skipped
+    pub fn read_struct<F, T, P>(protocol: &mut P, transport: &mut T, mut read_field: F) -> Result<()>
+    where F: FnMut(&mut P, &mut T, i16, Type) -> Result<bool>, T: Transport, P: Protocol {
+        try!(protocol.read_struct_begin(transport));
+        loop {
+            let (_, field_type, field_id) = try!(protocol.read_field_begin(transport));
+            if field_type == Type::Stop {
+                break;
+            }
+            if !try!(read_field(protocol, transport, field_id, field_type)) {
+                try!(protocol.skip(transport, field_type));
+            }
+            try!(protocol.read_field_end(transport));
+        }
+        protocol.read_struct_end(transport)
+    }
+
+    /// Writes a struct field, optional fields set to `None` are left out
+    pub fn write_field<V, T, P>(protocol: &mut P, transport: &mut T,
+                                name: &str, id: i16, value: &V) -> Result<()>
+    where V: Encode, T: Transport, P: Protocol {
+        if !value.is_set() {
+            return Ok(());
+        }
+        try!(protocol.write_field_begin(transport, name, V::typ(), id));
+        try!(value.encode(protocol, transport));
+        protocol.write_field_end(transport)
+    }
+
     pub fn send<W, T, P>(protocol: &mut P, transport: &mut T,
                          name: &str, _type: MessageType,
                          args: &W) -> Result<()>
     where W: Encode, T: Transport, P: Protocol {
-        let cseqid: i32 = 0;
-        try!(protocol.write_message_begin(transport, name, _type, cseqid));
+        send_message(protocol, transport, name, _type, 0, args)
+    }
+
+    pub fn send_message<W, T, P>(protocol: &mut P, transport: &mut T,
+                                 name: &str, _type: MessageType,
+                                 sequence_id: i32, args: &W) -> Result<()>
+    where W: Encode, T: Transport, P: Protocol {
+        try!(protocol.write_message_begin(transport, name, _type, sequence_id));
         try!(args.encode(protocol, transport));
         try!(protocol.write_message_end(transport));
         try!(transport.flush());
         Ok(())
     }
 
+    /// Like `receive`, but fails with `BadSequenceId` if the reply doesn't answer `sequence_id`
+    pub fn receive_reply<R, T, P>(protocol: &mut P, transport: &mut T,
+                                  op: &str, sequence_id: i32, result: &mut R) -> Result<()>
+    where R: Decode, T: Transport, P: Protocol {
+        let (name, ty, id) = try!(protocol.read_message_begin(transport));
+        if id != sequence_id {
+            return Err(::Error::from(Error::BadSequenceId));
+        }
+        receive_body(protocol, transport, op, result, &name, ty, id)
+    }
+
     pub fn receive<R, T, P>(protocol: &mut P, transport: &mut T,
                             op: &str, result: &mut R) -> Result<()>
     where R: Decode, T: Transport, P: Protocol {
@@ -264,14 +410,10 @@ pub mod helpers {
     where R: Decode, T: Transport, P: Protocol {
         match (name, ty, id) {
             (_, MessageType::Exception, _) => {
-                println!("got exception");
-                // TODO
-                //let x = ApplicationException;
-                //x.read(&mut protocol)
-                //protocol.read_message_end();
-                //transport.read_end();
-                //throw x
-                Err(::Error::UserException)
+                let mut exception = ApplicationException::default();
+                try!(exception.decode(protocol, transport));
+                try!(protocol.read_message_end(transport));
+                Err(::Error::ApplicationException(exception))
             }
             // TODO: Make sure the client doesn't receive Call messages and that the server
             // doesn't receive Reply messages
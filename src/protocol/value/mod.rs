@@ -0,0 +1,459 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//! Schema-less representation of thrift data, for replies the crate has no types for
+
+use std::collections::BTreeMap;
+use std::{fmt, str};
+
+use protocol::{Protocol, Type, Error};
+use transport::Transport;
+use Result;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Byte(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Double(f64),
+    /// Strings and binaries, which are the same on the wire
+    Binary(Vec<u8>),
+    /// Fields by id, the names of fields are not part of the data
+    Struct(BTreeMap<i16, Value>),
+    /// Element type and elements
+    List(Type, Vec<Value>),
+    Set(Type, Vec<Value>),
+    /// Key type, value type and entries in wire order
+    Map(Type, Type, Vec<(Value, Value)>),
+}
+
+impl Value {
+    pub fn typ(&self) -> Type {
+        match *self {
+            Value::Bool(_) => Type::Bool,
+            Value::Byte(_) => Type::Byte,
+            Value::I16(_) => Type::I16,
+            Value::I32(_) => Type::I32,
+            Value::I64(_) => Type::I64,
+            Value::Double(_) => Type::Double,
+            Value::Binary(_) => Type::String,
+            Value::Struct(_) => Type::Struct,
+            Value::List(_, _) => Type::List,
+            Value::Set(_, _) => Type::Set,
+            Value::Map(_, _, _) => Type::Map,
+        }
+    }
+
+    /// The content of a `Binary` holding UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Binary(ref bytes) => str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+
+    /// Field `id` of a `Struct`
+    pub fn field(&self, id: i16) -> Option<&Value> {
+        match *self {
+            Value::Struct(ref fields) => fields.get(&id),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+impl From<i8> for Value {
+    fn from(value: i8) -> Value {
+        Value::Byte(value)
+    }
+}
+
+impl From<i16> for Value {
+    fn from(value: i16) -> Value {
+        Value::I16(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Value {
+        Value::I32(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::I64(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Double(value)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(value: &'a str) -> Value {
+        Value::Binary(value.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Binary(value.into_bytes())
+    }
+}
+
+impl From<BTreeMap<i16, Value>> for Value {
+    fn from(fields: BTreeMap<i16, Value>) -> Value {
+        Value::Struct(fields)
+    }
+}
+
+/// Compact rendering for logs, binaries holding UTF-8 are shown as strings
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Byte(value) => write!(f, "{}", value),
+            Value::I16(value) => write!(f, "{}", value),
+            Value::I32(value) => write!(f, "{}", value),
+            Value::I64(value) => write!(f, "{}", value),
+            Value::Double(value) => write!(f, "{}", value),
+            Value::Binary(ref bytes) => {
+                match str::from_utf8(bytes) {
+                    Ok(string) => write!(f, "{:?}", string),
+                    Err(_) => write!(f, "{:?}", bytes),
+                }
+            }
+            Value::Struct(ref fields) => {
+                try!(write!(f, "{{"));
+                for (i, (id, value)) in fields.iter().enumerate() {
+                    try!(write!(f, "{}{}: {}", if i == 0 { "" } else { ", " }, id, value));
+                }
+                write!(f, "}}")
+            }
+            Value::List(_, ref values) |
+            Value::Set(_, ref values) => {
+                try!(write!(f, "["));
+                for (i, value) in values.iter().enumerate() {
+                    try!(write!(f, "{}{}", if i == 0 { "" } else { ", " }, value));
+                }
+                write!(f, "]")
+            }
+            Value::Map(_, _, ref entries) => {
+                try!(write!(f, "{{"));
+                for (i, entry) in entries.iter().enumerate() {
+                    try!(write!(f, "{}{} => {}", if i == 0 { "" } else { ", " }, entry.0, entry.1));
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Reads a value of type `type_` without knowing its schema
+pub fn read_value<P, T>(protocol: &mut P, transport: &mut T, type_: Type) -> Result<Value>
+where P: Protocol, T: Transport {
+    Ok(match type_ {
+        Type::Bool => Value::Bool(try!(protocol.read_bool(transport))),
+        Type::Byte => Value::Byte(try!(protocol.read_byte(transport))),
+        Type::I16 => Value::I16(try!(protocol.read_i16(transport))),
+        Type::I32 => Value::I32(try!(protocol.read_i32(transport))),
+        Type::I64 => Value::I64(try!(protocol.read_i64(transport))),
+        Type::Double => Value::Double(try!(protocol.read_double(transport))),
+        Type::String => Value::Binary(try!(protocol.read_binary(transport))),
+        Type::Struct => {
+            let mut fields = BTreeMap::new();
+            try!(protocol.read_struct_begin(transport));
+            loop {
+                let (_, field_type, field_id) = try!(protocol.read_field_begin(transport));
+                if field_type == Type::Stop {
+                    break;
+                }
+                fields.insert(field_id, try!(read_value(protocol, transport, field_type)));
+                try!(protocol.read_field_end(transport));
+            }
+            try!(protocol.read_struct_end(transport));
+            Value::Struct(fields)
+        }
+        Type::List => {
+            let (element_type, size) = try!(protocol.read_list_begin(transport));
+            let mut values = Vec::new();
+            for _ in 0..size {
+                values.push(try!(read_value(protocol, transport, element_type)));
+            }
+            try!(protocol.read_list_end(transport));
+            Value::List(element_type, values)
+        }
+        Type::Set => {
+            let (element_type, size) = try!(protocol.read_set_begin(transport));
+            let mut values = Vec::new();
+            for _ in 0..size {
+                values.push(try!(read_value(protocol, transport, element_type)));
+            }
+            try!(protocol.read_set_end(transport));
+            Value::Set(element_type, values)
+        }
+        Type::Map => {
+            let (key_type, value_type, size) = try!(protocol.read_map_begin(transport));
+            let mut entries = Vec::new();
+            for _ in 0..size {
+                let key = try!(read_value(protocol, transport, key_type));
+                let value = try!(read_value(protocol, transport, value_type));
+                entries.push((key, value));
+            }
+            try!(protocol.read_map_end(transport));
+            Value::Map(key_type, value_type, entries)
+        }
+        Type::Stop | Type::Void => return Err(::Error::from(Error::ProtocolViolation)),
+    })
+}
+
+/// Writes `value`, the elements of containers have to be of their declared type
+pub fn write_value<P, T>(protocol: &mut P, transport: &mut T, value: &Value) -> Result<()>
+where P: Protocol, T: Transport {
+    match *value {
+        Value::Bool(value) => protocol.write_bool(transport, value),
+        Value::Byte(value) => protocol.write_byte(transport, value),
+        Value::I16(value) => protocol.write_i16(transport, value),
+        Value::I32(value) => protocol.write_i32(transport, value),
+        Value::I64(value) => protocol.write_i64(transport, value),
+        Value::Double(value) => protocol.write_double(transport, value),
+        Value::Binary(ref bytes) => protocol.write_binary(transport, bytes),
+        Value::Struct(ref fields) => {
+            try!(protocol.write_struct_begin(transport, ""));
+            for (&id, value) in fields {
+                try!(protocol.write_field_begin(transport, "", value.typ(), id));
+                try!(write_value(protocol, transport, value));
+                try!(protocol.write_field_end(transport));
+            }
+            try!(protocol.write_field_stop(transport));
+            protocol.write_struct_end(transport)
+        }
+        Value::List(element_type, ref values) => {
+            try!(check_types(values.iter(), element_type));
+            try!(protocol.write_list_begin(transport, element_type, values.len()));
+            for value in values {
+                try!(write_value(protocol, transport, value));
+            }
+            protocol.write_list_end(transport)
+        }
+        Value::Set(element_type, ref values) => {
+            try!(check_types(values.iter(), element_type));
+            try!(protocol.write_set_begin(transport, element_type, values.len()));
+            for value in values {
+                try!(write_value(protocol, transport, value));
+            }
+            protocol.write_set_end(transport)
+        }
+        Value::Map(key_type, value_type, ref entries) => {
+            try!(check_types(entries.iter().map(|entry| &entry.0), key_type));
+            try!(check_types(entries.iter().map(|entry| &entry.1), value_type));
+            try!(protocol.write_map_begin(transport, key_type, value_type, entries.len()));
+            for entry in entries {
+                try!(write_value(protocol, transport, &entry.0));
+                try!(write_value(protocol, transport, &entry.1));
+            }
+            protocol.write_map_end(transport)
+        }
+    }
+}
+
+fn check_types<'a, I>(mut values: I, type_: Type) -> Result<()>
+where I: Iterator<Item = &'a Value> {
+    if values.all(|value| value.typ() == type_) {
+        Ok(())
+    } else {
+        Err(::Error::from(Error::ProtocolViolation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use log::{self, Level, LevelFilter, Log, Metadata, Record};
+
+    use super::{Value, read_value, write_value};
+    use protocol::{Error, Protocol, Type};
+    use protocol::binary_protocol::BinaryProtocol;
+    use protocol::compact_protocol::CompactProtocol;
+    use transport::memory_transport::MemoryTransport;
+
+    fn struct_of(fields: Vec<(i16, Value)>) -> Value {
+        Value::Struct(fields.into_iter().collect::<BTreeMap<_, _>>())
+    }
+
+    fn every_variant() -> Vec<Value> {
+        vec![
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Byte(-128),
+            Value::I16(-2),
+            Value::I32(100_000),
+            Value::I64(-1 << 40),
+            Value::Double(1.5),
+            Value::from("eth1/1/1"),
+            Value::Binary(vec![0xff, 0]),
+            struct_of(vec![(1, Value::I32(7)), (3, Value::Bool(true)), (-1, Value::from("x"))]),
+            Value::List(Type::I32, vec![Value::I32(1), Value::I32(2)]),
+            Value::List(Type::Struct, vec![struct_of(vec![(1, Value::from("a"))])]),
+            Value::Set(Type::String, vec![Value::from("a"), Value::from("b")]),
+            Value::Map(Type::I16, Type::List, vec![
+                (Value::I16(1), Value::List(Type::Bool, vec![Value::Bool(true)])),
+                (Value::I16(2), Value::List(Type::Bool, vec![Value::Bool(false)])),
+            ]),
+        ]
+    }
+
+    fn round_trip_with<P: Protocol>(mut protocol: P, value: &Value) {
+        let mut transport = MemoryTransport::new();
+        write_value(&mut protocol, &mut transport, value).unwrap();
+        let mut transport = MemoryTransport::with_input(transport.take_written());
+        assert_eq!(&read_value(&mut protocol, &mut transport, value.typ()).unwrap(), value);
+        assert!(transport.remaining().is_empty());
+    }
+
+    fn assert_violation(value: &Value) {
+        let mut transport = MemoryTransport::new();
+        match write_value(&mut BinaryProtocol::new(), &mut transport, value) {
+            Err(::Error::ProtocolError(Error::ProtocolViolation)) => {}
+            other => panic!("{}: unexpected result {:?}", value, other),
+        }
+    }
+
+    #[test]
+    fn round_trips_every_variant() {
+        for value in every_variant() {
+            round_trip_with(BinaryProtocol::new(), &value);
+            round_trip_with(CompactProtocol::new(), &value);
+        }
+    }
+
+    #[test]
+    fn round_trips_empty_containers() {
+        for value in &[
+            Value::Struct(BTreeMap::new()),
+            Value::List(Type::I64, vec![]),
+            Value::Set(Type::String, vec![]),
+            Value::Map(Type::String, Type::I32, vec![]),
+        ] {
+            round_trip_with(BinaryProtocol::new(), value);
+        }
+    }
+
+    #[test]
+    fn rejects_types_without_values() {
+        for &type_ in &[Type::Stop, Type::Void] {
+            let mut transport = MemoryTransport::with_input(vec![0; 8]);
+            match read_value(&mut BinaryProtocol::new(), &mut transport, type_) {
+                Err(::Error::ProtocolError(Error::ProtocolViolation)) => {}
+                other => panic!("{:?}: unexpected result {:?}", type_, other),
+            }
+        }
+    }
+
+    #[test]
+    fn displays_every_variant() {
+        let rendered: Vec<String> = every_variant().iter().map(|value| value.to_string()).collect();
+        assert_eq!(rendered, vec![
+            "true",
+            "false",
+            "-128",
+            "-2",
+            "100000",
+            "-1099511627776",
+            "1.5",
+            "\"eth1/1/1\"",
+            "[255, 0]",
+            "{-1: \"x\", 1: 7, 3: true}",
+            "[1, 2]",
+            "[{1: \"a\"}]",
+            "[\"a\", \"b\"]",
+            "{1 => [true], 2 => [false]}",
+        ]);
+        assert_eq!(Value::List(Type::I32, vec![]).to_string(), "[]");
+        assert_eq!(Value::Struct(BTreeMap::new()).to_string(), "{}");
+    }
+
+    #[test]
+    fn rejects_mismatched_element_types() {
+        assert_violation(&Value::List(Type::I32, vec![Value::I32(1), Value::I64(2)]));
+        assert_violation(&Value::Set(Type::String, vec![Value::Bool(true)]));
+        assert_violation(&Value::Map(Type::I16, Type::String, vec![(Value::I32(1), Value::from("a"))]));
+        assert_violation(&Value::Map(Type::I16, Type::String, vec![(Value::I16(1), Value::I16(2))]));
+        assert_violation(&Value::List(Type::List, vec![Value::List(Type::Byte, vec![Value::Bool(false)])]));
+    }
+
+    #[test]
+    fn rejects_mismatched_element_types_nested_in_a_struct() {
+        let bad = Value::Set(Type::I64, vec![Value::I64(1), Value::from("2")]);
+        assert_violation(&struct_of(vec![(1, Value::I32(1)), (2, bad.clone())]));
+        assert_violation(&struct_of(vec![(1, struct_of(vec![(4, bad)]))]));
+    }
+
+    struct Capture {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Debug
+        }
+
+        fn log(&self, record: &Record) {
+            if record.target().starts_with(module_path!().split("::").next().unwrap()) {
+                self.messages.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn binary_protocol_logs_skipped_values() {
+        // Only this test installs a logger, other tests merely take the logging path of `skip` as well
+        let capture: &'static Capture = Box::leak(Box::new(Capture { messages: Mutex::new(Vec::new()) }));
+        log::set_logger(capture).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+
+        let skipped = struct_of(vec![(1, Value::from("ignored")), (2, Value::List(Type::I16, vec![Value::I16(3)]))]);
+        let mut transport = MemoryTransport::new();
+        write_value(&mut BinaryProtocol::new(), &mut transport, &skipped).unwrap();
+        write_value(&mut BinaryProtocol::new(), &mut transport, &Value::I32(42)).unwrap();
+
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = MemoryTransport::with_input(transport.take_written());
+        protocol.skip(&mut transport, Type::Struct).unwrap();
+        assert_eq!(protocol.read_i32(&mut transport).unwrap(), 42);
+        assert!(transport.remaining().is_empty());
+
+        let messages = capture.messages.lock().unwrap();
+        assert!(messages.contains(&"Skipped Struct {1: \"ignored\", 2: [3]}".to_string()), "{:?}", *messages);
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use podio::{ReadPodExt, WritePodExt, BigEndian};
+
+use transport::Transport;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// TFramedTransport, every message is prefixed by its length as 4 byte big endian integer.
+///
+/// Writes are buffered until `flush`, reads consume one whole frame at a time.
+pub struct FramedTransport<T: Transport> {
+    inner: T,
+    max_frame_size: usize,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    write_buf: Vec<u8>,
+}
+
+impl<T: Transport> FramedTransport<T> {
+    pub fn new(inner: T) -> FramedTransport<T> {
+        FramedTransport::with_max_frame_size(inner, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(inner: T, max_frame_size: usize) -> FramedTransport<T> {
+        FramedTransport {
+            inner: inner,
+            max_frame_size: max_frame_size,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            write_buf: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Reads the next frame, `false` if the stream ended cleanly before it
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; 4];
+        let mut header_len = 0;
+        while header_len < header.len() {
+            match self.inner.read(&mut header[header_len..]) {
+                Ok(0) if header_len == 0 => return Ok(false),
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated frame header")),
+                Ok(len) => header_len += len,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let frame_size = try!((&header[..]).read_i32::<BigEndian>());
+        if frame_size < 0 || frame_size as usize > self.max_frame_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("frame size {} exceeds limit of {} bytes",
+                                              frame_size,
+                                              self.max_frame_size)));
+        }
+
+        self.read_buf = try!(ReadPodExt::read_exact(&mut self.inner, frame_size as usize));
+        self.read_pos = 0;
+        Ok(true)
+    }
+}
+
+impl<T: Transport> Read for FramedTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // empty frames are valid, but must not be reported as end of stream
+        while self.read_pos == self.read_buf.len() {
+            if !try!(self.read_frame()) {
+                return Ok(0);
+            }
+        }
+
+        let len = cmp::min(buf.len(), self.read_buf.len() - self.read_pos);
+        buf[..len].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + len]);
+        self.read_pos += len;
+        Ok(len)
+    }
+}
+
+impl<T: Transport> Write for FramedTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.write_buf.len() > self.max_frame_size {
+            self.write_buf.clear();
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("frame size exceeds limit of {} bytes",
+                                              self.max_frame_size)));
+        }
+
+        if !self.write_buf.is_empty() {
+            try!(self.inner.write_i32::<BigEndian>(self.write_buf.len() as i32));
+            try!(self.inner.write_all(&self.write_buf));
+            self.write_buf.clear();
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use super::{FramedTransport, DEFAULT_MAX_FRAME_SIZE};
+    use protocol::{MessageType, Protocol};
+    use protocol::binary_protocol::BinaryProtocol;
+    use transport::memory_transport::MemoryTransport;
+
+    fn frame(size: i32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![(size >> 24) as u8, (size >> 16) as u8, (size >> 8) as u8, size as u8];
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn reading(input: Vec<u8>) -> FramedTransport<MemoryTransport> {
+        FramedTransport::new(MemoryTransport::with_input(input))
+    }
+
+    fn read_all(transport: &mut FramedTransport<MemoryTransport>) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+        try!(transport.read_to_end(&mut data));
+        Ok(data)
+    }
+
+    #[test]
+    fn writes_one_frame_per_flush() {
+        let mut transport = FramedTransport::new(MemoryTransport::new());
+        transport.write_all(b"hello ").unwrap();
+        transport.write_all(b"agent").unwrap();
+        assert!(transport.get_ref().written().is_empty());
+        transport.flush().unwrap();
+        transport.write_all(b"!").unwrap();
+        transport.flush().unwrap();
+        // nothing buffered, no empty frame
+        transport.flush().unwrap();
+
+        let mut expected = frame(11, b"hello agent");
+        expected.extend(frame(1, b"!"));
+        assert_eq!(transport.get_ref().written(), &expected[..]);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut protocol = BinaryProtocol::new();
+        let mut transport = FramedTransport::new(MemoryTransport::new());
+        for sequence_id in 1..4 {
+            protocol.write_message_begin(&mut transport, "getBootType", MessageType::Call, sequence_id).unwrap();
+            protocol.write_message_end(&mut transport).unwrap();
+        }
+
+        let mut transport = reading(transport.get_mut().take_written());
+        for sequence_id in 1..4 {
+            let (name, _, read_id) = protocol.read_message_begin(&mut transport).unwrap();
+            assert_eq!((&name[..], read_id), ("getBootType", sequence_id));
+        }
+        assert_eq!(transport.read(&mut [0u8; 8]).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_across_frames_and_skips_empty_ones() {
+        let mut input = frame(3, b"abc");
+        input.extend(frame(0, b""));
+        input.extend(frame(0, b""));
+        input.extend(frame(2, b"de"));
+        let mut transport = reading(input);
+
+        let mut buf = [0u8; 2];
+        assert_eq!(transport.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"ab");
+        // a read never spans two frames
+        assert_eq!(transport.read(&mut buf).unwrap(), 1);
+        assert_eq!(read_all(&mut transport).unwrap(), b"de");
+    }
+
+    #[test]
+    fn clean_end_of_stream_between_frames() {
+        let mut transport = reading(frame(1, b"x"));
+        assert_eq!(read_all(&mut transport).unwrap(), b"x");
+        assert_eq!(transport.read(&mut [0u8; 4]).unwrap(), 0);
+        assert_eq!(transport.read(&mut [0u8; 4]).unwrap(), 0);
+
+        assert_eq!(reading(vec![]).read(&mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_truncated_frame() {
+        let input = frame(5, b"abcde");
+        for len in 1..input.len() {
+            let mut transport = reading(input[..len].to_vec());
+            assert!(read_all(&mut transport).is_err(), "{} bytes", len);
+        }
+        let err = reading(vec![0, 0]).read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_oversized_frame_on_read() {
+        for size in &[DEFAULT_MAX_FRAME_SIZE as i32 + 1, i32::max_value(), -1, i32::min_value()] {
+            let err = reading(frame(*size, b"")).read(&mut [0u8; 4]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "frame size {}", size);
+        }
+
+        let mut transport = FramedTransport::with_max_frame_size(MemoryTransport::with_input(frame(4, b"abcd")), 3);
+        assert_eq!(transport.read(&mut [0u8; 4]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut transport = FramedTransport::with_max_frame_size(MemoryTransport::with_input(frame(3, b"abc")), 3);
+        assert_eq!(read_all(&mut transport).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn rejects_oversized_frame_on_write() {
+        let mut transport = FramedTransport::new(MemoryTransport::new());
+        transport.write_all(&vec![0u8; DEFAULT_MAX_FRAME_SIZE + 1]).unwrap();
+        assert_eq!(transport.flush().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(transport.get_ref().written().is_empty());
+
+        // the rejected frame is dropped, the next one goes out whole
+        transport.write_all(&vec![7u8; DEFAULT_MAX_FRAME_SIZE]).unwrap();
+        transport.flush().unwrap();
+        let written = transport.get_mut().take_written();
+        assert_eq!(written.len(), DEFAULT_MAX_FRAME_SIZE + 4);
+        assert_eq!(&written[..4], &frame(DEFAULT_MAX_FRAME_SIZE as i32, b"")[..]);
+    }
+}
@@ -0,0 +1,501 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use std::str;
+
+use podio::{ReadPodExt, WritePodExt, BigEndian};
+
+use transport::Transport;
+use transport::framed_transport::DEFAULT_MAX_FRAME_SIZE;
+
+static HEADER_MAGIC: u16 = 0x0fff;
+// fbthrift prefix of frames over 4 GiB, followed by a 64 bit frame size
+static BIG_FRAME_MAGIC: u32 = 0x4249_4746;
+static BINARY_PROTOCOL_VERSION_1: u16 = 0x8001;
+static COMPACT_PROTOCOL_ID: u8 = 0x82;
+
+static INFO_PADDING: u64 = 0x00;
+static INFO_KEYVALUE: u64 = 0x01;
+static INFO_PKEYVALUE: u64 = 0x02;
+
+/// Framing used by the peer, detected on every frame read
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ClientType {
+    Header,
+    Framed,
+    Unframed,
+}
+
+/// Protocol of the payload, as announced in the THeader
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ProtocolId {
+    Binary = 0x00,
+    Compact = 0x02,
+}
+
+impl ProtocolId {
+    pub fn from_num(num: u64) -> Option<ProtocolId> {
+        match num {
+            0x00 => Some(ProtocolId::Binary),
+            0x02 => Some(ProtocolId::Compact),
+            _ => None,
+        }
+    }
+}
+
+/// fbthrift THeader transport.
+///
+/// Frames are written as THeader unless the transport was switched to another client type,
+/// on read the framing of the peer is detected, so header, framed and unframed replies are
+/// all understood. Writes are buffered until `flush`.
+///
+/// Big frames, which fbthrift uses for payloads over 4 GiB, are not supported and fail
+/// with `InvalidData`.
+pub struct HeaderTransport<T: Transport> {
+    inner: T,
+    client_type: ClientType,
+    protocol_id: ProtocolId,
+    flags: u16,
+    sequence_id: u32,
+    max_frame_size: usize,
+    read_headers: HashMap<String, String>,
+    write_headers: HashMap<String, String>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    write_buf: Vec<u8>,
+}
+
+impl<T: Transport> HeaderTransport<T> {
+    pub fn new(inner: T) -> HeaderTransport<T> {
+        HeaderTransport::with_protocol_id(inner, ProtocolId::Binary)
+    }
+
+    pub fn with_protocol_id(inner: T, protocol_id: ProtocolId) -> HeaderTransport<T> {
+        HeaderTransport {
+            inner: inner,
+            client_type: ClientType::Header,
+            protocol_id: protocol_id,
+            flags: 0,
+            sequence_id: 0,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            read_headers: HashMap::new(),
+            write_headers: HashMap::new(),
+            read_buf: Vec::new(),
+            read_pos: 0,
+            write_buf: Vec::new(),
+        }
+    }
+
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    pub fn client_type(&self) -> ClientType {
+        self.client_type
+    }
+
+    pub fn set_client_type(&mut self, client_type: ClientType) {
+        self.client_type = client_type;
+    }
+
+    pub fn protocol_id(&self) -> ProtocolId {
+        self.protocol_id
+    }
+
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: u16) {
+        self.flags = flags;
+    }
+
+    pub fn sequence_id(&self) -> u32 {
+        self.sequence_id
+    }
+
+    pub fn set_sequence_id(&mut self, sequence_id: u32) {
+        self.sequence_id = sequence_id;
+    }
+
+    /// Info headers received with the last frame
+    pub fn read_headers(&self) -> &HashMap<String, String> {
+        &self.read_headers
+    }
+
+    /// Info headers sent with the next frame, they are cleared once the frame is flushed
+    pub fn write_headers(&self) -> &HashMap<String, String> {
+        &self.write_headers
+    }
+
+    pub fn set_write_header(&mut self, key: &str, value: &str) {
+        self.write_headers.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn clear_write_headers(&mut self) {
+        self.write_headers.clear();
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn read_frame(&mut self) -> io::Result<()> {
+        let mut prefix = [0u8; 4];
+        try!(self.inner.read_exact(&mut prefix));
+
+        if prefix[0] == COMPACT_PROTOCOL_ID ||
+           ((prefix[0] as u16) << 8 | prefix[1] as u16) == BINARY_PROTOCOL_VERSION_1 {
+            // unframed message, hand out the prefix and read the rest straight from the transport
+            self.client_type = ClientType::Unframed;
+            self.protocol_id = if prefix[0] == COMPACT_PROTOCOL_ID {
+                ProtocolId::Compact
+            } else {
+                ProtocolId::Binary
+            };
+            self.read_buf = prefix.to_vec();
+            self.read_pos = 0;
+            return Ok(());
+        }
+
+        let frame_size = try!(Cursor::new(&prefix[..]).read_u32::<BigEndian>());
+        if frame_size == BIG_FRAME_MAGIC {
+            return Err(invalid_data("big frames are not supported".to_string()));
+        }
+        let frame_size = frame_size as usize;
+        if frame_size > self.max_frame_size {
+            return Err(invalid_data(format!("frame size {} exceeds limit of {} bytes",
+                                            frame_size,
+                                            self.max_frame_size)));
+        }
+        let frame = try!(ReadPodExt::read_exact(&mut self.inner, frame_size));
+
+        if frame.len() >= 2 && ((frame[0] as u16) << 8 | frame[1] as u16) == HEADER_MAGIC {
+            self.client_type = ClientType::Header;
+            return self.read_header_frame(frame);
+        }
+
+        self.client_type = ClientType::Framed;
+        if !frame.is_empty() && frame[0] == COMPACT_PROTOCOL_ID {
+            self.protocol_id = ProtocolId::Compact;
+        } else {
+            self.protocol_id = ProtocolId::Binary;
+        }
+        self.read_buf = frame;
+        self.read_pos = 0;
+        Ok(())
+    }
+
+    fn read_header_frame(&mut self, frame: Vec<u8>) -> io::Result<()> {
+        let mut cursor = Cursor::new(&frame[..]);
+        try!(cursor.read_u16::<BigEndian>()); // magic
+        let flags = try!(cursor.read_u16::<BigEndian>());
+        let sequence_id = try!(cursor.read_u32::<BigEndian>());
+        let header_size = try!(cursor.read_u16::<BigEndian>()) as usize * 4;
+
+        let header_start = cursor.position() as usize;
+        let header_end = header_start + header_size;
+        if header_end > frame.len() {
+            return Err(invalid_data("header size exceeds frame size".to_string()));
+        }
+
+        let mut header = Cursor::new(&frame[header_start..header_end]);
+        let protocol_id = try!(read_varint(&mut header));
+        let protocol_id = match ProtocolId::from_num(protocol_id) {
+            Some(protocol_id) => protocol_id,
+            None => return Err(invalid_data(format!("unsupported protocol id {}", protocol_id))),
+        };
+
+        let transform_count = try!(read_varint(&mut header));
+        if transform_count > 0 {
+            let transform_id = try!(read_varint(&mut header));
+            return Err(invalid_data(format!("unsupported transform {}", transform_id)));
+        }
+
+        let mut read_headers = HashMap::new();
+        while (header.position() as usize) < header_size {
+            let info_type = try!(read_varint(&mut header));
+            if info_type == INFO_PADDING {
+                break;
+            }
+            if info_type != INFO_KEYVALUE && info_type != INFO_PKEYVALUE {
+                // unknown info types can't be skipped, their size is not known
+                break;
+            }
+            let count = try!(read_varint(&mut header));
+            for _ in 0..count {
+                let key = try!(read_varint_string(&mut header));
+                let value = try!(read_varint_string(&mut header));
+                read_headers.insert(key, value);
+            }
+        }
+
+        self.flags = flags;
+        self.sequence_id = sequence_id;
+        self.protocol_id = protocol_id;
+        self.read_headers = read_headers;
+        self.read_buf = frame[header_end..].to_vec();
+        self.read_pos = 0;
+        Ok(())
+    }
+
+    fn write_header_frame(&mut self) -> io::Result<()> {
+        let mut header = Vec::new();
+        write_varint(&mut header, self.protocol_id as u64);
+        write_varint(&mut header, 0); // no transforms
+        if !self.write_headers.is_empty() {
+            write_varint(&mut header, INFO_KEYVALUE);
+            write_varint(&mut header, self.write_headers.len() as u64);
+            for (key, value) in &self.write_headers {
+                write_varint_string(&mut header, key);
+                write_varint_string(&mut header, value);
+            }
+        }
+        while header.len() % 4 != 0 {
+            header.push(INFO_PADDING as u8);
+        }
+
+        if header.len() / 4 > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("info headers of {} bytes exceed the header size field",
+                                              header.len())));
+        }
+
+        let frame_size = 10 + header.len() + self.write_buf.len();
+        if frame_size > self.max_frame_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("frame size {} exceeds limit of {} bytes",
+                                              frame_size,
+                                              self.max_frame_size)));
+        }
+
+        let mut frame = Vec::with_capacity(4 + frame_size);
+        try!(frame.write_u32::<BigEndian>(frame_size as u32));
+        try!(frame.write_u16::<BigEndian>(HEADER_MAGIC));
+        try!(frame.write_u16::<BigEndian>(self.flags));
+        try!(frame.write_u32::<BigEndian>(self.sequence_id));
+        try!(frame.write_u16::<BigEndian>((header.len() / 4) as u16));
+        frame.extend_from_slice(&header);
+        frame.extend_from_slice(&self.write_buf);
+
+        self.inner.write_all(&frame)
+    }
+}
+
+impl<T: Transport> Read for HeaderTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_pos == self.read_buf.len() {
+            if self.client_type == ClientType::Unframed && !self.read_buf.is_empty() {
+                return self.inner.read(buf);
+            }
+            while self.read_pos == self.read_buf.len() {
+                try!(self.read_frame());
+            }
+        }
+
+        let len = cmp::min(buf.len(), self.read_buf.len() - self.read_pos);
+        buf[..len].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + len]);
+        self.read_pos += len;
+        Ok(len)
+    }
+}
+
+impl<T: Transport> Write for HeaderTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // a new request starts, the next read has to detect the framing again
+        if self.client_type == ClientType::Unframed && self.read_pos == self.read_buf.len() {
+            self.read_buf.clear();
+            self.read_pos = 0;
+        }
+        self.write_buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.write_buf.is_empty() {
+            let result = match self.client_type {
+                ClientType::Header => self.write_header_frame(),
+                ClientType::Framed => {
+                    let frame_size = self.write_buf.len() as u32;
+                    self.inner.write_u32::<BigEndian>(frame_size)
+                        .and_then(|_| self.inner.write_all(&self.write_buf))
+                }
+                ClientType::Unframed => self.inner.write_all(&self.write_buf),
+            };
+            self.write_buf.clear();
+            self.write_headers.clear();
+            try!(result);
+        }
+        self.inner.flush()
+    }
+}
+
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: u64) {
+    let mut value = value;
+    while value & !0x7f != 0 {
+        buf.push(((value & 0x7f) | 0x80) as u8);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_varint_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut result: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = try!(reader.read_u8());
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(invalid_data("varint too long".to_string()));
+        }
+    }
+}
+
+/// Reads a string of the header, the length is checked against what is left of the header
+fn read_varint_string(reader: &mut Cursor<&[u8]>) -> io::Result<String> {
+    let len = try!(read_varint(reader));
+    let start = reader.position() as usize;
+    let remaining = reader.get_ref().len() - start;
+    if len > remaining as u64 {
+        return Err(invalid_data(format!("info header of {} bytes exceeds header size", len)));
+    }
+    let end = start + len as usize;
+    let value = try!(str::from_utf8(&reader.get_ref()[start..end])
+        .map_err(|_| invalid_data("info header is not valid utf8".to_string())))
+        .to_string();
+    reader.set_position(end as u64);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use podio::{WritePodExt, BigEndian};
+
+    use super::{ClientType, HeaderTransport, ProtocolId};
+    use transport::memory_transport::MemoryTransport;
+
+    fn header_frame(header: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut header = header.to_vec();
+        while header.len() % 4 != 0 {
+            header.push(0);
+        }
+        let frame_size = 10 + header.len() + payload.len();
+        let mut frame = Vec::new();
+        frame.write_u32::<BigEndian>(frame_size as u32).unwrap();
+        frame.extend_from_slice(&[0x0f, 0xff, 0, 0, 0, 0, 0, 7, 0, (header.len() / 4) as u8]);
+        frame.extend_from_slice(&header);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn read_error(input: Vec<u8>) -> io::Error {
+        let mut transport = HeaderTransport::new(MemoryTransport::with_input(input));
+        let mut buf = [0u8; 16];
+        transport.read(&mut buf).unwrap_err()
+    }
+
+    #[test]
+    fn round_trips_header_frames() {
+        let mut transport = HeaderTransport::with_protocol_id(MemoryTransport::new(), ProtocolId::Compact);
+        transport.set_sequence_id(42);
+        transport.set_write_header("client", "torc");
+        transport.write_all(b"payload").unwrap();
+        transport.flush().unwrap();
+
+        let written = transport.into_inner().take_written();
+        let mut transport = HeaderTransport::new(MemoryTransport::with_input(written));
+        let mut payload = [0u8; 7];
+        transport.read_exact(&mut payload).unwrap();
+        assert_eq!(&payload, b"payload");
+        assert_eq!(transport.client_type(), ClientType::Header);
+        assert_eq!(transport.protocol_id(), ProtocolId::Compact);
+        assert_eq!(transport.sequence_id(), 42);
+        assert_eq!(transport.read_headers().get("client").map(|value| &value[..]), Some("torc"));
+    }
+
+    #[test]
+    fn reads_framed_and_unframed_replies() {
+        let mut transport = HeaderTransport::new(MemoryTransport::with_input(vec![0, 0, 0, 2, 0x80, 0x01]));
+        let mut buf = [0u8; 2];
+        transport.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x80, 0x01]);
+        assert_eq!(transport.client_type(), ClientType::Framed);
+
+        let mut transport = HeaderTransport::new(MemoryTransport::with_input(vec![0x82, 0x21, 0, 1, 2]));
+        let mut buf = [0u8; 5];
+        transport.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x82, 0x21, 0, 1, 2]);
+        assert_eq!(transport.client_type(), ClientType::Unframed);
+        assert_eq!(transport.protocol_id(), ProtocolId::Compact);
+    }
+
+    #[test]
+    fn rejects_info_header_longer_than_header() {
+        // one key/value header whose key claims to be 2^62 bytes long
+        let header = [0, 0, 1, 1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x3f];
+        let err = read_error(header_frame(&header, b""));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let header = [0, 0, 1, 1, 5, b'k', b'e', b'y'];
+        let err = read_error(header_frame(&header, b""));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_big_frames() {
+        let err = read_error(vec![0x42, 0x49, 0x47, 0x46, 0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "big frames are not supported");
+    }
+
+    #[test]
+    fn rejects_frames_over_limit() {
+        let mut transport = HeaderTransport::new(MemoryTransport::with_input(vec![0x10, 0, 0, 0]));
+        transport.set_max_frame_size(1024);
+        let mut buf = [0u8; 1];
+        assert_eq!(transport.read(&mut buf).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
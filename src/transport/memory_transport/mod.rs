@@ -0,0 +1,113 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::cmp;
+use std::io::{self, Read, Write};
+
+/// Transport reading from and writing to in-memory buffers.
+///
+/// Reads are served from the input given with `with_input`/`push_input` and return
+/// end of stream once it is consumed, everything written is kept for `written`.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    write_buf: Vec<u8>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    pub fn with_input(input: Vec<u8>) -> MemoryTransport {
+        MemoryTransport {
+            read_buf: input,
+            read_pos: 0,
+            write_buf: Vec::new(),
+        }
+    }
+
+    pub fn push_input(&mut self, input: &[u8]) {
+        self.read_buf.extend_from_slice(input);
+    }
+
+    /// Input not consumed yet
+    pub fn remaining(&self) -> &[u8] {
+        &self.read_buf[self.read_pos..]
+    }
+
+    pub fn written(&self) -> &[u8] {
+        &self.write_buf
+    }
+
+    pub fn take_written(&mut self) -> Vec<u8> {
+        let mut written = Vec::new();
+        ::std::mem::swap(&mut written, &mut self.write_buf);
+        written
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.read_buf.len() - self.read_pos);
+        buf[..len].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + len]);
+        self.read_pos += len;
+        Ok(len)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::MemoryTransport;
+
+    #[test]
+    fn reads_input_and_keeps_output() {
+        let mut transport = MemoryTransport::with_input(b"in".to_vec());
+        transport.push_input(b"put");
+        transport.write_all(b"out").unwrap();
+
+        let mut buf = [0u8; 2];
+        transport.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"in");
+        assert_eq!(transport.remaining(), b"put");
+
+        let mut rest = Vec::new();
+        transport.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"put");
+        assert_eq!(transport.read(&mut buf).unwrap(), 0);
+
+        assert_eq!(transport.written(), b"out");
+        assert_eq!(transport.take_written(), b"out");
+        assert!(transport.written().is_empty());
+    }
+}
@@ -19,7 +19,20 @@
 
 use std::io::{Read, Write};
 
+use bufstream::BufStream;
+
+pub mod framed_transport;
+pub mod header_transport;
+pub mod memory_transport;
+pub mod pipe_transport;
+pub mod socket_transport;
+#[cfg(feature = "tls")]
+pub mod tls_transport;
+
 pub trait Transport: Write + Read { }
 
 impl<T: Write + Read> Transport for T { }
 
+/// Buffers reads and writes to the underlying stream, writes are sent on `flush`,
+/// which the protocols issue at `write_message_end`.
+pub type BufferedTransport<T> = BufStream<T>;
@@ -0,0 +1,151 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex};
+
+struct PipeState {
+    buf: VecDeque<u8>,
+    closed: bool,
+}
+
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+impl Pipe {
+    fn new() -> Arc<Pipe> {
+        Arc::new(Pipe {
+            state: Mutex::new(PipeState {
+                buf: VecDeque::new(),
+                closed: false,
+            }),
+            readable: Condvar::new(),
+        })
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.readable.notify_all();
+    }
+}
+
+/// One end of an in-process connection created by `pipe`.
+///
+/// Reads block until the other end wrote data, once the other end is dropped reads return
+/// end of stream and writes fail with `BrokenPipe`.
+pub struct PipeTransport {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+}
+
+/// Creates two connected transports, what is written to one can be read from the other
+pub fn pipe() -> (PipeTransport, PipeTransport) {
+    let a_to_b = Pipe::new();
+    let b_to_a = Pipe::new();
+
+    let a = PipeTransport {
+        incoming: b_to_a.clone(),
+        outgoing: a_to_b.clone(),
+    };
+    let b = PipeTransport {
+        incoming: a_to_b,
+        outgoing: b_to_a,
+    };
+    (a, b)
+}
+
+impl Read for PipeTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.incoming.state.lock().unwrap();
+        while state.buf.is_empty() && !state.closed {
+            state = self.incoming.readable.wait(state).unwrap();
+        }
+
+        let len = cmp::min(buf.len(), state.buf.len());
+        for (dst, src) in buf[..len].iter_mut().zip(state.buf.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for PipeTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.outgoing.state.lock().unwrap();
+        if state.closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "other end of the pipe is closed"));
+        }
+        state.buf.extend(buf.iter().cloned());
+        self.outgoing.readable.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeTransport {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use std::thread;
+
+    use super::pipe;
+
+    #[test]
+    fn connects_both_ends() {
+        let (mut a, mut b) = pipe();
+        a.write_all(b"ping").unwrap();
+        let reader = thread::spawn(move || {
+            let mut buf = [0u8; 4];
+            b.read_exact(&mut buf).unwrap();
+            b.write_all(b"pong").unwrap();
+            buf
+        });
+
+        let mut buf = [0u8; 4];
+        a.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+        assert_eq!(&reader.join().unwrap(), b"ping");
+    }
+
+    #[test]
+    fn closes_when_other_end_is_dropped() {
+        let (mut a, mut b) = pipe();
+        b.write_all(b"last").unwrap();
+        drop(b);
+
+        let mut buf = Vec::new();
+        a.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"last");
+        assert_eq!(a.write(b"lost").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
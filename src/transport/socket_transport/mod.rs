@@ -0,0 +1,483 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+static UNIX_SOCKET_PREFIX: &'static str = "unix:";
+
+/// Address of the agent, as parsed from a connect string
+#[derive(Clone, Debug, PartialEq)]
+pub enum SocketAddress {
+    /// `host:port`, `ipv4:port`, `[ipv6]:port` or `[ipv6%zone]:port`,
+    /// the zone being an interface name or a numeric scope id
+    Tcp(Vec<SocketAddr>),
+    /// `unix:/path/to/socket`
+    Unix(PathBuf),
+}
+
+impl SocketAddress {
+    pub fn parse(connect_string: &str) -> io::Result<SocketAddress> {
+        if let Some(path) = connect_string.strip_prefix(UNIX_SOCKET_PREFIX) {
+            return Ok(SocketAddress::Unix(PathBuf::from(path)));
+        }
+
+        if connect_string.starts_with('[') && connect_string.contains('%') {
+            return parse_scoped_ipv6(connect_string).map(|addr| SocketAddress::Tcp(vec![addr]));
+        }
+
+        let addrs: Vec<SocketAddr> = try!(connect_string.to_socket_addrs()).collect();
+        if addrs.is_empty() {
+            return Err(invalid_address(connect_string));
+        }
+        Ok(SocketAddress::Tcp(addrs))
+    }
+}
+
+/// Time limits for connecting to the agent and for waiting on its data
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    /// Limit for establishing a TCP connection, per resolved address
+    pub connect: Option<Duration>,
+    /// Limit for a whole request/response exchange with the agent
+    pub call: Option<Duration>,
+    /// Limit for the agent to go silent while a read or write is pending
+    pub idle: Option<Duration>,
+}
+
+impl Timeouts {
+    /// No time limits, all operations block until they complete
+    pub fn new() -> Timeouts {
+        Timeouts::default()
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Timeouts {
+        self.connect = Some(timeout);
+        self
+    }
+
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Timeouts {
+        self.call = Some(timeout);
+        self
+    }
+
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Timeouts {
+        self.idle = Some(timeout);
+        self
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// Stream socket connected to the agent
+///
+/// Reads and writes fail with `ErrorKind::TimedOut` once the idle timeout or the deadline
+/// set with `set_deadline` expires.
+pub struct SocketTransport {
+    stream: Stream,
+    idle_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl SocketTransport {
+    pub fn connect(connect_string: &str) -> io::Result<SocketTransport> {
+        SocketTransport::connect_with_timeouts(connect_string, &Timeouts::new())
+    }
+
+    pub fn connect_with_timeouts(connect_string: &str, timeouts: &Timeouts) -> io::Result<SocketTransport> {
+        let address = try!(SocketAddress::parse(connect_string));
+        SocketTransport::connect_address(&address, timeouts)
+    }
+
+    pub fn connect_address(address: &SocketAddress, timeouts: &Timeouts) -> io::Result<SocketTransport> {
+        let stream = match *address {
+            SocketAddress::Tcp(ref addrs) => {
+                let stream = try!(connect_tcp(addrs, timeouts.connect));
+                try!(stream.set_nodelay(true));
+                Stream::Tcp(stream)
+            }
+            #[cfg(unix)]
+            SocketAddress::Unix(ref path) => Stream::Unix(try!(UnixStream::connect(path))),
+            #[cfg(not(unix))]
+            SocketAddress::Unix(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "unix sockets are not supported on this platform"))
+            }
+        };
+
+        Ok(SocketTransport {
+            stream: stream,
+            idle_timeout: timeouts.idle,
+            deadline: None,
+        })
+    }
+
+    /// Bounds all following reads and writes by `deadline`, `None` lifts the bound
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Applies the shorter of idle timeout and remaining time to the deadline to the socket
+    fn arm_timeout(&mut self) -> io::Result<()> {
+        let mut timeout = self.idle_timeout;
+        if let Some(deadline) = self.deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "deadline expired"));
+            }
+            let remaining = deadline - now;
+            timeout = Some(timeout.map_or(remaining, |idle| cmp::min(idle, remaining)));
+        }
+
+        match self.stream {
+            Stream::Tcp(ref stream) => {
+                try!(stream.set_read_timeout(timeout));
+                stream.set_write_timeout(timeout)
+            }
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => {
+                try!(stream.set_read_timeout(timeout));
+                stream.set_write_timeout(timeout)
+            }
+        }
+    }
+}
+
+impl Read for SocketTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        try!(self.arm_timeout());
+        let result = match self.stream {
+            Stream::Tcp(ref mut stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.read(buf),
+        };
+        result.map_err(timed_out)
+    }
+}
+
+impl Write for SocketTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        try!(self.arm_timeout());
+        let result = match self.stream {
+            Stream::Tcp(ref mut stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.write(buf),
+        };
+        result.map_err(timed_out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stream {
+            Stream::Tcp(ref mut stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+
+fn connect_tcp(addrs: &[SocketAddr], timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect(addrs),
+    };
+
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")))
+}
+
+/// Sockets report an expired timeout as `WouldBlock` on unix
+fn timed_out(err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, err),
+        _ => err,
+    }
+}
+
+fn invalid_address(connect_string: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput,
+                   format!("invalid address {}", connect_string))
+}
+
+fn parse_scoped_ipv6(connect_string: &str) -> io::Result<SocketAddr> {
+    let end = match connect_string.find("]:") {
+        Some(end) => end,
+        None => return Err(invalid_address(connect_string)),
+    };
+    let host = &connect_string[1..end];
+    let port = &connect_string[end + 2..];
+
+    let zone_start = host.find('%').unwrap_or(host.len());
+    let ip = try!(Ipv6Addr::from_str(&host[..zone_start]).map_err(|_| invalid_address(connect_string)));
+    let port = try!(u16::from_str(port).map_err(|_| invalid_address(connect_string)));
+    let scope_id = try!(parse_zone(&host[zone_start + 1..]));
+
+    Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)))
+}
+
+fn parse_zone(zone: &str) -> io::Result<u32> {
+    if let Ok(scope_id) = u32::from_str(zone) {
+        return Ok(scope_id);
+    }
+    interface_index(zone)
+}
+
+#[cfg(unix)]
+fn interface_index(name: &str) -> io::Result<u32> {
+    use std::ffi::CString;
+    use libc;
+
+    let c_name = try!(CString::new(name).map_err(|_| invalid_address(name)));
+    let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("unknown interface {}", name)));
+    }
+    Ok(index)
+}
+
+#[cfg(not(unix))]
+fn interface_index(name: &str) -> io::Result<u32> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput,
+                       format!("zone {} has to be given as numeric scope id", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use std::net::{SocketAddr, SocketAddrV6, TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{timed_out, SocketAddress, Timeouts};
+    use api::FbossClient;
+    use Error;
+
+    fn tcp(address: &str) -> SocketAddress {
+        SocketAddress::Tcp(vec![SocketAddr::from_str(address).unwrap()])
+    }
+
+    fn scoped(address: &str, port: u16, scope_id: u32) -> SocketAddress {
+        let ip = FromStr::from_str(address).unwrap();
+        SocketAddress::Tcp(vec![SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id))])
+    }
+
+    #[test]
+    fn parses_addresses() {
+        let cases = vec![("127.0.0.1:5909", tcp("127.0.0.1:5909")),
+                         ("[::1]:5909", tcp("[::1]:5909")),
+                         ("[fe80::1%3]:5909", scoped("fe80::1", 5909, 3)),
+                         ("unix:/var/run/fboss.sock", SocketAddress::Unix(PathBuf::from("/var/run/fboss.sock"))),
+                         ("unix:relative.sock", SocketAddress::Unix(PathBuf::from("relative.sock")))];
+        for (connect_string, expected) in cases {
+            assert_eq!(SocketAddress::parse(connect_string).unwrap(), expected, "{}", connect_string);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_zone_given_as_interface_name() {
+        use super::interface_index;
+
+        assert_eq!(SocketAddress::parse("[fe80::1%lo]:5909").unwrap(),
+                   scoped("fe80::1", 5909, interface_index("lo").unwrap()));
+
+        match interface_index("eth0") {
+            Ok(index) => {
+                assert_eq!(SocketAddress::parse("[fe80::1%eth0]:5909").unwrap(),
+                           scoped("fe80::1", 5909, index))
+            }
+            Err(_) => assert!(SocketAddress::parse("[fe80::1%eth0]:5909").is_err()),
+        }
+
+        let err = SocketAddress::parse("[fe80::1%nosuchif0]:5909").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn rejects_bad_addresses() {
+        let cases = ["127.0.0.1",
+                     "[::1]",
+                     "[fe80::1%3]",
+                     "[fe80::1%3]:",
+                     "[fe80::1%3]5909",
+                     "[fe80::1%3]:65536",
+                     "[fe80::1%3]:port",
+                     "[fe80::zz%3]:5909",
+                     "[10.0.0.1%3]:5909",
+                     "[fe80::1%lo\0]:5909",
+                     "127.0.0.1:port",
+                     ""];
+        for connect_string in cases.iter() {
+            match SocketAddress::parse(connect_string) {
+                Err(ref err) if err.kind() != io::ErrorKind::NotFound => {}
+                other => panic!("{:?}: unexpected result {:?}", connect_string, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_would_block_as_timed_out() {
+        let err = timed_out(io::Error::new(io::ErrorKind::WouldBlock, "read timed out"));
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "read timed out");
+
+        for kind in &[io::ErrorKind::TimedOut, io::ErrorKind::ConnectionReset, io::ErrorKind::UnexpectedEof] {
+            assert_eq!(timed_out(io::Error::new(*kind, "failed")).kind(), *kind);
+        }
+    }
+
+    /// Agent accepting connections and writing `reply` to each, one byte per `interval`,
+    /// without ever reading the request
+    fn slow_agent(reply: Vec<u8>, interval: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let reply = reply.clone();
+                thread::spawn(move || {
+                    for byte in &reply {
+                        thread::sleep(interval);
+                        if stream.write_all(&[*byte]).is_err() {
+                            return;
+                        }
+                    }
+                    // drain the request and hold the connection open until the client closes it
+                    let mut buf = [0u8; 64];
+                    while stream.read(&mut buf).map(|read| read > 0).unwrap_or(false) {}
+                });
+            }
+        });
+        address
+    }
+
+    fn assert_times_out(timeouts: &Timeouts, address: &str, min: Duration, max: Duration) {
+        let mut client = FbossClient::connect_with_timeouts(address, timeouts).unwrap();
+        let start = Instant::now();
+        match client.get_switch_run_state() {
+            Err(Error::Timeout) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= min && elapsed < max, "timed out after {:?}", elapsed);
+        assert!(client.is_broken());
+    }
+
+    #[test]
+    fn idle_timeout_expires_on_silent_agent() {
+        let address = slow_agent(vec![], Duration::from_millis(0));
+        let timeouts = Timeouts::new().with_idle_timeout(Duration::from_millis(100));
+        assert_times_out(&timeouts, &address, Duration::from_millis(100), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn call_timeout_bounds_trickling_reply() {
+        // a reply header arriving byte by byte keeps resetting the idle timeout
+        let mut reply = vec![0x80, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x11];
+        reply.extend_from_slice(b"getSwitchRunState");
+        reply.extend_from_slice(&[0x00, 0x00, 0x00, 0x01]);
+        let address = slow_agent(reply, Duration::from_millis(50));
+        let timeouts = Timeouts::new()
+            .with_idle_timeout(Duration::from_millis(200))
+            .with_call_timeout(Duration::from_millis(300));
+        assert_times_out(&timeouts, &address, Duration::from_millis(300), Duration::from_millis(1200));
+
+        // the call timeout alone bounds a call to a silent agent
+        let timeouts = Timeouts::new().with_call_timeout(Duration::from_millis(100));
+        assert_times_out(&timeouts, &slow_agent(vec![], Duration::from_millis(0)),
+                         Duration::from_millis(100), Duration::from_secs(2));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn connect_timeout_expires_on_full_backlog() {
+        use std::os::unix::io::AsRawFd;
+        use libc;
+
+        // with no room in the accept queue the kernel drops further SYNs
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 0) }, 0);
+        let address = listener.local_addr().unwrap().to_string();
+        let timeouts = Timeouts::new().with_connect_timeout(Duration::from_millis(100));
+
+        let mut connected = vec![];
+        for _ in 0..8 {
+            let start = Instant::now();
+            match FbossClient::connect_with_timeouts(&address, &timeouts) {
+                Ok(client) => connected.push(client),
+                Err(Error::Timeout) => {
+                    assert!(start.elapsed() >= Duration::from_millis(100));
+                    return;
+                }
+                Err(err) => panic!("unexpected error {:?}", err),
+            }
+        }
+        panic!("{} connections were accepted", connected.len());
+    }
+
+    #[test]
+    fn reconnect_after_timeout_uses_new_connection() {
+        let (sender, receiver) = mpsc::channel();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                // keep each connection open, answering nothing
+                sender.send(stream.unwrap()).unwrap();
+            }
+        });
+
+        let timeouts = Timeouts::new().with_idle_timeout(Duration::from_millis(50));
+        let mut client = FbossClient::connect_with_timeouts(&address, &timeouts).unwrap();
+        for _ in 0..2 {
+            match client.get_boot_type() {
+                Err(Error::Timeout) => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        // a timeout is not retried, the broken connection is replaced before the second call
+        assert!(receiver.recv_timeout(Duration::from_secs(1)).is_ok());
+        assert!(receiver.recv_timeout(Duration::from_secs(1)).is_ok());
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,492 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements. See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership. The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License. You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied. See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use openssl::error::ErrorStack;
+use openssl::ssl::{self, HandshakeError, SslConnector, SslFiletype, SslMethod, SslStream, SslVerifyMode};
+use openssl::x509::X509;
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+
+/// Settings for the TLS session with the agent
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    ca_file: Option<PathBuf>,
+    certificate_chain_file: Option<PathBuf>,
+    private_key_file: Option<PathBuf>,
+    server_name: Option<String>,
+    verify_peer: bool,
+}
+
+impl TlsConfig {
+    /// Verifies the agent against the system's trusted CAs, no client certificate
+    pub fn new() -> TlsConfig {
+        TlsConfig {
+            ca_file: None,
+            certificate_chain_file: None,
+            private_key_file: None,
+            server_name: None,
+            verify_peer: true,
+        }
+    }
+
+    /// Verifies the agent against the CA certificates in the given PEM bundle
+    /// instead of the system's trusted CAs
+    pub fn with_ca_file<P: AsRef<Path>>(mut self, ca_file: P) -> TlsConfig {
+        self.ca_file = Some(ca_file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Presents the PEM certificate chain and private key to the agent for mutual TLS
+    pub fn with_client_certificate<C, K>(mut self, certificate_chain_file: C, private_key_file: K) -> TlsConfig
+        where C: AsRef<Path>,
+              K: AsRef<Path>
+    {
+        self.certificate_chain_file = Some(certificate_chain_file.as_ref().to_path_buf());
+        self.private_key_file = Some(private_key_file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Name sent as SNI and checked against the agent's certificate,
+    /// defaults to the host of the connect string
+    pub fn with_server_name(mut self, server_name: &str) -> TlsConfig {
+        self.server_name = Some(server_name.to_string());
+        self
+    }
+
+    /// Disables certificate and host name verification, only meant for lab setups
+    pub fn with_verify_peer(mut self, verify_peer: bool) -> TlsConfig {
+        self.verify_peer = verify_peer;
+        self
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_ref().map(|name| &name[..])
+    }
+
+    fn connector(&self) -> io::Result<SslConnector> {
+        let mut builder = try!(SslConnector::builder(SslMethod::tls()).map_err(ssl_error));
+
+        if let Some(ref ca_file) = self.ca_file {
+            // replaces the store the builder filled with the system's trusted CAs
+            builder.set_cert_store(try!(ca_store(ca_file)));
+        }
+        if let Some(ref certificate_chain_file) = self.certificate_chain_file {
+            try!(builder.set_certificate_chain_file(certificate_chain_file).map_err(ssl_error));
+        }
+        if let Some(ref private_key_file) = self.private_key_file {
+            try!(builder.set_private_key_file(private_key_file, SslFiletype::PEM).map_err(ssl_error));
+            try!(builder.check_private_key().map_err(ssl_error));
+        }
+        if !self.verify_peer {
+            builder.set_verify(SslVerifyMode::NONE);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> TlsConfig {
+        TlsConfig::new()
+    }
+}
+
+/// TLS session on top of a connected stream, e.g. a `SocketTransport`
+pub struct TlsTransport<S> {
+    stream: SslStream<S>,
+}
+
+impl<S: Read + Write> TlsTransport<S> {
+    /// Runs the TLS handshake over `stream`, `server_name` is used for SNI and host name
+    /// verification unless the config overrides it
+    pub fn connect(config: &TlsConfig, server_name: &str, stream: S) -> io::Result<TlsTransport<S>> {
+        let connector = try!(config.connector());
+        let mut ssl_config = try!(connector.configure().map_err(ssl_error));
+        if !config.verify_peer {
+            ssl_config.set_verify_hostname(false);
+        }
+
+        let server_name = config.server_name().unwrap_or(server_name);
+        match ssl_config.connect(server_name, stream) {
+            Ok(stream) => Ok(TlsTransport { stream: stream }),
+            Err(HandshakeError::SetupFailure(err)) => Err(ssl_error(err)),
+            Err(HandshakeError::Failure(mid_handshake)) => Err(handshake_error(mid_handshake.into_error())),
+            // a blocking stream only stops a handshake halfway when its read or write timed out
+            Err(HandshakeError::WouldBlock(_)) => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        self.stream.get_mut()
+    }
+}
+
+impl<S: Read + Write> Read for TlsTransport<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl<S: Read + Write> Write for TlsTransport<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Host part of a `host:port` or `[ipv6%zone]:port` connect string
+pub fn server_name(connect_string: &str) -> Option<&str> {
+    if connect_string.starts_with('[') {
+        return connect_string.find(']').map(|end| {
+            let host = &connect_string[1..end];
+            host.split('%').next().unwrap_or(host)
+        });
+    }
+
+    match connect_string.rfind(':') {
+        Some(end) if end > 0 => Some(&connect_string[..end]),
+        _ => None,
+    }
+}
+
+
+/// Store holding just the certificates of a PEM bundle
+fn ca_store(ca_file: &Path) -> io::Result<X509Store> {
+    let mut pem = vec![];
+    try!(File::open(ca_file).and_then(|mut file| file.read_to_end(&mut pem)));
+    let certificates = try!(X509::stack_from_pem(&pem).map_err(ssl_error));
+    if certificates.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("no certificate in {}", ca_file.display())));
+    }
+
+    let mut store = try!(X509StoreBuilder::new().map_err(ssl_error));
+    for certificate in certificates {
+        try!(store.add_cert(certificate).map_err(ssl_error));
+    }
+    Ok(store.build())
+}
+
+fn ssl_error(err: ErrorStack) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Keeps the kind of a failed read or write of the stream, so a timeout stays a timeout
+fn handshake_error(err: ssl::Error) -> io::Error {
+    match err.into_io_error() {
+        Ok(err) => {
+            match err.kind() {
+                io::ErrorKind::WouldBlock => io::Error::new(io::ErrorKind::TimedOut, err),
+                _ => err,
+            }
+        }
+        Err(err) => io::Error::other(format!("TLS handshake failed: {}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::ssl::{NameType, SslAcceptor, SslAcceptorBuilder, SslMethod, SslVerifyMode};
+    use openssl::x509::{X509, X509NameBuilder};
+    use openssl::x509::store::X509StoreBuilder;
+    use openssl::x509::extension::SubjectAlternativeName;
+
+    use super::{TlsConfig, TlsTransport};
+    use api::FbossClient;
+    use transport::socket_transport::Timeouts;
+    use Error;
+
+    /// Self-signed certificate for `localhost`, written to a PEM file to be used as CA
+    fn self_signed(name: &str) -> (X509, PKey<Private>, PathBuf) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", "localhost").unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(&subject).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        let san = SubjectAlternativeName::new().dns("localhost").build(&builder.x509v3_context(None, None)).unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let certificate = builder.build();
+
+        let path = env::temp_dir().join(format!("torc-tls-{}-{}.pem", name, ::std::process::id()));
+        File::create(&path).unwrap().write_all(&certificate.to_pem().unwrap()).unwrap();
+        (certificate, key, path)
+    }
+
+    fn acceptor(certificate: &X509, key: &PKey<Private>) -> SslAcceptorBuilder {
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(certificate).unwrap();
+        acceptor.set_private_key(key).unwrap();
+        acceptor
+    }
+
+    /// Agent answering one TLS connection by echoing the first four bytes
+    fn echo_agent(acceptor: SslAcceptor) -> (u16, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let agent = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream) {
+                let mut buf = [0u8; 4];
+                if stream.read_exact(&mut buf).is_ok() {
+                    stream.write_all(&buf).unwrap();
+                }
+            }
+        });
+        (port, agent)
+    }
+
+    fn ping(transport: &mut TlsTransport<TcpStream>) -> io::Result<()> {
+        try!(transport.write_all(b"ping"));
+        let mut buf = [0u8; 4];
+        try!(transport.read_exact(&mut buf));
+        assert_eq!(&buf, b"ping");
+        Ok(())
+    }
+
+    fn remove(paths: &[&PathBuf]) {
+        for path in paths {
+            let _ = ::std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn connects_to_agent_with_trusted_certificate() {
+        let (certificate, key, ca_file) = self_signed("trusted");
+        let (port, agent) = echo_agent(acceptor(&certificate, &key).build());
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new().with_ca_file(&ca_file);
+        let mut transport = TlsTransport::connect(&config, "localhost", stream).unwrap();
+        ping(&mut transport).unwrap();
+
+        agent.join().unwrap();
+        remove(&[&ca_file]);
+    }
+
+    #[test]
+    fn rejects_untrusted_certificate() {
+        let (certificate, key, ca_file) = self_signed("untrusted");
+        let (port, agent) = echo_agent(acceptor(&certificate, &key).build());
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let err = TlsTransport::connect(&TlsConfig::new(), "localhost", stream).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(err.to_string().starts_with("TLS handshake failed"), "{}", err);
+
+        agent.join().unwrap();
+        remove(&[&ca_file]);
+    }
+
+    #[test]
+    fn ca_file_replaces_system_cas() {
+        let (system_certificate, system_key, system_ca_file) = self_signed("system");
+        let (_, _, ca_file) = self_signed("bundle");
+        // OpenSSL reads the system's trusted CAs from this file, no other test trusts the system CAs
+        env::set_var("SSL_CERT_FILE", &system_ca_file);
+
+        let (port, agent) = echo_agent(acceptor(&system_certificate, &system_key).build());
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut transport = TlsTransport::connect(&TlsConfig::new(), "localhost", stream).unwrap();
+        ping(&mut transport).unwrap();
+        agent.join().unwrap();
+
+        let (port, agent) = echo_agent(acceptor(&system_certificate, &system_key).build());
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new().with_ca_file(&ca_file);
+        let err = TlsTransport::connect(&config, "localhost", stream).err().unwrap();
+        assert!(err.to_string().starts_with("TLS handshake failed"), "{}", err);
+        agent.join().unwrap();
+
+        env::remove_var("SSL_CERT_FILE");
+        remove(&[&system_ca_file, &ca_file]);
+    }
+
+    #[test]
+    fn rejects_ca_file_without_certificates() {
+        let path = env::temp_dir().join(format!("torc-tls-empty-{}.pem", ::std::process::id()));
+        File::create(&path).unwrap().write_all(b"no certificate").unwrap();
+
+        let stream = TcpStream::connect(("127.0.0.1", silent_agent().0)).unwrap();
+        let err = TlsTransport::connect(&TlsConfig::new().with_ca_file(&path), "localhost", stream).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let stream = TcpStream::connect(("127.0.0.1", silent_agent().0)).unwrap();
+        let config = TlsConfig::new().with_ca_file(path.with_extension("missing"));
+        let err = TlsTransport::connect(&config, "localhost", stream).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        remove(&[&path]);
+    }
+
+    #[test]
+    fn presents_client_certificate() {
+        let (certificate, key, ca_file) = self_signed("mtls-agent");
+        let (client_certificate, client_key, client_certificate_file) = self_signed("mtls-client");
+        let client_key_file = client_certificate_file.with_extension("key");
+        File::create(&client_key_file).unwrap().write_all(&client_key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+        let mutual_acceptor = || {
+            let mut acceptor = acceptor(&certificate, &key);
+            let mut store = X509StoreBuilder::new().unwrap();
+            store.add_cert(client_certificate.clone()).unwrap();
+            acceptor.set_verify_cert_store(store.build()).unwrap();
+            acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+            acceptor.build()
+        };
+
+        let (port, agent) = echo_agent(mutual_acceptor());
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new()
+            .with_ca_file(&ca_file)
+            .with_client_certificate(&client_certificate_file, &client_key_file);
+        let mut transport = TlsTransport::connect(&config, "localhost", stream).unwrap();
+        ping(&mut transport).unwrap();
+        agent.join().unwrap();
+
+        // with TLS 1.3 the client only learns of the rejection when it reads
+        let (port, agent) = echo_agent(mutual_acceptor());
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new().with_ca_file(&ca_file);
+        let result = TlsTransport::connect(&config, "localhost", stream).and_then(|mut transport| ping(&mut transport));
+        assert!(result.is_err());
+        agent.join().unwrap();
+
+        // a key not matching the certificate is refused before the handshake
+        let other_key_file = ca_file.with_extension("key");
+        File::create(&other_key_file).unwrap().write_all(&key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let stream = TcpStream::connect(("127.0.0.1", silent_agent().0)).unwrap();
+        let config = TlsConfig::new().with_client_certificate(&client_certificate_file, &other_key_file);
+        assert!(TlsTransport::connect(&config, "localhost", stream).is_err());
+
+        remove(&[&ca_file, &client_certificate_file, &client_key_file, &other_key_file]);
+    }
+
+    /// Agent reporting the SNI name of the connection it accepts
+    fn sni_agent(certificate: &X509, key: &PKey<Private>) -> (u16, mpsc::Receiver<Option<String>>) {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let mut acceptor = acceptor(certificate, key);
+        acceptor.set_servername_callback(move |ssl, _| {
+            let server_name = ssl.servername(NameType::HOST_NAME).map(|name| name.to_string());
+            sender.lock().unwrap().send(server_name).unwrap();
+            Ok(())
+        });
+        (echo_agent(acceptor.build()).0, receiver)
+    }
+
+    #[test]
+    fn sends_server_name() {
+        let (certificate, key, ca_file) = self_signed("sni");
+
+        // the host of the connect string by default
+        let (port, server_names) = sni_agent(&certificate, &key);
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new().with_ca_file(&ca_file);
+        let mut transport = TlsTransport::connect(&config, "localhost", stream).unwrap();
+        ping(&mut transport).unwrap();
+        assert_eq!(server_names.recv().unwrap(), Some("localhost".to_string()));
+
+        // the configured name overrides it
+        let (port, server_names) = sni_agent(&certificate, &key);
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new().with_ca_file(&ca_file).with_server_name("localhost");
+        let mut transport = TlsTransport::connect(&config, "switch1.example.net", stream).unwrap();
+        ping(&mut transport).unwrap();
+        assert_eq!(server_names.recv().unwrap(), Some("localhost".to_string()));
+
+        // and is checked against the agent's certificate
+        let (port, server_names) = sni_agent(&certificate, &key);
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let config = TlsConfig::new().with_ca_file(&ca_file).with_server_name("switch1.example.net");
+        let err = TlsTransport::connect(&config, "localhost", stream).err().unwrap();
+        assert!(err.to_string().starts_with("TLS handshake failed"), "{}", err);
+        assert_eq!(server_names.recv().unwrap(), Some("switch1.example.net".to_string()));
+
+        remove(&[&ca_file]);
+    }
+
+    /// Agent accepting TCP connections without ever answering the handshake
+    fn silent_agent() -> (u16, mpsc::Receiver<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if sender.send(stream.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        (port, receiver)
+    }
+
+    #[test]
+    fn handshake_times_out() {
+        let (port, _connections) = silent_agent();
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        let config = TlsConfig::new().with_verify_peer(false);
+        let err = TlsTransport::connect(&config, "localhost", stream).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let timeouts = Timeouts::new().with_connect_timeout(Duration::from_millis(100));
+        match FbossClient::connect_tls_with_timeouts(&format!("127.0.0.1:{}", port), &config, &timeouts) {
+            Err(Error::Timeout) => {}
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("handshake with a silent agent succeeded"),
+        }
+    }
+}